@@ -0,0 +1,58 @@
+// Lexes generated sources of growing size and prints the time per byte,
+// which stays flat when lexing is linear: `cargo bench --bench lexer`
+use morph::Lexer;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const PROGRAM: &str = r#"
+add | Int, Int -> Int;
+add = a, b -> a + b;
+
+greet = (name, greeting mut) -> {
+    greeting += ", ";
+    println { greeting, name, "! ünïcödé ✓" };
+    return 'λ';
+};
+
+total = 0..=100 : filter { n -> n % 2 == 0 } : map { n -> n ** 2 } : sum;
+ratio = total / 3.14;
+"#;
+
+const RUNS: usize = 5;
+
+fn source(bytes: usize) -> String {
+    PROGRAM.repeat(bytes / PROGRAM.len() + 1)
+}
+
+// the fastest of a few runs, which is the least disturbed by noise
+fn time(source: &str) -> (usize, Duration) {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let tokens = Lexer::new(black_box(source)).count();
+            (tokens, start.elapsed())
+        })
+        .min_by_key(|(_, elapsed)| *elapsed)
+        .unwrap()
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>10} {:>10} {:>8}",
+        "size", "tokens", "time", "ns/byte"
+    );
+
+    for megabytes in [1, 2, 4, 8, 16] {
+        let source = source(megabytes << 20);
+        let (tokens, elapsed) = time(&source);
+        let per_byte = elapsed.as_nanos() as f64 / source.len() as f64;
+
+        println!(
+            "{:>6}MB {:>10} {:>8.1}ms {:>8.2}",
+            megabytes,
+            tokens,
+            elapsed.as_secs_f64() * 1000.0,
+            per_byte
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::eval::{call, eval_binary, ordering, Env, Value};
+use crate::parser::Operator;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// the declarations every program starts with
+pub const PRELUDE: &str = include_str!("prelude.mo");
+
+// the traits that can be derived, with their only method
+pub const DERIVABLE: [(&str, &str, fn(Vec<Value>) -> Value); 5] = [
+    ("Format", "format", format),
+    ("Eq", "eq", eq),
+    ("Ord", "cmp", cmp),
+    ("Hash", "hash", hash),
+    ("Clone", "clone", clone),
+];
+
+// whether the builtin method called `name` only takes the receiver
+pub fn takes_receiver(name: &str) -> bool {
+    matches!(name, "format" | "hash" | "clone" | "len" | "sum")
+}
+
+// the derivable traits that the builtin types implement
+pub const IMPLEMENTED: [(&str, &[&str]); 8] = [
+    ("Unit", &["Format", "Eq", "Ord", "Hash", "Clone"]),
+    ("Int", &["Format", "Eq", "Ord", "Hash", "Clone"]),
+    ("Float", &["Format", "Eq", "Ord", "Clone"]),
+    ("Bool", &["Format", "Eq", "Ord", "Hash", "Clone"]),
+    ("Char", &["Format", "Eq", "Ord", "Hash", "Clone"]),
+    ("String", &["Format", "Eq", "Ord", "Hash", "Clone"]),
+    ("Tuple", &["Format", "Eq", "Ord", "Hash", "Clone"]),
+    ("Array", &["Format", "Eq", "Ord", "Hash", "Clone"]),
+];
+
+pub fn define(env: &mut Env) {
+    let builtins: [(&'static str, fn(Vec<Value>) -> Value); 3] =
+        [("print", print), ("println", println), ("format", format)];
+
+    for (name, function) in builtins {
+        env.define(name.to_owned(), Value::Builtin { name, function });
+    }
+
+    // methods, since each collection has its own element type, and `Option`
+    // and `Result` have their own `map`
+    let methods: [(&'static str, fn(Vec<Value>) -> Value, &[&str]); 4] = [
+        ("map", map, &["Array", "Range", "String"]),
+        ("filter", filter, &["Array", "Range", "String"]),
+        ("len", len, &["Array", "Range", "String"]),
+        ("sum", sum, &["Array", "Range"]),
+    ];
+
+    for (name, function, types) in methods {
+        for ty in types {
+            env.define_method(ty, name.to_owned(), Value::Builtin { name, function });
+        }
+    }
+
+    for (trait_, method, _) in DERIVABLE {
+        env.define_trait(trait_.to_owned(), vec![(method.to_owned(), None)]);
+    }
+
+    for (ty, traits) in IMPLEMENTED {
+        for trait_ in traits {
+            derive(env, trait_, ty);
+        }
+    }
+}
+
+// implement a derivable trait for the type called `ty`, or return false if
+// the trait can't be derived
+pub fn derive(env: &mut Env, trait_: &str, ty: &str) -> bool {
+    let Some(&(_, name, function)) = DERIVABLE.iter().find(|(ident, ..)| *ident == trait_) else {
+        return false;
+    };
+
+    env.define_method(ty, name.to_owned(), Value::Builtin { name, function });
+    env.implement(trait_, ty);
+    true
+}
+
+fn expect_args(name: &str, args: &[Value], count: usize) -> Result<(), Value> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        Err(Value::Error(format!(
+            "{} expected {} arguments, found {}",
+            name,
+            count,
+            args.len()
+        )))
+    }
+}
+
+// the elements of a value that can be iterated over
+pub(crate) fn elements(value: Value) -> Result<Vec<Value>, Value> {
+    match value {
+        Value::Array(values) => Ok(values),
+        Value::Range(from, to) => Ok((from..to).map(Value::Int).collect()),
+        Value::Str(str) => Ok(str.chars().map(Value::Char).collect()),
+        value => Err(Value::Error(format!(
+            "{} is not iterable",
+            value.type_name()
+        ))),
+    }
+}
+
+// a leading string is a template whose `{}` are filled by the arguments after it,
+// and whatever is left over is appended separated by spaces
+fn format_args(args: &[Value]) -> String {
+    let mut args = args.iter();
+    let mut out = String::new();
+
+    if let Some(Value::Str(template)) = args.as_slice().first() {
+        args.next();
+        let mut pieces = template.split("{}");
+        out += pieces.next().unwrap_or_default();
+
+        for piece in pieces {
+            match args.next() {
+                Some(arg) => out += &arg.to_string(),
+                None => out += "{}",
+            }
+            out += piece;
+        }
+    } else if let Some(arg) = args.next() {
+        out += &arg.to_string();
+    }
+
+    for arg in args {
+        out += " ";
+        out += &arg.to_string();
+    }
+
+    out
+}
+
+fn format(args: Vec<Value>) -> Value {
+    Value::Str(format_args(&args))
+}
+
+fn print(args: Vec<Value>) -> Value {
+    print!("{}", format_args(&args));
+    Value::Unit
+}
+
+fn println(args: Vec<Value>) -> Value {
+    println!("{}", format_args(&args));
+    Value::Unit
+}
+
+fn len(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("len", &args, 1) {
+        return err;
+    }
+
+    let len = match &args[0] {
+        Value::Str(str) => str.chars().count(),
+        Value::Array(values) => values.len(),
+        Value::Range(from, to) => (*to).saturating_sub(*from).max(0) as usize,
+        value => return Value::Error(format!("{} has no length", value.type_name())),
+    };
+
+    Value::Int(len as i64)
+}
+
+fn filter(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("filter", &args, 2) {
+        return err;
+    }
+
+    let mut args = args.into_iter();
+    let (values, predicate) = (args.next().unwrap(), args.next().unwrap());
+
+    let values = match elements(values) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let mut filtered = Vec::new();
+
+    for value in values {
+        match call(&predicate, vec![value.clone()]) {
+            Value::Bool(true) => filtered.push(value),
+            Value::Bool(false) => {}
+            err @ Value::Error(_) => return err,
+            result => {
+                return Value::Error(format!(
+                    "filter predicate must return a Bool, found {}",
+                    result.type_name()
+                ))
+            }
+        }
+    }
+
+    Value::Array(filtered)
+}
+
+fn map(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("map", &args, 2) {
+        return err;
+    }
+
+    let mut args = args.into_iter();
+    let (values, function) = (args.next().unwrap(), args.next().unwrap());
+
+    let values = match elements(values) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let mut mapped = Vec::with_capacity(values.len());
+
+    for value in values {
+        match call(&function, vec![value]) {
+            err @ Value::Error(_) => return err,
+            value => mapped.push(value),
+        }
+    }
+
+    Value::Array(mapped)
+}
+
+fn sum(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("sum", &args, 1) {
+        return err;
+    }
+
+    let values = match elements(args.into_iter().next().unwrap()) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let mut values = values.into_iter();
+
+    let mut total = match values.next() {
+        Some(value @ (Value::Int(_) | Value::Float(_))) => value,
+        Some(value) => return Value::Error(format!("cannot sum {}", value.type_name())),
+        None => return Value::Int(0),
+    };
+
+    for value in values {
+        total = match (total, value) {
+            (Value::Int(l), Value::Int(r)) => match l.checked_add(r) {
+                Some(int) => Value::Int(int),
+                None => return Value::Error("integer overflow".to_owned()),
+            },
+            (Value::Float(l), Value::Float(r)) => Value::Float(l + r),
+            (l, r) => {
+                return Value::Error(format!(
+                    "cannot sum {} and {}",
+                    l.type_name(),
+                    r.type_name()
+                ))
+            }
+        };
+    }
+
+    total
+}
+
+fn eq(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("eq", &args, 2) {
+        return err;
+    }
+
+    let mut args = args.into_iter();
+    eval_binary(&Operator::Equal, args.next().unwrap(), args.next().unwrap())
+}
+
+// -1, 0 or 1 as the first argument is less than, equal to or greater than the
+// second
+fn cmp(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("cmp", &args, 2) {
+        return err;
+    }
+
+    match ordering(&args[0], &args[1]) {
+        Some(Some(ordering)) => Value::Int(ordering as i64),
+        Some(None) => Value::Error("cannot order NaN".to_owned()),
+        None => Value::Error(format!(
+            "cannot compare {} with {}",
+            args[0].type_name(),
+            args[1].type_name()
+        )),
+    }
+}
+
+fn hash(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("hash", &args, 1) {
+        return err;
+    }
+
+    let mut hasher = DefaultHasher::new();
+
+    match hash_value(&args[0], &mut hasher) {
+        Ok(()) => Value::Int(hasher.finish() as i64),
+        Err(err) => err,
+    }
+}
+
+// feed the structure of a value into `hasher`, so that equal values hash alike
+fn hash_value(value: &Value, hasher: &mut DefaultHasher) -> Result<(), Value> {
+    std::mem::discriminant(value).hash(hasher);
+
+    match value {
+        Value::Unit => {}
+        Value::Int(int) => int.hash(hasher),
+        Value::Bool(bool) => bool.hash(hasher),
+        Value::Char(char) => char.hash(hasher),
+        Value::Str(str) => str.hash(hasher),
+        Value::Array(values) | Value::Tuple(values) => {
+            values.len().hash(hasher);
+            for value in values {
+                hash_value(value, hasher)?;
+            }
+        }
+        Value::Struct(constructor, values) | Value::Variant(constructor, values) => {
+            constructor.ty.hash(hasher);
+            constructor.index.hash(hasher);
+            for value in values {
+                hash_value(value, hasher)?;
+            }
+        }
+        value => return Err(Value::Error(format!("cannot hash {}", value.type_name()))),
+    }
+
+    Ok(())
+}
+
+fn clone(args: Vec<Value>) -> Value {
+    if let Err(err) = expect_args("clone", &args, 1) {
+        return err;
+    }
+
+    args.into_iter().next().unwrap()
+}
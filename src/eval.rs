@@ -1,14 +1,28 @@
-use crate::parser::{Expr, Stmt};
+use crate::builtins;
+use crate::parser::{Expr, Operator, Parser, Type};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::rc::Rc;
 
 pub trait Eval {
-    fn eval(&self, env: &Env) -> Value;
+    fn eval(&self, env: &Rc<RefCell<Env>>) -> Value;
 }
 
 pub struct Env {
-    symbol_table: HashMap<String, Box<Expr>>,
+    symbol_table: HashMap<String, Value>,
+    // the methods of each type, from its `impl` blocks
+    methods: HashMap<String, HashMap<String, Value>>,
+    // the methods of each trait, with their default if they have one
+    traits: HashMap<String, Vec<(String, Option<Value>)>>,
+    // the traits implemented by each type, as `(trait, type)`
+    impls: HashSet<(String, String)>,
+    // the dictionaries passed to the function call this is the scope of, by
+    // the type parameter whose bounds they are for
+    dictionaries: HashMap<String, Vec<Rc<Dictionary>>>,
+    // the bounded type parameter that is the declared type of each argument of
+    // that call
+    params: HashMap<String, String>,
     outer_scope: Option<Rc<RefCell<Env>>>,
 }
 
@@ -16,13 +30,189 @@ impl Env {
     pub fn new() -> Rc<RefCell<Self>> {
         let env = Env {
             symbol_table: HashMap::new(),
+            methods: HashMap::new(),
+            traits: HashMap::new(),
+            impls: HashSet::new(),
+            dictionaries: HashMap::new(),
+            params: HashMap::new(),
             outer_scope: None,
         };
 
         Rc::new(RefCell::new(env))
     }
+
+    // the top level scope of a program, with the builtin functions and the
+    // prelude defined
+    pub fn global() -> Rc<RefCell<Self>> {
+        let env = Env::new();
+        builtins::define(&mut env.borrow_mut());
+
+        let (prelude, _) = Parser::new(builtins::PRELUDE).parse();
+        prelude.eval(&env);
+        env
+    }
+
+    // create a scope for a block or a function call, enclosed by `outer`
+    pub fn child(outer: &Rc<RefCell<Env>>) -> Rc<RefCell<Self>> {
+        let env = Env {
+            symbol_table: HashMap::new(),
+            methods: HashMap::new(),
+            traits: HashMap::new(),
+            impls: HashSet::new(),
+            dictionaries: HashMap::new(),
+            params: HashMap::new(),
+            outer_scope: Some(Rc::clone(outer)),
+        };
+
+        Rc::new(RefCell::new(env))
+    }
+
+    // bind `ident` in this scope, shadowing any binding in the outer scopes
+    pub fn define(&mut self, ident: String, value: Value) {
+        self.params.remove(&ident);
+        self.symbol_table.insert(ident, value);
+    }
+
+    pub fn get(&self, ident: &str) -> Option<Value> {
+        match self.symbol_table.get(ident) {
+            Some(value) => Some(value.clone()),
+            None => self.outer_scope.as_ref()?.borrow().get(ident),
+        }
+    }
+
+    // update the innermost existing binding of `ident`
+    pub fn assign(&mut self, ident: &str, value: Value) -> Result<(), String> {
+        if let Some(slot) = self.symbol_table.get_mut(ident) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.outer_scope {
+            Some(outer) => outer.borrow_mut().assign(ident, value),
+            None => Err(format!("cannot assign to unknown identifier `{}`", ident)),
+        }
+    }
+
+    pub fn define_method(&mut self, ty: &str, ident: String, value: Value) {
+        self.methods
+            .entry(ty.to_owned())
+            .or_default()
+            .insert(ident, value);
+    }
+
+    // the method `ident` of the values whose `type_name` is `ty`
+    pub fn method(&self, ty: &str, ident: &str) -> Option<Value> {
+        match self.methods.get(ty).and_then(|methods| methods.get(ident)) {
+            Some(value) => Some(value.clone()),
+            None => self.outer_scope.as_ref()?.borrow().method(ty, ident),
+        }
+    }
+
+    // whether some type has a method called `ident`
+    pub fn is_method(&self, ident: &str) -> bool {
+        self.methods
+            .values()
+            .any(|methods| methods.contains_key(ident))
+            || self
+                .outer_scope
+                .as_ref()
+                .is_some_and(|outer| outer.borrow().is_method(ident))
+    }
+
+    // the method `ident` of the value bound to `value`, if its declared type
+    // is a bounded type parameter: the one in the dictionaries passed for the
+    // parameter, which has no others
+    pub fn bounded_method(&self, value: &str, ident: &str) -> Option<Option<Value>> {
+        if !self.symbol_table.contains_key(value) {
+            return self
+                .outer_scope
+                .as_ref()?
+                .borrow()
+                .bounded_method(value, ident);
+        }
+
+        let dictionaries = self.dictionaries.get(self.params.get(value)?)?;
+        let method = dictionaries
+            .iter()
+            .find_map(|dictionary| dictionary.methods.get(ident));
+        Some(method.cloned())
+    }
+
+    pub fn define_trait(&mut self, ident: String, methods: Vec<(String, Option<Value>)>) {
+        self.traits.insert(ident, methods);
+    }
+
+    pub fn trait_methods(&self, ident: &str) -> Option<Vec<(String, Option<Value>)>> {
+        match self.traits.get(ident) {
+            Some(methods) => Some(methods.clone()),
+            None => self.outer_scope.as_ref()?.borrow().trait_methods(ident),
+        }
+    }
+
+    pub fn implement(&mut self, trait_: &str, ty: &str) {
+        self.impls.insert((trait_.to_owned(), ty.to_owned()));
+    }
+
+    pub fn implements(&self, trait_: &str, ty: &str) -> bool {
+        let key = (trait_.to_owned(), ty.to_owned());
+
+        match &self.outer_scope {
+            _ if self.impls.contains(&key) => true,
+            Some(outer) => outer.borrow().implements(trait_, ty),
+            None => false,
+        }
+    }
+
+    // the methods of `trait_` for the values whose `type_name` is `ty`, if it
+    // implements the trait
+    pub fn dictionary(&self, trait_: &str, ty: &str) -> Option<Dictionary> {
+        if !self.implements(trait_, ty) {
+            return None;
+        }
+
+        let methods = self
+            .trait_methods(trait_)?
+            .into_iter()
+            .filter_map(|(ident, _)| {
+                let method = self.method(ty, &ident)?;
+                Some((ident, method))
+            })
+            .collect();
+
+        Some(Dictionary {
+            ty: ty.to_owned(),
+            methods,
+        })
+    }
+}
+
+// The methods of a trait for the type a bounded type parameter stands for in
+// one call, looked up once per call instead of at each use
+#[derive(Debug)]
+pub struct Dictionary {
+    pub ty: String,
+    pub methods: HashMap<String, Value>,
+}
+
+// a trait bounding a type parameter of a function
+#[derive(Debug)]
+pub struct Bound {
+    pub param: String,
+    pub trait_: String,
+}
+
+// closures keep their defining scope alive, which usually contains the closure
+// itself, so only the names are printed to avoid recursing forever
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Env")
+            .field("symbols", &self.symbol_table.keys())
+            .field("outer_scope", &self.outer_scope)
+            .finish()
+    }
 }
 
+#[derive(Debug, Clone)]
 pub enum Value {
     Unit,
     Int(i64),
@@ -30,11 +220,645 @@ pub enum Value {
     Bool(bool),
     Char(char),
     Str(String),
+    Array(Vec<Value>),
+    // integers from the start up to, but not including, the end
+    Range(i64, i64),
+    Tuple(Vec<Value>),
+    // instances of a declared type, with their fields in declaration order
+    Struct(Rc<Constructor>, Vec<Value>),
+    Variant(Rc<Constructor>, Vec<Value>),
     Return(Box<Value>),
+    // a `break` or `continue` on its way to the loop with the label, or to the
+    // innermost loop without one
+    Break(Option<String>, Box<Value>),
+    Continue(Option<String>),
     Error(String),
     Function {
         args: Vec<String>,
-        body: Vec<Stmt>,
-        outer_scope: Env,
+        body: Rc<Expr>,
+        outer_scope: Rc<RefCell<Env>>,
+    },
+    Builtin {
+        name: &'static str,
+        function: fn(Vec<Value>) -> Value,
+    },
+    // a struct or variant with fields, called with them to build an instance
+    Constructor(Rc<Constructor>),
+    // a method and the receiver it is called on: `point.distance`
+    Method(Box<Value>, Box<Value>),
+    // a function with bounded type parameters and the declared types of its
+    // arguments, which is passed a dictionary for each bound when called
+    Generic {
+        bounds: Rc<Vec<Bound>>,
+        types: Rc<Vec<Type>>,
+        function: Box<Value>,
     },
 }
+
+// A struct, or a variant of an enum, as declared
+#[derive(Debug, PartialEq)]
+pub struct Constructor {
+    // the type it builds, which is the struct itself or the enum
+    pub ty: String,
+    pub ident: String,
+    // the names of the fields, `None` for positional ones
+    pub fields: Vec<Option<String>>,
+    // the declared types of the fields, in terms of the type parameters
+    pub types: Vec<Type>,
+    pub generics: Vec<String>,
+    pub variant: bool,
+    // the position of the variant in its enum, which orders the variants
+    pub index: usize,
+}
+
+impl Constructor {
+    pub fn build(self: &Rc<Self>, values: Vec<Value>) -> Value {
+        match self.variant {
+            true => Value::Variant(Rc::clone(self), values),
+            false => Value::Struct(Rc::clone(self), values),
+        }
+    }
+
+    // the position of the field called `name`
+    pub fn field(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .position(|field| field.as_deref() == Some(name))
+    }
+}
+
+impl Value {
+    pub fn is_error(&self) -> bool {
+        matches!(self, Value::Error(_))
+    }
+
+    // values that stop the evaluation of a block and bubble up to the caller
+    pub fn is_unwinding(&self) -> bool {
+        matches!(
+            self,
+            Value::Return(_) | Value::Break(..) | Value::Continue(_) | Value::Error(_)
+        )
+    }
+
+    pub(crate) fn type_name(&self) -> &str {
+        match self {
+            Value::Unit => "Unit",
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Bool(_) => "Bool",
+            Value::Char(_) => "Char",
+            Value::Str(_) => "String",
+            Value::Array(_) => "Array",
+            Value::Range(..) => "Range",
+            Value::Tuple(_) => "Tuple",
+            Value::Struct(constructor, _) | Value::Variant(constructor, _) => &constructor.ty,
+            Value::Return(value) | Value::Break(_, value) => value.type_name(),
+            Value::Continue(_) => "Unit",
+            Value::Error(_) => "Error",
+            Value::Function { .. }
+            | Value::Builtin { .. }
+            | Value::Constructor(_)
+            | Value::Method(..)
+            | Value::Generic { .. } => "Function",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "()"),
+            Value::Int(int) => write!(f, "{}", int),
+            Value::Float(float) => write!(f, "{:?}", float),
+            Value::Bool(bool) => write!(f, "{}", bool),
+            Value::Char(ch) => write!(f, "{}", ch),
+            Value::Str(str) => write!(f, "{}", str),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Value::Range(from, to) => write!(f, "{}..{}", from, to),
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                match values.len() {
+                    1 => write!(f, ",)"),
+                    _ => write!(f, ")"),
+                }
+            }
+            // `Point { x: 1, y: 2 }`, `Some { 1 }` or `None`
+            Value::Struct(constructor, values) | Value::Variant(constructor, values) => {
+                write!(f, "{}", constructor.ident)?;
+                if values.is_empty() {
+                    return Ok(());
+                }
+
+                write!(f, " {{ ")?;
+                for (i, (name, value)) in constructor.fields.iter().zip(values).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match name {
+                        Some(name) => write!(f, "{}: {}", name, value)?,
+                        None => write!(f, "{}", value)?,
+                    }
+                }
+                write!(f, " }}")
+            }
+            Value::Return(value) | Value::Break(_, value) => write!(f, "{}", value),
+            Value::Continue(_) => write!(f, "()"),
+            Value::Error(err) => write!(f, "Runtime Error: {}", err),
+            Value::Function { args, .. } if args.is_empty() => write!(f, "<function>"),
+            Value::Function { args, .. } => write!(f, "<function {}>", args.join(", ")),
+            Value::Builtin { name, .. } => write!(f, "<builtin {}>", name),
+            Value::Constructor(constructor) => write!(f, "<constructor {}>", constructor.ident),
+            Value::Method(_, function) | Value::Generic { function, .. } => {
+                write!(f, "{}", function)
+            }
+        }
+    }
+}
+
+pub fn call(function: &Value, values: Vec<Value>) -> Value {
+    match function {
+        Value::Function {
+            args,
+            body,
+            outer_scope,
+        } => call_function(
+            args,
+            body,
+            outer_scope,
+            values,
+            HashMap::new(),
+            HashMap::new(),
+        ),
+        Value::Builtin { function, .. } => function(values),
+        Value::Constructor(constructor) => {
+            if constructor.fields.len() != values.len() {
+                return Value::Error(format!(
+                    "`{}` has {} fields, found {}",
+                    constructor.ident,
+                    constructor.fields.len(),
+                    values.len()
+                ));
+            }
+
+            constructor.build(values)
+        }
+        Value::Method(receiver, function) => {
+            let mut values = values;
+            values.insert(0, (**receiver).clone());
+            call(function, values)
+        }
+        Value::Generic {
+            bounds,
+            types,
+            function,
+        } => {
+            let Value::Function {
+                args,
+                body,
+                outer_scope,
+            } = &**function
+            else {
+                return call(function, values);
+            };
+
+            let mut dictionaries: HashMap<String, Vec<Rc<Dictionary>>> = HashMap::new();
+
+            for bound in bounds.iter() {
+                // the type the parameter stands for is that of the values of
+                // it in the arguments. Without any, none of its methods are
+                // called.
+                let Some(value) = types
+                    .iter()
+                    .zip(&values)
+                    .find_map(|(ty, value)| instance(ty, value, &bound.param))
+                else {
+                    continue;
+                };
+                let ty = value.type_name();
+
+                match outer_scope.borrow().dictionary(&bound.trait_, ty) {
+                    Some(dictionary) => dictionaries
+                        .entry(bound.param.clone())
+                        .or_default()
+                        .push(Rc::new(dictionary)),
+                    None => {
+                        return Value::Error(format!(
+                            "`{}` doesn't implement `{}`",
+                            ty, bound.trait_
+                        ))
+                    }
+                }
+            }
+
+            let params = args
+                .iter()
+                .zip(types.iter())
+                .filter_map(|(arg, ty)| match ty {
+                    Type::NewType(param, args) if args.is_empty() => {
+                        Some((arg.clone(), param.clone()))
+                    }
+                    _ => None,
+                })
+                .filter(|(_, param)| dictionaries.contains_key(param))
+                .collect();
+
+            call_function(args, body, outer_scope, values, dictionaries, params)
+        }
+        err @ Value::Error(_) => err.clone(),
+        value => Value::Error(format!("{} is not callable", value.type_name())),
+    }
+}
+
+// call a function defined in `outer_scope`, with `dictionaries` passed to it
+// for the type parameters that are the declared types of the arguments in
+// `params`
+fn call_function(
+    args: &[String],
+    body: &Expr,
+    outer_scope: &Rc<RefCell<Env>>,
+    values: Vec<Value>,
+    dictionaries: HashMap<String, Vec<Rc<Dictionary>>>,
+    params: HashMap<String, String>,
+) -> Value {
+    if args.len() != values.len() {
+        return Value::Error(format!(
+            "expected {} arguments, found {}",
+            args.len(),
+            values.len()
+        ));
+    }
+
+    let env = Env::child(outer_scope);
+
+    for (arg, value) in args.iter().zip(values) {
+        env.borrow_mut().define(arg.clone(), value);
+    }
+
+    env.borrow_mut().dictionaries = dictionaries;
+    env.borrow_mut().params = params;
+
+    returned(body.eval(&env))
+}
+
+// a value of the type parameter `param` in `value`, whose declared type is `ty`
+fn instance<'v>(ty: &Type, value: &'v Value, param: &str) -> Option<&'v Value> {
+    match (ty, value) {
+        (Type::NewType(ident, args), _) if args.is_empty() && ident == param => Some(value),
+        (Type::Tuple(types), Value::Tuple(values)) => types
+            .iter()
+            .zip(values)
+            .find_map(|(ty, value)| instance(ty, value, param)),
+        (Type::NewType(ident, args), Value::Array(values)) if ident == "Array" => {
+            let element = args.first()?;
+            values
+                .iter()
+                .find_map(|value| instance(element, value, param))
+        }
+        (Type::NewType(ident, args), Value::Struct(constructor, values))
+        | (Type::NewType(ident, args), Value::Variant(constructor, values))
+            if *ident == constructor.ty =>
+        {
+            constructor
+                .types
+                .iter()
+                .zip(values)
+                .find_map(|(field, value)| {
+                    let field = substitute(field, &constructor.generics, args);
+                    instance(&field, value, param)
+                })
+        }
+        _ => None,
+    }
+}
+
+// `ty` with the type parameters in `generics` replaced by the types in `args`
+fn substitute(ty: &Type, generics: &[String], args: &[Type]) -> Type {
+    match ty {
+        Type::NewType(ident, params) if params.is_empty() => {
+            match generics.iter().position(|generic| generic == ident) {
+                Some(i) => args.get(i).cloned().unwrap_or_else(|| ty.clone()),
+                None => ty.clone(),
+            }
+        }
+        Type::NewType(ident, params) => Type::NewType(
+            ident.clone(),
+            params
+                .iter()
+                .map(|param| substitute(param, generics, args))
+                .collect(),
+        ),
+        Type::Tuple(types) => Type::Tuple(
+            types
+                .iter()
+                .map(|ty| substitute(ty, generics, args))
+                .collect(),
+        ),
+        Type::Function(params, ret) => Type::Function(
+            params
+                .iter()
+                .map(|param| substitute(param, generics, args))
+                .collect(),
+            Box::new(substitute(ret, generics, args)),
+        ),
+        ty => ty.clone(),
+    }
+}
+
+// the result of a function or program whose body has finished or unwound. The
+// parser only allows `break` and `continue` inside loops, so they never get here.
+pub fn returned(value: Value) -> Value {
+    match value {
+        Value::Return(value) => *value,
+        Value::Break(..) | Value::Continue(_) => {
+            Value::Error("`break` and `continue` only work inside a loop".to_owned())
+        }
+        value => value,
+    }
+}
+
+pub fn eval_unary(operator: &Operator, operand: Value) -> Value {
+    match (operator, operand) {
+        (_, err @ Value::Error(_)) => err,
+        (Operator::Minus, Value::Int(int)) => match int.checked_neg() {
+            Some(int) => Value::Int(int),
+            None => Value::Error("integer overflow".to_owned()),
+        },
+        (Operator::Minus, Value::Float(float)) => Value::Float(-float),
+        (Operator::Not, Value::Bool(bool)) => Value::Bool(!bool),
+        (Operator::BitNot, Value::Int(int)) => Value::Int(!int),
+        (operator, operand) => Value::Error(format!(
+            "unsupported operand for {:?}: {}",
+            operator,
+            operand.type_name()
+        )),
+    }
+}
+
+pub fn eval_binary(operator: &Operator, left: Value, right: Value) -> Value {
+    use Operator::*;
+    use Value::{Bool, Float, Int};
+
+    match (operator, left, right) {
+        (_, err @ Value::Error(_), _) | (_, _, err @ Value::Error(_)) => err,
+
+        (Plus, Int(l), Int(r)) => checked(l.checked_add(r)),
+        (Minus, Int(l), Int(r)) => checked(l.checked_sub(r)),
+        (Multiply, Int(l), Int(r)) => checked(l.checked_mul(r)),
+        (Divide | Modulo, Int(_), Int(0)) => Value::Error("division by zero".to_owned()),
+        (Divide, Int(l), Int(r)) => checked(l.checked_div(r)),
+        (Modulo, Int(l), Int(r)) => checked(l.checked_rem(r)),
+        (Power, Int(l), Int(r)) => match u32::try_from(r) {
+            Ok(r) => checked(l.checked_pow(r)),
+            Err(_) => Value::Error("negative integer exponent".to_owned()),
+        },
+
+        (Plus, Float(l), Float(r)) => Float(l + r),
+        (Minus, Float(l), Float(r)) => Float(l - r),
+        (Multiply, Float(l), Float(r)) => Float(l * r),
+        (Divide, Float(l), Float(r)) => Float(l / r),
+        (Modulo, Float(l), Float(r)) => Float(l % r),
+        (Power, Float(l), Float(r)) => Float(l.powf(r)),
+
+        (BitAnd, Int(l), Int(r)) => Int(l & r),
+        (BitOr, Int(l), Int(r)) => Int(l | r),
+        (BitXor, Int(l), Int(r)) => Int(l ^ r),
+        (LeftShift | RightShift, Int(_), Int(r)) if !(0..64).contains(&r) => {
+            Value::Error("shift amount out of range".to_owned())
+        }
+        (LeftShift, Int(l), Int(r)) => Int(l << r),
+        (RightShift, Int(l), Int(r)) => Int(l >> r),
+
+        (And, Bool(l), Bool(r)) => Bool(l && r),
+        (Or, Bool(l), Bool(r)) => Bool(l || r),
+
+        (Plus, Value::Str(l), Value::Str(r)) => Value::Str(l + &r),
+
+        (Equal | NotEqual | LessThan | GreaterThan | LessEqual | GreaterEqual, left, right) => {
+            compare(operator, left, right)
+        }
+
+        (operator, left, right) => Value::Error(format!(
+            "unsupported operands for {:?}: {} and {}",
+            operator,
+            left.type_name(),
+            right.type_name()
+        )),
+    }
+}
+
+fn compare(operator: &Operator, left: Value, right: Value) -> Value {
+    let ordering = match ordering(&left, &right) {
+        Some(ordering) => ordering,
+        None => {
+            return Value::Error(format!(
+                "cannot compare {} with {}",
+                left.type_name(),
+                right.type_name()
+            ))
+        }
+    };
+
+    let result = match operator {
+        Operator::Equal => ordering.is_some_and(|ordering| ordering.is_eq()),
+        Operator::NotEqual => !ordering.is_some_and(|ordering| ordering.is_eq()),
+        Operator::LessThan => ordering.is_some_and(|ordering| ordering.is_lt()),
+        Operator::GreaterThan => ordering.is_some_and(|ordering| ordering.is_gt()),
+        Operator::LessEqual => ordering.is_some_and(|ordering| ordering.is_le()),
+        Operator::GreaterEqual => ordering.is_some_and(|ordering| ordering.is_ge()),
+        _ => unreachable!("{:?} is not a comparison", operator),
+    };
+
+    Value::Bool(result)
+}
+
+// how two values of the same type compare, which is `None` inside for NaN.
+// Tuples of the same length and arrays compare element by element, and
+// instances of a type by variant and then field by field.
+pub(crate) fn ordering(left: &Value, right: &Value) -> Option<Option<std::cmp::Ordering>> {
+    let ordering = match (left, right) {
+        (Value::Unit, Value::Unit) => Some(std::cmp::Ordering::Equal),
+        (Value::Int(l), Value::Int(r)) => l.partial_cmp(r),
+        (Value::Float(l), Value::Float(r)) => l.partial_cmp(r),
+        (Value::Bool(l), Value::Bool(r)) => l.partial_cmp(r),
+        (Value::Char(l), Value::Char(r)) => l.partial_cmp(r),
+        (Value::Str(l), Value::Str(r)) => l.partial_cmp(r),
+        (Value::Tuple(l), Value::Tuple(r)) if l.len() == r.len() => return elementwise(l, r),
+        (Value::Array(l), Value::Array(r)) => return elementwise(l, r),
+        (Value::Struct(l, l_values), Value::Struct(r, r_values))
+        | (Value::Variant(l, l_values), Value::Variant(r, r_values))
+            if l.ty == r.ty =>
+        {
+            match l.index.cmp(&r.index) {
+                std::cmp::Ordering::Equal => return elementwise(l_values, r_values),
+                ordering => Some(ordering),
+            }
+        }
+        _ => return None,
+    };
+
+    Some(ordering)
+}
+
+// compare the elements in order, then the lengths
+fn elementwise(left: &[Value], right: &[Value]) -> Option<Option<std::cmp::Ordering>> {
+    for (l, r) in left.iter().zip(right) {
+        match ordering(l, r)? {
+            Some(std::cmp::Ordering::Equal) => {}
+            ordering => return Some(ordering),
+        }
+    }
+
+    Some(Some(left.len().cmp(&right.len())))
+}
+
+fn checked(result: Option<i64>) -> Value {
+    match result {
+        Some(int) => Value::Int(int),
+        None => Value::Error("integer overflow".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{Arg, Binary, Call, ExprKind, Function, Span};
+
+    fn expr(kind: ExprKind) -> Expr {
+        Expr::new(kind, Span::default())
+    }
+
+    fn ident(ident: &str) -> Expr {
+        expr(ExprKind::Ident(ident.to_owned()))
+    }
+
+    fn function(args: &[&str], body: Expr) -> Expr {
+        expr(ExprKind::Function(Box::new(Function {
+            args: args
+                .iter()
+                .map(|arg| Arg {
+                    ident: arg.to_string(),
+                    mutable: false,
+                    span: Span::default(),
+                })
+                .collect(),
+            body: Rc::new(body),
+        })))
+    }
+
+    fn add(left: Expr, right: Expr) -> Expr {
+        expr(ExprKind::Binary(Box::new(Binary {
+            operator: Operator::Plus,
+            left_operand: left,
+            right_operand: right,
+        })))
+    }
+
+    fn call_ident(ident: &str, arg: Expr) -> Expr {
+        expr(ExprKind::Call(Box::new(Call {
+            callee: self::ident(ident),
+            args: vec![arg],
+        })))
+    }
+
+    fn int(value: &Value) -> i64 {
+        match value {
+            Value::Int(int) => *int,
+            value => panic!("expected Int, found {}", value),
+        }
+    }
+
+    #[test]
+    fn lookup_walks_outer_scopes() {
+        let global = Env::new();
+        global.borrow_mut().define("x".to_owned(), Value::Int(1));
+
+        let inner = Env::child(&Env::child(&global));
+        assert_eq!(inner.borrow().get("x").as_ref().map(int), Some(1));
+        assert!(inner.borrow().get("y").is_none());
+    }
+
+    #[test]
+    fn define_shadows_outer_binding() {
+        let global = Env::new();
+        global.borrow_mut().define("x".to_owned(), Value::Int(1));
+
+        let inner = Env::child(&global);
+        inner.borrow_mut().define("x".to_owned(), Value::Int(2));
+
+        assert_eq!(inner.borrow().get("x").as_ref().map(int), Some(2));
+        assert_eq!(global.borrow().get("x").as_ref().map(int), Some(1));
+    }
+
+    #[test]
+    fn assign_updates_innermost_binding() {
+        let global = Env::new();
+        global.borrow_mut().define("x".to_owned(), Value::Int(1));
+
+        let inner = Env::child(&global);
+        assert!(inner.borrow_mut().assign("x", Value::Int(5)).is_ok());
+        assert_eq!(global.borrow().get("x").as_ref().map(int), Some(5));
+
+        assert!(inner.borrow_mut().assign("y", Value::Int(5)).is_err());
+        assert!(global.borrow().get("y").is_none());
+    }
+
+    #[test]
+    fn closures_capture_defining_scope() {
+        let env = Env::new();
+        let make_adder = function(&["n"], function(&["x"], add(ident("x"), ident("n"))));
+
+        let add_one = call(&make_adder.eval(&env), vec![Value::Int(1)]);
+        let add_ten = call(&make_adder.eval(&env), vec![Value::Int(10)]);
+
+        assert_eq!(int(&call(&add_one, vec![Value::Int(2)])), 3);
+        assert_eq!(int(&call(&add_ten, vec![Value::Int(2)])), 12);
+    }
+
+    #[test]
+    fn closures_share_their_scope() {
+        let env = Env::new();
+        env.borrow_mut().define("count".to_owned(), Value::Int(0));
+
+        let read_count = function(&[], ident("count")).eval(&env);
+        env.borrow_mut().assign("count", Value::Int(5)).unwrap();
+
+        assert_eq!(int(&call(&read_count, vec![])), 5);
+    }
+
+    #[test]
+    fn apply_twice() {
+        let env = Env::new();
+        let apply_twice = function(&["f", "x"], call_ident("f", call_ident("f", ident("x"))));
+        let increment = function(&["a"], add(ident("a"), expr(ExprKind::Int("1".to_owned()))));
+
+        let result = call(
+            &apply_twice.eval(&env),
+            vec![increment.eval(&env), Value::Int(5)],
+        );
+
+        assert_eq!(int(&result), 7);
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        let env = Env::new();
+        let identity = function(&["x"], ident("x")).eval(&env);
+
+        assert!(call(&identity, vec![]).is_error());
+    }
+}
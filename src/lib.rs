@@ -1,5 +1,8 @@
 #![allow(unused, warnings)]
 
 pub mod alloc;
+pub mod builtins;
 pub mod eval;
 pub mod parser;
+
+pub use parser::{Lexer, Token, TokenKind, TokenStream, KEYWORDS};
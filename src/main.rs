@@ -1,9 +1,15 @@
-use morph::parser::Parser;
+use morph::eval::{Env, Eval, Value};
+use morph::parser::{FlatParser, Parser, Severity, Span, TypeError};
 use std::io::{stdin, stdout, Write};
 
 fn main() {
     let stdin = stdin();
     let mut stdout = stdout();
+    let env = Env::global();
+    // the lines evaluated so far, which each new line is checked along with
+    let mut program = String::new();
+    // print the syntax tree of each line instead of evaluating it
+    let mut ast_mode = false;
 
     println!("Morph v0.1.0");
     println!();
@@ -12,24 +18,95 @@ fn main() {
         print!(">> ");
         stdout.flush().unwrap();
         let mut buf = String::new();
-        stdin.read_line(&mut buf).unwrap();
+
+        if stdin.read_line(&mut buf).unwrap() == 0 {
+            break;
+        }
 
         // for token in lexer {
         //     println!("{:?}", token);
         // }
 
+        if buf.trim() == ":ast" {
+            ast_mode = !ast_mode;
+            println!("ast mode {}", if ast_mode { "on" } else { "off" });
+            println!();
+            continue;
+        }
+
+        if ast_mode {
+            let (ast, diagnostics) = FlatParser::new(&buf).parse();
+            println!("{}", ast);
+
+            for diagnostic in diagnostics {
+                print!("{}", diagnostic.render("repl", &buf));
+            }
+
+            println!();
+            continue;
+        }
+
         let mut parser = Parser::new(&buf);
-        let ast = parser.parse();
+        let (ast, diagnostics) = parser.parse();
+
+        for diagnostic in &diagnostics {
+            print!("{}", diagnostic.render("repl", &buf));
+        }
 
-        match ast {
-            Ok(ast) => {
-                for stmt in ast.stmts() {
-                    println!("{:?}", stmt);
+        if diagnostics.is_empty() {
+            // the lines may leave out the `;` separating them
+            let source = format!("{}{};\n", program, buf.trim_end());
+            let (mut checked, _) = FlatParser::new(&source).parse();
+            let errors = checked.infer_types().err().unwrap_or_default();
+            let mut typed = true;
+
+            // only report what the new line got wrong
+            for error in errors.iter().chain(checked.warnings()) {
+                let Some(mut error) = in_line(error, program.len()) else {
+                    continue;
+                };
+
+                // a later line may bind it
+                if error.code == "unknown-identifier" {
+                    error.severity = Severity::Warning;
+                }
+
+                typed &= error.severity == Severity::Warning;
+                print!("{}", error.diagnostic().render("repl", &buf));
+            }
+
+            if typed {
+                match ast.eval(&env) {
+                    Value::Unit => program = source,
+                    value @ Value::Error(_) => println!("{}", value),
+                    value => {
+                        program = source;
+                        println!("{}", value);
+                    }
                 }
             }
-            Err(err) => println!("{:?}", err),
         }
 
         println!();
     }
 }
+
+// `error` with its spans in terms of the line at `offset` of the program, if
+// it is about that line. Spans in the lines before it are left out.
+fn in_line(error: &TypeError, offset: usize) -> Option<TypeError> {
+    if error.spans.first().is_some_and(|span| span.start < offset) {
+        return None;
+    }
+
+    let spans = error
+        .spans
+        .iter()
+        .filter(|span| span.start >= offset)
+        .map(|span| Span::new(span.start - offset, span.end - offset))
+        .collect();
+
+    Some(TypeError {
+        spans,
+        ..error.clone()
+    })
+}
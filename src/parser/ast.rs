@@ -1,15 +1,26 @@
-use crate::eval::{Env, Eval, Value};
+use crate::builtins::{self, elements};
+use crate::eval::{call, eval_binary, eval_unary, returned, Bound, Constructor, Env, Eval, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
 
-#[derive(Debug)]
+use super::{parse_float, parse_int, parse_negated_int, unescape, Span};
+
+#[derive(Debug, PartialEq)]
 pub struct Ast {
-    stmts: Vec<Stmt>,
+    pub stmts: Vec<Stmt>,
+    // every comment in the source, in order
+    pub trivia: Vec<Trivia>,
     // new_types: Vec<Type>,
     // expr_types: HashMap<Expression, Type>,
 }
 
 impl Ast {
     pub fn new() -> Self {
-        Self { stmts: Vec::new() }
+        Self {
+            stmts: Vec::new(),
+            trivia: Vec::new(),
+        }
     }
 
     pub fn push(&mut self, stmt: Stmt) {
@@ -21,110 +32,242 @@ impl Ast {
     }
 }
 
-#[derive(Debug)]
-pub enum Stmt {
+// A comment, which the parser skips but keeps for formatters and
+// documentation generators
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trivia {
+    pub span: Span,
+    // a `---` doc comment
+    pub doc: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+    // the lines of the doc comments right above a binding or signature
+    pub docs: Vec<String>,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt {
+            kind,
+            span,
+            docs: Vec::new(),
+        }
+    }
+
+    pub fn with_docs(mut self, docs: Vec<String>) -> Self {
+        self.docs = docs;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
     Expr(Expr),
     Binding(String, Expr),
+    Assign(String, Operator, Expr),
     Return(Expr),
-    For(Option<Expr>, Vec<Stmt>),
-    Spawn(Vec<Stmt>),
-    Select(Vec<Stmt>),
+    // `break 'label value`, where both are optional
+    Break(Option<String>, Option<Expr>),
+    Continue(Option<String>),
+    Signature(Box<Signature>),
+    TypeDecl(Box<TypeDecl>),
+    Trait(Box<Trait>),
+    Impl(Box<Impl>),
+    // a statement with a syntax error, which was skipped
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
 }
 
-#[derive(Debug)]
-pub enum Expr {
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
     Unit,
     Int(String),
     Float(String),
     Bool(String),
     Char(String),
     Str(String),
+    RawStr(String),
     Ident(String),
-    Array(String),
-    Map(String),
-    Index(Box<Index>),
     Range(Box<Range>),
-    Slice(Box<Slice>),
     Unary(Box<Unary>),
     Binary(Box<Binary>),
     Conditional(Box<Conditional>),
     Match(Box<Match>),
+    Tuple(Vec<Expr>),
+    Loop(Box<Loop>),
+    While(Box<While>),
+    For(Box<For>),
     Function(Box<Function>),
     Call(Box<Call>),
     Field(Box<Field>),
+    Struct(Box<Struct>),
+    Try(Box<Try>),
     Scope(Vec<Stmt>),
 }
 
-#[derive(Debug)]
-struct Index {
-    target: Expr,
-    index: Expr,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    pub from: Expr,
+    pub to: Expr,
+    pub inclusive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unary {
+    pub operator: Operator,
+    pub operand: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binary {
+    pub operator: Operator,
+    pub left_operand: Expr,
+    pub right_operand: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conditional {
+    pub condition: Expr,
+    pub consequent: Vec<Stmt>,
+    pub alternative: Option<Vec<Stmt>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub matched: Expr,
+    pub arms: Vec<Arm>,
+}
+
+// `pattern if guard => body`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+    pub span: Span,
+}
+
+// `'label: loop { body }`, which runs until a `break`, whose value it takes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub label: Option<String>,
+    pub body: Vec<Stmt>,
+}
+
+// `while condition { body }`, or `while pattern = value { body }`, which runs
+// for as long as the value matches the pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct While {
+    pub label: Option<String>,
+    pub pattern: Option<Pattern>,
+    pub condition: Expr,
+    pub body: Vec<Stmt>,
+}
+
+// `for pattern in iterable { body }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct For {
+    pub label: Option<String>,
+    pub pattern: Pattern,
+    pub iterable: Expr,
+    pub body: Vec<Stmt>,
 }
 
-#[derive(Debug)]
-struct Range {
-    target: Expr,
-    from: Expr,
-    to: Expr,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
 }
 
-#[derive(Debug)]
-struct Slice {
-    target: Expr,
-    range: Expr,
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    Wildcard,
+    Binding(String),
+    // a literal, or a negated number: `-1`
+    Literal(Expr),
+    // `1..=5` or `'a'..'z'`, with literal bounds
+    Range(Box<Range>),
+    // `(a, _)`
+    Tuple(Vec<Pattern>),
+    // `Some { x }`, `Some(x)`, `Point { x = px, y }`, or `None` without fields
+    Constructor(String, Vec<FieldPattern>),
+    // `1 | 2 | 3`
+    Or(Vec<Pattern>),
+    // `n @ 1..=5` binds the whole value if it matches the pattern
+    At(String, Box<Pattern>),
 }
 
-#[derive(Debug)]
-struct Unary {
-    operator: Operator,
-    operand: Expr,
+// A field of a constructor pattern, matched by position unless it is named
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldPattern {
+    pub name: Option<String>,
+    pub pattern: Pattern,
 }
 
-#[derive(Debug)]
-struct Binary {
-    operator: Operator,
-    left_operand: Expr,
-    right_operand: Expr,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub args: Vec<Arg>,
+    // shared with the closures made from the function
+    pub body: Rc<Expr>,
 }
 
-#[derive(Debug)]
-struct Conditional {
-    condition: Expr,
-    consequent: Vec<Stmt>,
-    alternative: Option<Vec<Stmt>>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arg {
+    pub ident: String,
+    pub mutable: bool,
+    pub span: Span,
 }
 
-#[derive(Debug)]
-struct Match {
-    matched: Expr,
-    arms: Vec<Expr>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub callee: Expr,
+    pub args: Vec<Expr>,
 }
 
-#[derive(Debug)]
-struct Function {
-    args: Vec<String>,
-    body: Expr,
+// `point.x`, or `pair.0` where `field` is an `Int`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub target: Expr,
+    pub field: Expr,
 }
 
-#[derive(Debug)]
-struct Call {
-    ident: String,
-    args: Expr,
+// `Point { x: 0, y: 0 }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub ident: String,
+    pub fields: Vec<(String, Expr)>,
 }
 
-#[derive(Debug)]
-struct Field {
-    target: Expr,
-    field: Expr,
+// `value?` or `value.try`, which unwraps a `Some` or an `Ok` and returns
+// anything else, or `value ? default`, which returns the default instead
+#[derive(Debug, Clone, PartialEq)]
+pub struct Try {
+    pub expr: Expr,
+    pub default: Option<Expr>,
 }
 
-#[derive(Debug)]
-struct Method {
-    target: Expr,
-    method: Expr,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub target: Expr,
+    pub method: Expr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Operator {
     Plus,
     Minus,
@@ -141,41 +284,1038 @@ pub enum Operator {
     And,
     Or,
     Not,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    LeftShift,
+    RightShift,
+}
+
+// `ident | generics -> ty;`, followed by the binding it annotates, if any
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub ident: String,
+    pub generics: Vec<Generic>,
+    pub ty: Type,
+    pub binding: Option<Stmt>,
+}
+
+// `Point<T> :: { x: T, y: T }` or `Option :: T -> | Some { T } | None`,
+// followed by the traits to derive, if any
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDecl {
+    pub ident: String,
+    pub generics: Vec<Generic>,
+    pub kind: TypeDeclKind,
+    pub derives: Vec<String>,
 }
 
-// #[derive(Debug)]
-// pub enum Type {
-//     Unit,
-//     Bool,
-//     Int,
-//     Float,
-//     Char,
-//     String,
-//     Tuple(Vec<Type>),
-//     Function(Box<Type>, Box<Type>),
-//     NewType(String),
-// }
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeDeclKind {
+    Struct(Vec<FieldDecl>),
+    Enum(Vec<Variant>),
+}
+
+// `Some { T }`, `Baz { boo: Option<T> }`, or `None` without fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub ident: String,
+    pub fields: Vec<FieldDecl>,
+}
+
+// a field of a struct or variant, which is positional unless it is named
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDecl {
+    pub name: Option<String>,
+    pub ty: Type,
+}
+
+// `trait ToString = { to_string | Self -> String; }`, where the methods with
+// only a signature are required and the others have a default
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trait {
+    pub ident: String,
+    pub generics: Vec<Generic>,
+    // `Binding`s and `Signature`s
+    pub items: Vec<Stmt>,
+}
+
+// `impl<T> Format for Node<T> where T: Format { format = self, f -> ...; }`,
+// or `impl Point { ... }` for methods outside of any trait
+#[derive(Debug, Clone, PartialEq)]
+pub struct Impl {
+    pub generics: Vec<Generic>,
+    pub trait_: Option<Type>,
+    pub ty: Type,
+    // the bounds of the `where` clause
+    pub predicates: Vec<Generic>,
+    pub items: Vec<Stmt>,
+}
+
+// a type parameter and the traits it must implement: `P: Parser<R> + Clone`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generic {
+    pub ident: String,
+    pub bounds: Vec<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Unit,
+    Never,
+    Bool,
+    Int,
+    Float,
+    Char,
+    String,
+    Tuple(Vec<Type>),
+    Function(Vec<Type>, Box<Type>),
+    NewType(String, Vec<Type>),
+}
+
+impl Type {
+    // the `type_name` of the values of this type at runtime, which keys the
+    // methods implemented for it
+    pub fn type_name(&self) -> Option<&str> {
+        match self {
+            Type::Unit => Some("Unit"),
+            Type::Never => None,
+            Type::Bool => Some("Bool"),
+            Type::Int => Some("Int"),
+            Type::Float => Some("Float"),
+            Type::Char => Some("Char"),
+            Type::String => Some("String"),
+            Type::Tuple(_) => Some("Tuple"),
+            Type::Function(..) => Some("Function"),
+            Type::NewType(ident, _) => Some(ident),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Modulo => "%",
+            Operator::Power => "**",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::LessThan => "<",
+            Operator::GreaterThan => ">",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::Not => "!",
+            Operator::BitAnd => "&",
+            Operator::BitOr => "|",
+            Operator::BitXor => "^",
+            Operator::BitNot => "~",
+            Operator::LeftShift => "<<",
+            Operator::RightShift => ">>",
+        };
+
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ident)?;
+
+        let generics = self.generics.iter().enumerate();
+
+        // without bounds, the type parameters can only be declared on the name
+        if self
+            .generics
+            .iter()
+            .all(|generic| generic.bounds.is_empty())
+        {
+            for (i, generic) in generics {
+                write!(f, "{}{}", if i == 0 { "<" } else { ", " }, generic)?;
+            }
+            if !self.generics.is_empty() {
+                write!(f, ">")?;
+            }
+            return write!(f, " | {}", self.ty);
+        }
+
+        write!(f, " | ")?;
+        for (i, generic) in generics {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", generic)?;
+        }
+
+        write!(f, " -> {}", self.ty)
+    }
+}
+
+impl fmt::Display for Generic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ident)?;
+
+        for (i, bound) in self.bounds.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { " + " };
+            write!(f, "{}{}", separator, bound)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Unit => write!(f, "()"),
+            Type::Never => write!(f, "Never"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Char => write!(f, "Char"),
+            Type::String => write!(f, "String"),
+            Type::Tuple(types) => {
+                write!(f, "(")?;
+                write_types(f, types)?;
+                write!(f, ")")
+            }
+            Type::Function(params, ret) => {
+                if params.is_empty() {
+                    write!(f, "()")?;
+                }
+
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    match param {
+                        Type::Function(..) => write!(f, "({})", param)?,
+                        param => write!(f, "{}", param)?,
+                    }
+                }
+
+                write!(f, " -> {}", ret)
+            }
+            Type::NewType(ident, args) => {
+                write!(f, "{}", ident)?;
+
+                if !args.is_empty() {
+                    write!(f, "<")?;
+                    write_types(f, args)?;
+                    write!(f, ">")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_types(f: &mut fmt::Formatter<'_>, types: &[Type]) -> fmt::Result {
+    for (i, ty) in types.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", ty)?;
+    }
+
+    Ok(())
+}
 
 impl Eval for Expr {
-    fn eval(&self, _: &Env) -> Value {
-        todo!()
+    fn eval(&self, env: &Rc<RefCell<Env>>) -> Value {
+        match &self.kind {
+            ExprKind::Unit => Value::Unit,
+            ExprKind::Int(literal) => match parse_int(literal) {
+                Ok(int) => Value::Int(int),
+                Err(err) => Value::Error(err),
+            },
+            ExprKind::Float(literal) => match parse_float(literal) {
+                Ok(float) => Value::Float(float),
+                Err(err) => Value::Error(err),
+            },
+            ExprKind::Bool(literal) => Value::Bool(literal == "true"),
+            ExprKind::Char(literal) => match unescape(literal).chars().next() {
+                Some(ch) => Value::Char(ch),
+                None => Value::Error("empty char literal".to_owned()),
+            },
+            ExprKind::Str(literal) => Value::Str(unescape(literal)),
+            ExprKind::RawStr(literal) => Value::Str(literal.clone()),
+            ExprKind::Ident(ident) => match env.borrow().get(ident) {
+                Some(value) => value,
+                None => Value::Error(format!("unknown identifier `{}`", ident)),
+            },
+            // `-9223372036854775808` is in range, though its literal isn't
+            ExprKind::Unary(unary) if unary.operator == Operator::Minus => {
+                match &unary.operand.kind {
+                    ExprKind::Int(literal) => match parse_negated_int(literal) {
+                        Ok(int) => Value::Int(int),
+                        Err(err) => Value::Error(err),
+                    },
+                    _ => match unary.operand.eval(env) {
+                        value if value.is_unwinding() => value,
+                        operand => eval_unary(&unary.operator, operand),
+                    },
+                }
+            }
+            ExprKind::Unary(unary) => match unary.operand.eval(env) {
+                value if value.is_unwinding() => value,
+                operand => eval_unary(&unary.operator, operand),
+            },
+            ExprKind::Binary(binary) => {
+                let left = binary.left_operand.eval(env);
+
+                match (&binary.operator, left) {
+                    (_, left) if left.is_unwinding() => left,
+                    (Operator::And, Value::Bool(false)) => Value::Bool(false),
+                    (Operator::Or, Value::Bool(true)) => Value::Bool(true),
+                    (operator, left) => match binary.right_operand.eval(env) {
+                        right if right.is_unwinding() => right,
+                        right => eval_binary(operator, left, right),
+                    },
+                }
+            }
+            ExprKind::Conditional(conditional) => match conditional.condition.eval(env) {
+                Value::Bool(true) => conditional.consequent.eval(&Env::child(env)),
+                Value::Bool(false) => match &conditional.alternative {
+                    Some(alternative) => alternative.eval(&Env::child(env)),
+                    None => Value::Unit,
+                },
+                value if value.is_unwinding() => value,
+                _ => Value::Error("condition must be a Bool".to_owned()),
+            },
+            ExprKind::Scope(stmts) => stmts.eval(&Env::child(env)),
+            ExprKind::Loop(looped) => loop {
+                if let Some(value) = exit(&looped.label, looped.body.eval(&Env::child(env))) {
+                    return value;
+                }
+            },
+            ExprKind::While(looped) => loop {
+                let scope = Env::child(env);
+                let condition = looped.condition.eval(env);
+
+                let enter = match (&looped.pattern, condition) {
+                    (_, err) if err.is_unwinding() => return err,
+                    (Some(pattern), value) => match pattern.bind(&value, &scope) {
+                        Ok(matched) => matched,
+                        Err(err) => return err,
+                    },
+                    (None, Value::Bool(condition)) => condition,
+                    (None, _) => return Value::Error("condition must be a Bool".to_owned()),
+                };
+
+                if !enter {
+                    return Value::Unit;
+                }
+
+                if let Some(value) = exit(&looped.label, looped.body.eval(&scope)) {
+                    return value;
+                }
+            },
+            ExprKind::For(looped) => {
+                let values: Box<dyn Iterator<Item = Value>> = match looped.iterable.eval(env) {
+                    Value::Range(from, to) => Box::new((from..to).map(Value::Int)),
+                    err if err.is_unwinding() => return err,
+                    iterable => match elements(iterable) {
+                        Ok(values) => Box::new(values.into_iter()),
+                        Err(err) => return err,
+                    },
+                };
+
+                for value in values {
+                    let scope = Env::child(env);
+
+                    match looped.pattern.bind(&value, &scope) {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(err) => return err,
+                    }
+
+                    if let Some(value) = exit(&looped.label, looped.body.eval(&scope)) {
+                        return value;
+                    }
+                }
+
+                Value::Unit
+            }
+            ExprKind::Function(function) => Value::Function {
+                args: function.args.iter().map(|arg| arg.ident.clone()).collect(),
+                body: Rc::clone(&function.body),
+                outer_scope: Rc::clone(env),
+            },
+            ExprKind::Call(call_expr) => {
+                let mut args = Vec::with_capacity(call_expr.args.len() + 1);
+
+                // a method is called with its receiver as the first argument
+                let function = match &call_expr.callee.kind {
+                    ExprKind::Field(field) => match field.member(env) {
+                        Ok(Member::Field(value)) => Some(value),
+                        Ok(Member::Method(receiver, method)) => {
+                            args.push(receiver);
+                            Some(method)
+                        }
+                        Err(err) => return err,
+                    },
+                    // resolved once the type of the first argument is known
+                    ExprKind::Ident(_) if !call_expr.args.is_empty() => None,
+                    _ => Some(call_expr.callee.eval(env)),
+                };
+
+                if let Some(function) = function.as_ref().filter(|value| value.is_unwinding()) {
+                    return function.clone();
+                }
+
+                for arg in &call_expr.args {
+                    match arg.eval(env) {
+                        value if value.is_unwinding() => return value,
+                        value => args.push(value),
+                    }
+                }
+
+                // `to_string { point }` and `point : to_string` call the method
+                // of the type of `point`, or else whatever `to_string` is bound to
+                let function = match (function, &call_expr.callee.kind) {
+                    (Some(function), _) => function,
+                    (None, ExprKind::Ident(ident)) => {
+                        let method = method_of(env, &call_expr.args[0], &args[0], ident);
+                        let bound = || env.borrow().get(ident);
+
+                        match method.or_else(bound) {
+                            Some(function) => function,
+                            None if env.borrow().is_method(ident) => {
+                                return Value::Error(format!(
+                                    "{} has no method `{}`",
+                                    args[0].type_name(),
+                                    ident
+                                ))
+                            }
+                            None => call_expr.callee.eval(env),
+                        }
+                    }
+                    (None, _) => unreachable!("only identifiers are resolved late"),
+                };
+
+                if function.is_unwinding() {
+                    return function;
+                }
+
+                call(&function, args)
+            }
+            ExprKind::Tuple(exprs) => {
+                let mut values = Vec::with_capacity(exprs.len());
+
+                for expr in exprs {
+                    match expr.eval(env) {
+                        value if value.is_unwinding() => return value,
+                        value => values.push(value),
+                    }
+                }
+
+                Value::Tuple(values)
+            }
+            ExprKind::Match(matched) => {
+                let value = matched.matched.eval(env);
+                if value.is_unwinding() {
+                    return value;
+                }
+
+                for arm in &matched.arms {
+                    let scope = Env::child(env);
+
+                    match arm.pattern.bind(&value, &scope) {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(err) => return err,
+                    }
+
+                    if let Some(guard) = &arm.guard {
+                        match guard.eval(&scope) {
+                            Value::Bool(true) => {}
+                            Value::Bool(false) => continue,
+                            value if value.is_unwinding() => return value,
+                            _ => return Value::Error("guard must be a Bool".to_owned()),
+                        }
+                    }
+
+                    return arm.body.eval(&scope);
+                }
+
+                Value::Error(format!("no arm matches {}", value))
+            }
+            ExprKind::Range(range) => {
+                let from = match range.from.eval(env) {
+                    value if value.is_unwinding() => return value,
+                    value => value,
+                };
+                let to = match range.to.eval(env) {
+                    value if value.is_unwinding() => return value,
+                    value => value,
+                };
+
+                let (from, to) = match (from, to) {
+                    (Value::Int(from), Value::Int(to)) => (from, to),
+                    (from, to) => {
+                        return Value::Error(format!(
+                            "range bounds must be Int, found {} and {}",
+                            from.type_name(),
+                            to.type_name()
+                        ))
+                    }
+                };
+
+                match (range.inclusive, to.checked_add(1)) {
+                    (false, _) => Value::Range(from, to),
+                    (true, Some(to)) => Value::Range(from, to),
+                    (true, None) => Value::Error("integer overflow".to_owned()),
+                }
+            }
+            ExprKind::Struct(literal) => {
+                let constructor = match env.borrow().get(&literal.ident) {
+                    Some(Value::Constructor(constructor)) => constructor,
+                    _ => return Value::Error(format!("`{}` is not a struct", literal.ident)),
+                };
+
+                let mut values = vec![None; constructor.fields.len()];
+
+                for (name, expr) in &literal.fields {
+                    let Some(i) = constructor.field(name) else {
+                        return Value::Error(format!(
+                            "`{}` has no field `{}`",
+                            constructor.ident, name
+                        ));
+                    };
+
+                    match expr.eval(env) {
+                        value if value.is_unwinding() => return value,
+                        value => values[i] = Some(value),
+                    }
+                }
+
+                let mut fields = Vec::with_capacity(values.len());
+
+                for (value, name) in values.into_iter().zip(&constructor.fields) {
+                    match (value, name) {
+                        (Some(value), _) => fields.push(value),
+                        (None, Some(name)) => {
+                            return Value::Error(format!(
+                                "missing field `{}` of `{}`",
+                                name, constructor.ident
+                            ))
+                        }
+                        (None, None) => {
+                            return Value::Error(format!(
+                                "`{}` has positional fields",
+                                constructor.ident
+                            ))
+                        }
+                    }
+                }
+
+                constructor.build(fields)
+            }
+            ExprKind::Field(field) => match field.member(env) {
+                Ok(Member::Field(value)) => value,
+                // methods that only take the receiver are called right away
+                Ok(Member::Method(receiver, method)) => match &method {
+                    Value::Function { args, .. } if args.len() == 1 => {
+                        call(&method, vec![receiver])
+                    }
+                    Value::Builtin { name, .. } if builtins::takes_receiver(name) => {
+                        call(&method, vec![receiver])
+                    }
+                    _ => Value::Method(Box::new(receiver), Box::new(method)),
+                },
+                Err(err) => err,
+            },
+            ExprKind::Try(try_) => try_.eval(env),
+        }
+    }
+}
+
+// what `value.name` refers to
+enum Member {
+    Field(Value),
+    // the receiver and the method
+    Method(Value, Value),
+}
+
+// the method `name` of `value`, which `expr` evaluated to. An argument whose
+// declared type is a bounded type parameter has the methods of the dictionaries
+// passed for it, and other values those of their type.
+fn method_of(env: &Rc<RefCell<Env>>, expr: &Expr, value: &Value, name: &str) -> Option<Value> {
+    if let ExprKind::Ident(ident) = &expr.kind {
+        if let Some(method) = env.borrow().bounded_method(ident, name) {
+            return method;
+        }
+    }
+
+    env.borrow().method(value.type_name(), name)
+}
+
+impl Field {
+    // the field of the target, or else the method of its type
+    fn member(&self, env: &Rc<RefCell<Env>>) -> Result<Member, Value> {
+        let target = self.target.eval(env);
+        if target.is_unwinding() {
+            return Err(target);
+        }
+
+        let found = match (&target, &self.field.kind) {
+            (Value::Struct(constructor, values), ExprKind::Ident(name))
+            | (Value::Variant(constructor, values), ExprKind::Ident(name)) => {
+                constructor.field(name).map(|i| &values[i])
+            }
+            (Value::Struct(_, values), ExprKind::Int(index))
+            | (Value::Variant(_, values), ExprKind::Int(index))
+            | (Value::Tuple(values), ExprKind::Int(index)) => {
+                index.parse().ok().and_then(|i: usize| values.get(i))
+            }
+            _ => None,
+        };
+
+        match (found, &self.field.kind) {
+            (Some(value), _) => Ok(Member::Field(value.clone())),
+            (None, ExprKind::Ident(name)) => match method_of(env, &self.target, &target, name) {
+                Some(method) => Ok(Member::Method(target, method)),
+                None => Err(Value::Error(format!(
+                    "{} has no field or method `{}`",
+                    target.type_name(),
+                    name
+                ))),
+            },
+            (None, ExprKind::Int(name)) => Err(Value::Error(format!(
+                "{} has no field `{}`",
+                target.type_name(),
+                name
+            ))),
+            (None, kind) => Err(Value::Error(format!("cannot access the field {:?}", kind))),
+        }
+    }
+}
+
+impl Eval for Try {
+    fn eval(&self, env: &Rc<RefCell<Env>>) -> Value {
+        let value = self.expr.eval(env);
+
+        let (constructor, mut values) = match value {
+            value if value.is_unwinding() => return value,
+            Value::Variant(constructor, values) => (constructor, values),
+            value => {
+                return Value::Error(format!(
+                    "`?` expects an Option or a Result, found {}",
+                    value.type_name()
+                ))
+            }
+        };
+
+        match (constructor.ty.as_str(), constructor.ident.as_str()) {
+            ("Option", "Some") | ("Result", "Ok") if values.len() == 1 => values.remove(0),
+            ("Option", "None") | ("Result", "Err") => match &self.default {
+                Some(default) => match default.eval(env) {
+                    value if value.is_unwinding() => value,
+                    value => Value::Return(Box::new(value)),
+                },
+                None => Value::Return(Box::new(Value::Variant(constructor, values))),
+            },
+            (ty, _) => Value::Error(format!("`?` expects an Option or a Result, found {}", ty)),
+        }
     }
 }
 
 impl Eval for Stmt {
-    fn eval(&self, env: &Env) -> Value {
-        todo!()
+    fn eval(&self, env: &Rc<RefCell<Env>>) -> Value {
+        match &self.kind {
+            StmtKind::Expr(expr) => expr.eval(env),
+            StmtKind::Binding(ident, expr) => {
+                let value = expr.eval(env);
+                if value.is_unwinding() {
+                    return value;
+                }
+                env.borrow_mut().define(ident.clone(), value);
+                Value::Unit
+            }
+            StmtKind::Assign(ident, operator, expr) => {
+                let current = match env.borrow().get(ident) {
+                    Some(value) => value,
+                    None => return Value::Error(format!("unknown identifier `{}`", ident)),
+                };
+
+                let value = match expr.eval(env) {
+                    value if value.is_unwinding() => return value,
+                    value => eval_binary(operator, current, value),
+                };
+                if value.is_unwinding() {
+                    return value;
+                }
+
+                match env.borrow_mut().assign(ident, value) {
+                    Ok(()) => Value::Unit,
+                    Err(err) => Value::Error(err),
+                }
+            }
+            StmtKind::Signature(signature) => signature.define(env),
+            StmtKind::Return(expr) => match expr.eval(env) {
+                value if value.is_unwinding() => value,
+                value => Value::Return(Box::new(value)),
+            },
+            StmtKind::Break(label, value) => {
+                let value = match value {
+                    Some(value) => value.eval(env),
+                    None => Value::Unit,
+                };
+
+                match value {
+                    err if err.is_unwinding() => err,
+                    value => Value::Break(label.clone(), Box::new(value)),
+                }
+            }
+            StmtKind::Continue(label) => Value::Continue(label.clone()),
+            StmtKind::TypeDecl(decl) => decl.define(&mut env.borrow_mut()),
+            StmtKind::Trait(declared) => declared.define(env),
+            StmtKind::Impl(implemented) => implemented.define(env),
+            StmtKind::Error => {
+                Value::Error("cannot evaluate a statement with a syntax error".to_owned())
+            }
+        }
+    }
+}
+
+// The value a loop stops with after running its body once, if it stops: that
+// of a `break` out of it, or anything else that unwinds past it. A loop
+// without a label only handles the `break`s and `continue`s without one.
+fn exit(label: &Option<String>, body: Value) -> Option<Value> {
+    let targets = |target: &Option<String>| target.is_none() || target == label;
+
+    match body {
+        Value::Break(target, value) if targets(&target) => Some(*value),
+        Value::Continue(target) if targets(&target) => None,
+        value if value.is_unwinding() => Some(value),
+        _ => None,
+    }
+}
+
+impl Pattern {
+    // whether `value` matches, binding the names in the pattern in `env` if so
+    pub fn bind(&self, value: &Value, env: &Rc<RefCell<Env>>) -> Result<bool, Value> {
+        match &self.kind {
+            PatternKind::Wildcard => Ok(true),
+            PatternKind::Binding(ident) => {
+                env.borrow_mut().define(ident.clone(), value.clone());
+                Ok(true)
+            }
+            PatternKind::Literal(literal) => {
+                compare(Operator::Equal, value.clone(), literal.eval(env))
+            }
+            PatternKind::Range(range) => {
+                let above = compare(Operator::GreaterEqual, value.clone(), range.from.eval(env))?;
+                let below = match range.inclusive {
+                    true => compare(Operator::LessEqual, value.clone(), range.to.eval(env))?,
+                    false => compare(Operator::LessThan, value.clone(), range.to.eval(env))?,
+                };
+                Ok(above && below)
+            }
+            PatternKind::Tuple(patterns) => match value {
+                Value::Tuple(values) if values.len() == patterns.len() => {
+                    for (pattern, value) in patterns.iter().zip(values) {
+                        if !pattern.bind(value, env)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            PatternKind::Constructor(ident, fields) => {
+                let expected = match env.borrow().get(ident) {
+                    Some(Value::Constructor(constructor))
+                    | Some(Value::Variant(constructor, _)) => constructor,
+                    _ => return Err(Value::Error(format!("`{}` is not a constructor", ident))),
+                };
+
+                let values = match value {
+                    Value::Struct(constructor, values) | Value::Variant(constructor, values)
+                        if *constructor == expected =>
+                    {
+                        values
+                    }
+                    _ => return Ok(false),
+                };
+
+                if fields.len() > values.len() {
+                    return Err(Value::Error(format!(
+                        "`{}` has {} fields, found {}",
+                        ident,
+                        values.len(),
+                        fields.len()
+                    )));
+                }
+
+                for (i, field) in fields.iter().enumerate() {
+                    let i = match &field.name {
+                        Some(name) => match expected.field(name) {
+                            Some(i) => i,
+                            None => {
+                                return Err(Value::Error(format!(
+                                    "`{}` has no field `{}`",
+                                    ident, name
+                                )))
+                            }
+                        },
+                        None => i,
+                    };
+
+                    if !field.pattern.bind(&values[i], env)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
+            PatternKind::Or(patterns) => {
+                for pattern in patterns {
+                    if pattern.bind(value, env)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            PatternKind::At(ident, pattern) => {
+                let matched = pattern.bind(value, env)?;
+                if matched {
+                    env.borrow_mut().define(ident.clone(), value.clone());
+                }
+                Ok(matched)
+            }
+        }
+    }
+}
+
+impl Signature {
+    // evaluate the binding the signature is attached to. A function whose
+    // type parameters have bounds is passed their dictionaries when called.
+    fn define(&self, env: &Rc<RefCell<Env>>) -> Value {
+        let Some(binding) = &self.binding else {
+            return Value::Unit;
+        };
+
+        let value = binding.eval(env);
+        let bounds = self.bounds();
+
+        let Type::Function(types, _) = &self.ty else {
+            return value;
+        };
+        if value.is_unwinding() || bounds.is_empty() {
+            return value;
+        }
+
+        let function = env.borrow().get(&self.ident);
+        if let Some(function @ Value::Function { .. }) = function {
+            let generic = Value::Generic {
+                bounds: Rc::new(bounds),
+                types: Rc::new(types.clone()),
+                function: Box::new(function),
+            };
+            env.borrow_mut().define(self.ident.clone(), generic);
+        }
+
+        value
+    }
+
+    // the traits bounding each type parameter
+    fn bounds(&self) -> Vec<Bound> {
+        let mut bounds = Vec::new();
+
+        for generic in &self.generics {
+            for bound in &generic.bounds {
+                if let Type::NewType(trait_, _) = bound {
+                    bounds.push(Bound {
+                        param: generic.ident.clone(),
+                        trait_: trait_.clone(),
+                    });
+                }
+            }
+        }
+
+        bounds
+    }
+}
+
+impl TypeDecl {
+    // bind the constructors of the type in `env`, and implement the traits it
+    // derives. Variants without fields are values of their own.
+    fn define(&self, env: &mut Env) -> Value {
+        let constructors = match &self.kind {
+            TypeDeclKind::Struct(fields) => vec![(&self.ident, fields, false)],
+            TypeDeclKind::Enum(variants) => variants
+                .iter()
+                .map(|variant| (&variant.ident, &variant.fields, true))
+                .collect(),
+        };
+
+        let generics: Vec<_> = self
+            .generics
+            .iter()
+            .map(|generic| generic.ident.clone())
+            .collect();
+
+        for (index, (ident, fields, variant)) in constructors.into_iter().enumerate() {
+            let constructor = Rc::new(Constructor {
+                ty: self.ident.clone(),
+                ident: ident.clone(),
+                fields: fields.iter().map(|field| field.name.clone()).collect(),
+                types: fields.iter().map(|field| field.ty.clone()).collect(),
+                generics: generics.clone(),
+                variant,
+                index,
+            });
+
+            let value = match variant && fields.is_empty() {
+                true => constructor.build(Vec::new()),
+                false => Value::Constructor(constructor),
+            };
+            env.define(ident.clone(), value);
+        }
+
+        for derived in &self.derives {
+            if !builtins::derive(env, derived, &self.ident) {
+                return Value::Error(format!("`{}` cannot be derived", derived));
+            }
+        }
+
+        Value::Unit
+    }
+}
+
+impl Trait {
+    // record the methods of the trait, evaluating the defaults
+    fn define(&self, env: &Rc<RefCell<Env>>) -> Value {
+        let mut methods = Vec::with_capacity(self.items.len());
+
+        for (ident, body) in self.items.iter().filter_map(method) {
+            let default = match body.map(|body| body.eval(env)) {
+                Some(value) if value.is_unwinding() => return value,
+                default => default,
+            };
+            methods.push((ident.to_owned(), default));
+        }
+
+        env.borrow_mut().define_trait(self.ident.clone(), methods);
+        Value::Unit
+    }
+}
+
+impl Impl {
+    // add the methods to those of the type, checking them against the trait
+    // and filling in its defaults
+    fn define(&self, env: &Rc<RefCell<Env>>) -> Value {
+        let Some(ty) = self.ty.type_name() else {
+            return Value::Error(format!("cannot implement methods for `{}`", self.ty));
+        };
+
+        let mut methods = Vec::with_capacity(self.items.len());
+
+        for (ident, body) in self.items.iter().filter_map(method) {
+            let Some(body) = body else { continue };
+
+            match body.eval(env) {
+                value if value.is_unwinding() => return value,
+                value => methods.push((ident.to_owned(), value)),
+            }
+        }
+
+        if let Some(trait_) = &self.trait_ {
+            let Some(name) = trait_.type_name() else {
+                return Value::Error(format!("`{}` is not a trait", trait_));
+            };
+            let Some(declared) = env.borrow().trait_methods(name) else {
+                return Value::Error(format!("unknown trait `{}`", name));
+            };
+
+            if env.borrow().implements(name, ty) {
+                return Value::Error(format!(
+                    "`{}` is already implemented for `{}`",
+                    name, self.ty
+                ));
+            }
+
+            if let Some((ident, _)) = methods
+                .iter()
+                .find(|(ident, _)| !declared.iter().any(|(method, _)| method == ident))
+            {
+                return Value::Error(format!("`{}` is not a method of `{}`", ident, name));
+            }
+
+            for (ident, default) in declared {
+                if methods.iter().any(|(method, _)| *method == ident) {
+                    continue;
+                }
+
+                match default {
+                    Some(default) => methods.push((ident, default)),
+                    None => {
+                        return Value::Error(format!(
+                            "missing method `{}` in the impl of `{}` for `{}`",
+                            ident, name, self.ty
+                        ))
+                    }
+                }
+            }
+
+            env.borrow_mut().implement(name, ty);
+        }
+
+        for (ident, value) in methods {
+            env.borrow_mut().define_method(ty, ident, value);
+        }
+
+        Value::Unit
+    }
+}
+
+// the name of a method of a trait or impl, and its body unless it is required
+fn method(item: &Stmt) -> Option<(&str, Option<&Expr>)> {
+    match &item.kind {
+        StmtKind::Binding(ident, expr) => Some((ident, Some(expr))),
+        StmtKind::Signature(signature) => match &signature.binding {
+            Some(binding) => method(binding),
+            None => Some((&signature.ident, None)),
+        },
+        _ => None,
+    }
+}
+
+// compare a value with a bound of a pattern
+fn compare(operator: Operator, left: Value, right: Value) -> Result<bool, Value> {
+    match eval_binary(&operator, left, right) {
+        Value::Bool(result) => Ok(result),
+        err => Err(err),
     }
 }
 
 impl Eval for Vec<Stmt> {
-    fn eval(&self, env: &Env) -> Value {
-        todo!()
+    fn eval(&self, env: &Rc<RefCell<Env>>) -> Value {
+        let mut result = Value::Unit;
+
+        for stmt in self {
+            result = stmt.eval(env);
+
+            if result.is_unwinding() {
+                return result;
+            }
+        }
+
+        result
     }
 }
 
 impl Eval for Ast {
-    fn eval(&self, env: &Env) -> Value {
-        todo!()
+    fn eval(&self, env: &Rc<RefCell<Env>>) -> Value {
+        returned(self.stmts.eval(env))
     }
 }
@@ -0,0 +1,243 @@
+use super::Span;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// An error or warning about some part of the source, rendered like rustc does:
+//
+// Syntax Error: expected SEMICOLON [syntax-error]
+//  --> main.mo:1:7
+//   |
+// 1 | x = 1 y = 2;
+//   |       ^ found `y`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // a kebab case name for the kind of problem: `syntax-error`
+    pub code: &'static str,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, code, message.into(), span)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Warning, code, message.into(), span)
+    }
+
+    fn new(severity: Severity, code: &'static str, message: String, span: Span) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            primary: Label {
+                span,
+                message: String::new(),
+            },
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    // the message under the primary span
+    pub fn with_label(mut self, message: impl Into<String>) -> Self {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    // the header, followed by the labelled lines of `source` and the notes
+    pub fn render(&self, name: &str, source: &str) -> String {
+        let lines = Lines::new(source);
+
+        let mut labels: Vec<_> = std::iter::once((&self.primary, '^'))
+            .chain(self.secondary.iter().map(|label| (label, '-')))
+            .map(|(label, marker)| (lines.position(label.span.start), label, marker))
+            .collect();
+        labels.sort_by_key(|((line, column), _, _)| (*line, *column));
+
+        let (line, column) = lines.position(self.primary.span.start);
+        let last_line = labels.iter().map(|((line, _), _, _)| *line).max();
+        let width = last_line.unwrap_or(line).to_string().len();
+        let gutter = " ".repeat(width);
+
+        let mut out = format!("{}\n", self);
+        out += &format!("{}--> {}:{}:{}\n", gutter, name, line, column);
+        out += &format!("{} |\n", gutter);
+
+        let mut previous = None;
+
+        for ((line, column), label, marker) in labels {
+            let text = lines.text(line);
+
+            if previous != Some(line) {
+                if previous.is_some_and(|previous| line > previous + 1) {
+                    out += "...\n";
+                }
+                out += &format!("{:>width$} | {}\n", line, text, width = width);
+                previous = Some(line);
+            }
+
+            // underline up to the end of the first line of the span
+            let line_end = lines.offset(line) + text.len();
+            let start = label.span.start.min(line_end);
+            let end = label.span.end.clamp(start, line_end);
+            let len = source[start..end].chars().count().max(1);
+
+            let underline = format!(
+                "{}{}",
+                " ".repeat(column - 1),
+                marker.to_string().repeat(len)
+            );
+
+            match label.message.as_str() {
+                "" => out += &format!("{} | {}\n", gutter, underline),
+                message => out += &format!("{} | {} {}\n", gutter, underline, message),
+            }
+        }
+
+        if !self.notes.is_empty() {
+            out += &format!("{} |\n", gutter);
+        }
+
+        for note in &self.notes {
+            out += &format!("{} = note: {}\n", gutter, note);
+        }
+
+        out
+    }
+}
+
+// `Syntax Error: expected SEMICOLON [syntax-error]`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => {
+                // `syntax-error` is titled `Syntax Error`
+                for (i, word) in self.code.split('-').enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+
+                    let mut chars = word.chars();
+                    if let Some(first) = chars.next() {
+                        write!(f, "{}{}", first.to_ascii_uppercase(), chars.as_str())?;
+                    }
+                }
+            }
+            Severity::Warning => write!(f, "Warning")?,
+        }
+
+        write!(f, ": {} [{}]", self.message, self.code)
+    }
+}
+
+// the byte offset where each line of the source starts
+struct Lines<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Lines { source, starts }
+    }
+
+    // the line and column of `offset`, both starting at 1, with the column in chars
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = self.starts.partition_point(|start| *start <= offset);
+        let column = self.source[self.starts[line - 1]..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    fn offset(&self, line: usize) -> usize {
+        self.starts[line - 1]
+    }
+
+    fn text(&self, line: usize) -> &'a str {
+        let end = self
+            .starts
+            .get(line)
+            .map_or(self.source.len(), |next| next - 1);
+        self.source[self.offset(line)..end].trim_end_matches('\r')
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels_and_notes() {
+        let source = "add | Int, Int -> Int;\n\nadd = a, b -> a == b;\n";
+        let diagnostic = Diagnostic::error("type-error", "mismatched types", Span::new(30, 44))
+            .with_label("this returns `Bool`")
+            .with_secondary(Span::new(18, 21), "expected `Int` because of this")
+            .with_note("`==` always returns `Bool`");
+
+        assert_eq!(
+            diagnostic.render("main.mo", source),
+            "\
+Type Error: mismatched types [type-error]
+ --> main.mo:3:7
+  |
+1 | add | Int, Int -> Int;
+  |                   --- expected `Int` because of this
+...
+3 | add = a, b -> a == b;
+  |       ^^^^^^^^^^^^^^ this returns `Bool`
+  |
+  = note: `==` always returns `Bool`
+"
+        );
+    }
+
+    #[test]
+    fn warnings_and_multibyte_columns() {
+        let source = "é = \"ünused\";";
+        let diagnostic = Diagnostic::warning("unused-binding", "unused binding", Span::new(0, 2));
+
+        assert_eq!(
+            diagnostic.render("main.mo", source),
+            "\
+Warning: unused binding [unused-binding]
+ --> main.mo:1:1
+  |
+1 | é = \"ünused\";
+  | ^
+"
+        );
+    }
+}
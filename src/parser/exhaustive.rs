@@ -0,0 +1,284 @@
+// Exhaustiveness and reachability of `match` arms, after Maranget's
+// "Warnings for pattern matching": an arm is unreachable if its pattern is
+// not useful after the arms above it, and a match is exhaustive if `_` would
+// not be useful after all of them.
+//
+// Literals other than `Bool` and `()` never form a complete signature, so a
+// match on numbers or strings needs a catch-all arm. The variants of an enum
+// form one once they are all named.
+
+use super::flat_ast::{Ast, Expression, ExpressionRef, FieldDecl, TypeDecl};
+use super::{parse_int, unescape, Operator};
+use std::collections::HashMap;
+
+// the declaration of the type of each constructor
+pub(crate) type Constructors<'d, 'a> = HashMap<&'a str, &'d TypeDecl<'a>>;
+
+pub(crate) struct MatchCheck {
+    pub exhaustive: bool,
+    // the arms that no value can reach
+    pub unreachable: Vec<ExpressionRef>,
+}
+
+// `arms` are `Arm` nodes. Guarded arms can be unreachable, but don't cover
+// anything for the arms below them.
+pub(crate) fn check_match(
+    ast: &Ast<'_>,
+    constructors: &Constructors<'_, '_>,
+    arms: &[ExpressionRef],
+) -> MatchCheck {
+    let mut rows: Vec<Vec<Pat>> = Vec::new();
+    let mut unreachable = Vec::new();
+
+    for arm in arms {
+        let Expression::Arm { pattern, guard, .. } = ast.get_expression(arm.root()) else {
+            continue;
+        };
+
+        let row = vec![Pat::lower(ast, constructors, *pattern)];
+
+        if !useful(constructors, &rows, &row) {
+            unreachable.push(*arm);
+        }
+
+        if guard.is_none() {
+            rows.push(row);
+        }
+    }
+
+    MatchCheck {
+        exhaustive: !useful(constructors, &rows, &[Pat::Wild]),
+        unreachable,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+    Or(Vec<Pat>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Ctor {
+    Unit,
+    Bool(bool),
+    Tuple(usize),
+    // ints and chars, both bounds included
+    Range(i128, i128),
+    // any other literal, by its text
+    Literal(String),
+    Variant(String, usize),
+}
+
+impl Ctor {
+    fn arity(&self) -> usize {
+        match self {
+            Ctor::Tuple(arity) | Ctor::Variant(_, arity) => *arity,
+            _ => 0,
+        }
+    }
+
+    // whether every value matching `other` matches `self`
+    fn covers(&self, other: &Ctor) -> bool {
+        match (self, other) {
+            (Ctor::Range(from, to), Ctor::Range(other_from, other_to)) => {
+                from <= other_from && other_to <= to
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl Pat {
+    fn lower(ast: &Ast<'_>, constructors: &Constructors<'_, '_>, pattern: ExpressionRef) -> Pat {
+        let ctor = |ctor| Pat::Ctor(ctor, Vec::new());
+        let lower = |pattern| Pat::lower(ast, constructors, pattern);
+        let lower_all = |run| ast.children(run).into_iter().map(lower).collect::<Vec<_>>();
+
+        match ast.get_expression(pattern.root()) {
+            Expression::Unit => ctor(Ctor::Unit),
+            Expression::Bool(literal) => ctor(Ctor::Bool(*literal == "true")),
+            Expression::Tuple(patterns) => {
+                let patterns = lower_all(*patterns);
+                Pat::Ctor(Ctor::Tuple(patterns.len()), patterns)
+            }
+            Expression::Constructor { ident, fields } => {
+                let declared = declared_fields(constructors, ident);
+                let fields = ast.children(*fields);
+                let mut pats = vec![Pat::Wild; declared.map_or(fields.len(), <[_]>::len)];
+
+                // named fields go where they are declared, and the fields
+                // left out match anything
+                for (i, field) in fields.into_iter().enumerate() {
+                    let (i, field) = match ast.get_expression(field.root()) {
+                        Expression::NamedField { ident, value } => {
+                            let position = declared.and_then(|declared| {
+                                declared.iter().position(|field| field.name == Some(*ident))
+                            });
+                            (position.unwrap_or(i), *value)
+                        }
+                        _ => (i, field),
+                    };
+
+                    if let Some(pat) = pats.get_mut(i) {
+                        *pat = lower(field);
+                    }
+                }
+
+                Pat::Ctor(Ctor::Variant(ident.to_string(), pats.len()), pats)
+            }
+            Expression::Or(patterns) => Pat::Or(lower_all(*patterns)),
+            Expression::At { pattern, .. } => lower(*pattern),
+            Expression::Range {
+                from,
+                to,
+                inclusive,
+            } => match (bound(ast, *from), bound(ast, *to)) {
+                (Some(from), Some(to)) if *inclusive => ctor(Ctor::Range(from, to)),
+                (Some(from), Some(to)) => ctor(Ctor::Range(from, to - 1)),
+                _ => ctor(Ctor::Literal(format!("{:?}", pattern))),
+            },
+            Expression::Int(_) | Expression::Char(_) | Expression::Prefix { .. } => {
+                match bound(ast, pattern) {
+                    Some(value) => ctor(Ctor::Range(value, value)),
+                    None => ctor(Ctor::Literal(format!("{:?}", pattern))),
+                }
+            }
+            Expression::Float(literal) | Expression::Str(literal) | Expression::RawStr(literal) => {
+                ctor(Ctor::Literal(literal.to_string()))
+            }
+            _ => Pat::Wild,
+        }
+    }
+
+    // the alternatives of an or-pattern, or the pattern itself
+    fn alternatives(&self) -> Vec<Pat> {
+        match self {
+            Pat::Or(patterns) => patterns.iter().flat_map(Pat::alternatives).collect(),
+            pattern => vec![pattern.clone()],
+        }
+    }
+}
+
+// the fields of a declared constructor
+fn declared_fields<'d, 'a>(
+    constructors: &Constructors<'d, 'a>,
+    ident: &str,
+) -> Option<&'d [FieldDecl<'a>]> {
+    let decl: &'d TypeDecl<'a> = constructors.get(ident)?;
+    decl.constructors()
+        .into_iter()
+        .find(|(constructor, _)| *constructor == ident)
+        .map(|(_, fields)| fields)
+}
+
+// the value of an int or char literal, possibly negated
+fn bound(ast: &Ast<'_>, literal: ExpressionRef) -> Option<i128> {
+    match ast.get_expression(literal.root()) {
+        Expression::Int(literal) => parse_int(literal).ok().map(i128::from),
+        Expression::Char(literal) => unescape(literal).chars().next().map(|c| c as i128),
+        Expression::Prefix {
+            operator: Operator::Minus,
+            operand,
+        } => bound(ast, *operand).map(|value| -value),
+        _ => None,
+    }
+}
+
+// whether some value matches `row` but none of the rows of `matrix`
+fn useful(constructors: &Constructors<'_, '_>, matrix: &[Vec<Pat>], row: &[Pat]) -> bool {
+    let Some((head, rest)) = row.split_first() else {
+        return matrix.is_empty();
+    };
+
+    // rows starting with an or-pattern are split into one row per alternative
+    let matrix: Vec<Vec<Pat>> = matrix
+        .iter()
+        .flat_map(|row| {
+            row[0].alternatives().into_iter().map(|head| {
+                let mut row = row.clone();
+                row[0] = head;
+                row
+            })
+        })
+        .collect();
+
+    match head {
+        Pat::Or(alternatives) => alternatives.iter().any(|alternative| {
+            let mut row = vec![alternative.clone()];
+            row.extend_from_slice(rest);
+            useful(constructors, &matrix, &row)
+        }),
+        Pat::Ctor(ctor, fields) => {
+            let mut row = fields.clone();
+            row.extend_from_slice(rest);
+            useful(constructors, &specialize(&matrix, ctor), &row)
+        }
+        Pat::Wild => match complete_signature(constructors, &matrix) {
+            Some(ctors) => ctors.iter().any(|ctor| {
+                let mut row = vec![Pat::Wild; ctor.arity()];
+                row.extend_from_slice(rest);
+                useful(constructors, &specialize(&matrix, ctor), &row)
+            }),
+            None => {
+                let default: Vec<_> = matrix
+                    .iter()
+                    .filter(|row| row[0] == Pat::Wild)
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+                useful(constructors, &default, rest)
+            }
+        },
+    }
+}
+
+// the rows that match a value built with `ctor`, with its fields in place of
+// the first column
+fn specialize(matrix: &[Vec<Pat>], ctor: &Ctor) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter_map(|row| {
+            let mut specialized = match &row[0] {
+                Pat::Wild => vec![Pat::Wild; ctor.arity()],
+                Pat::Ctor(head, fields) if head.covers(ctor) => fields.clone(),
+                _ => return None,
+            };
+            specialized.extend_from_slice(&row[1..]);
+            Some(specialized)
+        })
+        .collect()
+}
+
+// every constructor of the type of the first column, if the column names
+// enough of them to know the type and the type has finitely many
+fn complete_signature(
+    constructors: &Constructors<'_, '_>,
+    matrix: &[Vec<Pat>],
+) -> Option<Vec<Ctor>> {
+    let heads: Vec<&Ctor> = matrix
+        .iter()
+        .filter_map(|row| match &row[0] {
+            Pat::Ctor(ctor, _) => Some(ctor),
+            _ => None,
+        })
+        .collect();
+
+    let signature = match heads.first()? {
+        Ctor::Unit => vec![Ctor::Unit],
+        Ctor::Bool(_) => vec![Ctor::Bool(true), Ctor::Bool(false)],
+        Ctor::Tuple(arity) => vec![Ctor::Tuple(*arity)],
+        Ctor::Variant(ident, _) => constructors
+            .get(ident.as_str())?
+            .constructors()
+            .into_iter()
+            .map(|(ident, fields)| Ctor::Variant(ident.to_owned(), fields.len()))
+            .collect(),
+        _ => return None,
+    };
+
+    match signature.iter().all(|ctor| heads.contains(&ctor)) {
+        true => Some(signature),
+        false => None,
+    }
+}
@@ -1,7 +1,13 @@
-use crate::List;
+use super::ast::{self, Operator, Trivia};
+use super::infer::{self, TypeError};
+use super::{FlatParser, List, Severity, Span};
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 
-#[derive(Debug)]
+// Expressions are stored in post-order: the nodes of a subtree are contiguous
+// and the root of the subtree is always its last node.
+#[derive(Debug, PartialEq)]
 pub enum Expression<'a> {
     Unit,
     Dash,
@@ -10,10 +16,8 @@ pub enum Expression<'a> {
     Bool(&'a str),
     Char(&'a str),
     Str(&'a str),
+    RawStr(&'a str),
     Ident(&'a str),
-    Array(&'a str),
-    Map(&'a str),
-    Index(ExpressionRef), // expr[expr]
     Prefix {
         operator: Operator,
         operand: ExpressionRef,
@@ -28,98 +32,333 @@ pub enum Expression<'a> {
         consequent: ExpressionRef,
         alternative: Option<ExpressionRef>,
     },
+    // `arms` is a run of `Arm`s
     Match {
         matched: ExpressionRef,
         arms: ExpressionRef,
     },
+    // `pattern if guard => body`
+    Arm {
+        pattern: ExpressionRef,
+        guard: Option<ExpressionRef>,
+        body: ExpressionRef,
+    },
+    // `args` is a run of `Arg`s
     Function {
         args: ExpressionRef,
         body: ExpressionRef,
     },
-    Tuple,
-    Block,
-    Path,
-    Field,
-    Closure,
-    Method,
-    Range,
-    Macro,
+    // `args` is a run of argument subtrees
+    Call {
+        callee: ExpressionRef,
+        args: ExpressionRef,
+    },
+    Arg {
+        ident: &'a str,
+        mutable: bool,
+    },
+    // `value : call`, where `call` is missing its first argument
+    Pipeline {
+        value: ExpressionRef,
+        call: ExpressionRef,
+    },
+    Binding {
+        ident: &'a str,
+        value: ExpressionRef,
+    },
+    Assign {
+        ident: &'a str,
+        operator: Operator,
+        value: ExpressionRef,
+    },
+    Return(ExpressionRef),
+    Loop {
+        label: Option<&'a str>,
+        body: ExpressionRef,
+    },
+    // `pattern` is only there in `while pattern = condition { body }`
+    While {
+        label: Option<&'a str>,
+        pattern: Option<ExpressionRef>,
+        condition: ExpressionRef,
+        body: ExpressionRef,
+    },
+    For {
+        label: Option<&'a str>,
+        pattern: ExpressionRef,
+        iterable: ExpressionRef,
+        body: ExpressionRef,
+    },
+    Break {
+        label: Option<&'a str>,
+        value: Option<ExpressionRef>,
+    },
+    Continue(Option<&'a str>),
+    // a pattern like `Some { x }`, where `fields` is a run of patterns and
+    // `NamedField`s. The other patterns are `Dash`, `Ident` for the names
+    // they bind, literals, `Range`s of literals, `Tuple`s, `Or` and `At`.
+    Constructor {
+        ident: &'a str,
+        fields: ExpressionRef,
+    },
+    // `x = pattern` in a constructor pattern, or `x: value` in a `Struct`
+    NamedField {
+        ident: &'a str,
+        value: ExpressionRef,
+    },
+    // `Point { x: 0, y: 0 }`, where `fields` is a run of `NamedField`s
+    Struct {
+        ident: &'a str,
+        fields: ExpressionRef,
+    },
+    // `target.x` or `target.0`, where `field` is an `Ident` or an `Int`
+    Field {
+        target: ExpressionRef,
+        field: ExpressionRef,
+    },
+    // `expr?` or `expr.try`, or `expr ? default`
+    Try {
+        expr: ExpressionRef,
+        default: Option<ExpressionRef>,
+    },
+    TypeDecl(Box<TypeDecl<'a>>),
+    Trait(Box<Trait<'a>>),
+    Impl(Box<Impl<'a>>),
+    // `1 | 2`, where the run holds the alternatives
+    Or(ExpressionRef),
+    // `n @ pattern`
+    At {
+        ident: &'a str,
+        pattern: ExpressionRef,
+    },
+    // `binding` is the `Binding` annotated by the signature, if any
+    Signature {
+        ident: &'a str,
+        generics: Vec<Generic<'a>>,
+        ty: TypeRef,
+        binding: Option<ExpressionRef>,
+    },
+    // a run of element subtrees
+    Tuple(ExpressionRef),
+    // a run of statement subtrees
+    Block(ExpressionRef),
+    Range {
+        from: ExpressionRef,
+        to: ExpressionRef,
+        inclusive: bool,
+    },
+    // a statement with a syntax error, which owns the nodes parsed before the
+    // error was found
+    Error(ExpressionRef),
+}
+
+impl Expression<'_> {
+    // the first node of the subtree rooted at this expression, which sits at `root`
+    fn start(&self, root: usize) -> usize {
+        let first = match self {
+            Expression::Return(expr)
+            | Expression::Block(expr)
+            | Expression::Tuple(expr)
+            | Expression::Or(expr) => Some(*expr),
+            Expression::Prefix { operand, .. } => Some(*operand),
+            Expression::Infix { left, .. } => Some(*left),
+            Expression::If { condition, .. } => Some(*condition),
+            Expression::Match { matched, .. } => Some(*matched),
+            Expression::Arm { pattern, .. } | Expression::At { pattern, .. } => Some(*pattern),
+            Expression::NamedField { value, .. } => Some(*value),
+            Expression::Struct { fields, .. } => Some(*fields),
+            Expression::Field { target, .. } => Some(*target),
+            Expression::Try { expr, .. } => Some(*expr),
+            Expression::Function { args, body } => Some(if args.len > 0 { *args } else { *body }),
+            Expression::Call { callee, .. } => Some(*callee),
+            Expression::Pipeline { value, .. } => Some(*value),
+            Expression::Binding { value, .. } | Expression::Assign { value, .. } => Some(*value),
+            Expression::Signature { binding, .. } => *binding,
+            Expression::Range { from, .. } => Some(*from),
+            Expression::Loop { body, .. } => Some(*body),
+            Expression::While {
+                pattern, condition, ..
+            } => Some(pattern.unwrap_or(*condition)),
+            Expression::For { pattern, .. } => Some(*pattern),
+            Expression::Break { value, .. } => *value,
+            Expression::Constructor { fields, .. } => Some(*fields),
+            Expression::Trait(declared) => Some(declared.items),
+            Expression::Impl(implemented) => Some(implemented.items),
+            Expression::Error(skipped) => Some(*skipped),
+            _ => None,
+        };
+
+        match first {
+            Some(first) if first.len > 0 => first.index,
+            _ => root,
+        }
+    }
 }
 
+// A run of `len` nodes starting at `index`. For a single expression this is
+// the whole subtree, whose root is the last node of the run.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct ExpressionRef {
     pub index: usize,
     pub len: usize,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl ExpressionRef {
+    pub fn root(&self) -> usize {
+        self.index + self.len - 1
+    }
+}
+
+// A top level statement and the subtree of its root expression
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct StatementRef {
     pub index: usize,
     pub len: usize,
     pub kind: StatementKind,
 }
 
-#[derive(Debug)]
-pub struct Statement<'a> {
-    pub kind: StatementKind,
-    pub exprs: &'a [Expression<'a>],
+impl StatementRef {
+    pub fn expr(&self) -> ExpressionRef {
+        ExpressionRef {
+            index: self.index,
+            len: self.len,
+        }
+    }
+}
+
+// `Point<T> :: { x: T, y: T }` or `Option :: T -> | Some { T } | None`
+#[derive(Debug, PartialEq)]
+pub struct TypeDecl<'a> {
+    pub ident: &'a str,
+    pub generics: Vec<Generic<'a>>,
+    pub kind: TypeDeclKind<'a>,
+    pub derives: Vec<&'a str>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TypeDeclKind<'a> {
+    Struct(Vec<FieldDecl<'a>>),
+    Enum(Vec<Variant<'a>>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Variant<'a> {
+    pub ident: &'a str,
+    pub fields: Vec<FieldDecl<'a>>,
+}
+
+// a field of a struct or variant, which is positional unless it is named
+#[derive(Debug, PartialEq)]
+pub struct FieldDecl<'a> {
+    pub name: Option<&'a str>,
+    pub ty: TypeRef,
+}
+
+impl<'a> TypeDecl<'a> {
+    // the variants of an enum, or the struct itself, which is its only
+    // constructor, with their fields
+    pub fn constructors(&self) -> Vec<(&'a str, &[FieldDecl<'a>])> {
+        match &self.kind {
+            TypeDeclKind::Struct(fields) => vec![(self.ident, fields.as_slice())],
+            TypeDeclKind::Enum(variants) => variants
+                .iter()
+                .map(|variant| (variant.ident, variant.fields.as_slice()))
+                .collect(),
+        }
+    }
+}
+
+// `trait ToString = { to_string | Self -> String; }`, where `items` is a run of
+// `Signature`s and `Binding`s, and the signatures without a binding are the
+// methods an impl must define
+#[derive(Debug, PartialEq)]
+pub struct Trait<'a> {
+    pub ident: &'a str,
+    pub generics: Vec<Generic<'a>>,
+    pub items: ExpressionRef,
+}
+
+// `impl<T> Format for Node<T> where T: Format { ... }`, or `impl Point { ... }`
+// without a trait
+#[derive(Debug, PartialEq)]
+pub struct Impl<'a> {
+    pub generics: Vec<Generic<'a>>,
+    pub trait_: Option<TypeRef>,
+    pub ty: TypeRef,
+    pub predicates: Vec<Generic<'a>>,
+    pub items: ExpressionRef,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum StatementKind {
     Expr,
     Binding,
+    Assign,
     Return,
-    For,
+    Break,
+    Continue,
+    Signature,
+    TypeDecl,
+    Trait,
+    Impl,
     Call,
     Scope,
-    Spawn,
+    Error,
 }
 
-#[derive(Debug)]
-pub enum Operator {
-    Plus,
-    Minus,
-    Multiply,
-    Divide,
-    Modulo,
-    Power,
-    Equal,
-    NotEqual,
-    LessThan,
-    GreaterThan,
-    LessEqual,
-    GreaterEqual,
-    And,
-    Or,
-    Not,
-}
-
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct TypeRef {
-    index: usize,
-    len: usize,
+    pub index: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Type<'a> {
     Unit,
+    Never,
     Bool,
     Int,
     Float,
     Char,
     String,
     Tuple(Vec<TypeRef>),
+    // the arguments are always a `Tuple`
     Function(TypeRef, TypeRef),
-    NewType(&'a str),
+    NewType(&'a str, Vec<TypeRef>),
+    // a type parameter of a signature, only equal to itself
+    Param(&'a str),
+    // an unknown type, solved during inference
+    Var(usize),
+}
+
+// a type parameter of a signature and its bounds
+#[derive(Debug, PartialEq)]
+pub struct Generic<'a> {
+    pub ident: &'a str,
+    pub bounds: Vec<TypeRef>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    pub line: u32,
+    pub column: u32,
 }
 
 #[derive(Debug)]
 pub struct Ast<'a> {
-    exprs: List<Expression<'a>>,
-    stmts: List<StatementRef>,
-    types: List<Type<'a>>,
-    expr_types: HashMap<ExpressionRef, TypeRef>,
+    pub(crate) exprs: List<Expression<'a>>,
+    pub(crate) spans: List<Span>,
+    pub(crate) locations: List<Location>,
+    pub(crate) stmts: List<StatementRef>,
+    pub(crate) types: List<Type<'a>>,
+    pub(crate) expr_types: HashMap<ExpressionRef, TypeRef>,
+    // the lines of the doc comments of bindings and signatures, by root
+    pub(crate) docs: HashMap<usize, Vec<&'a str>>,
+    pub(crate) trivia: List<Trivia>,
+    // the warnings of the last type inference
+    pub(crate) warnings: Vec<TypeError>,
+    // the statements of the prelude, which are parsed after the program the
+    // first time its types are inferred
+    pub(crate) prelude: Vec<ExpressionRef>,
 }
 
 impl<'a> Ast<'a> {
@@ -127,48 +366,1419 @@ impl<'a> Ast<'a> {
         Self {
             stmts: List::new(),
             exprs: List::new(),
+            spans: List::new(),
+            locations: List::new(),
             types: List::new(),
             expr_types: HashMap::new(),
+            docs: HashMap::new(),
+            trivia: List::new(),
+            warnings: Vec::new(),
+            prelude: Vec::new(),
         }
     }
 
-    pub fn add_expression(&mut self, expr: Expression<'a>) -> ExpressionRef {
+    // the children of `expr` must be the subtrees added right before it
+    pub fn add_expression(
+        &mut self,
+        expr: Expression<'a>,
+        span: Span,
+        location: Location,
+    ) -> ExpressionRef {
+        let root = self.exprs.len();
+        let index = expr.start(root);
+
         self.exprs.push(expr);
-        todo!()
+        self.spans.push(span);
+        self.locations.push(location);
+
+        ExpressionRef {
+            index,
+            len: root - index + 1,
+        }
     }
 
-    pub fn add_statement(&mut self, kind: StatementKind) -> StatementRef {
-        // self.stmts.push(stmt);
-        todo!()
+    pub fn add_statement(&mut self, kind: StatementKind, expr: ExpressionRef) -> StatementRef {
+        let stmt = StatementRef {
+            index: expr.index,
+            len: expr.len,
+            kind,
+        };
+
+        self.stmts.push(stmt);
+        stmt
     }
 
-    pub fn add_type(&mut self, typ: Type<'a>) {
+    pub fn add_type(&mut self, typ: Type<'a>) -> TypeRef {
         self.types.push(typ);
+        TypeRef {
+            index: self.types.len() - 1,
+        }
     }
 
     pub fn get_statement(&self, index: usize) -> &StatementRef {
         &self.stmts[index]
     }
 
+    pub fn statements(&self) -> impl Iterator<Item = &StatementRef> {
+        self.stmts.iter()
+    }
+
     pub fn get_expression(&self, index: usize) -> &Expression<'a> {
         &self.exprs[index]
     }
 
+    pub fn get_span(&self, index: usize) -> Span {
+        self.spans[index]
+    }
+
+    pub fn get_location(&self, index: usize) -> Location {
+        self.locations[index]
+    }
+
+    pub fn get_docs(&self, index: usize) -> &[&'a str] {
+        self.docs.get(&index).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn add_docs(&mut self, expr: ExpressionRef, docs: Vec<&'a str>) {
+        if !docs.is_empty() {
+            self.docs.insert(expr.root(), docs);
+        }
+    }
+
+    // every comment in the source, in order
+    pub fn trivia(&self) -> impl Iterator<Item = &Trivia> {
+        self.trivia.iter()
+    }
+
+    // the subtree rooted at `root`
+    pub fn subtree(&self, root: usize) -> ExpressionRef {
+        let index = self.exprs[root].start(root);
+
+        ExpressionRef {
+            index,
+            len: root - index + 1,
+        }
+    }
+
+    // the run of nodes added since `start`
+    pub fn run_since(&self, start: usize) -> ExpressionRef {
+        ExpressionRef {
+            index: start,
+            len: self.exprs.len() - start,
+        }
+    }
+
+    // split a run of sibling subtrees, such as the arguments of a call
+    pub fn children(&self, run: ExpressionRef) -> Vec<ExpressionRef> {
+        let mut children = Vec::new();
+        let mut end = run.index + run.len;
+
+        while end > run.index {
+            let child = self.subtree(end - 1);
+            end = child.index;
+            children.push(child);
+        }
+
+        children.reverse();
+        children
+    }
+
+    pub fn get_type_of(&self, typ: TypeRef) -> &Type<'a> {
+        &self.types[typ.index]
+    }
+
+    pub fn warnings(&self) -> &[TypeError] {
+        &self.warnings
+    }
+
     pub fn get_type(&self, expr_ref: ExpressionRef) -> Option<TypeRef> {
         self.expr_types.get(&expr_ref).copied()
     }
 
-    pub fn infer_types(&mut self) -> Result<(), ()> {
-        todo!()
+    // fails with the type errors, if there are any. Warnings are kept in the
+    // tree either way.
+    pub fn infer_types(&mut self) -> Result<(), Vec<TypeError>> {
+        if self.prelude.is_empty() {
+            self.prelude = FlatParser::parse_prelude(self);
+        }
+
+        let (expr_types, errors) = infer::infer(self);
+        self.expr_types = expr_types;
+
+        let (warnings, errors) = errors
+            .into_iter()
+            .partition(|error| error.severity == Severity::Warning);
+        self.warnings = warnings;
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// Conversions between the boxed AST and the flat one. The boxed AST only has
+// spans, so lowered nodes get the default location.
+impl<'a> From<&'a ast::Ast> for Ast<'a> {
+    fn from(boxed: &'a ast::Ast) -> Self {
+        let mut ast = Ast::new();
+
+        for stmt in boxed.stmts() {
+            let (kind, expr) = ast.lower_stmt(stmt);
+            ast.add_statement(kind, expr);
+        }
+
+        for trivia in &boxed.trivia {
+            ast.trivia.push(*trivia);
+        }
+
+        ast
+    }
+}
+
+impl<'a> Ast<'a> {
+    fn lower(&mut self, expr: Expression<'a>, span: Span) -> ExpressionRef {
+        self.add_expression(expr, span, Location::default())
+    }
+
+    fn lower_stmt(&mut self, stmt: &'a ast::Stmt) -> (StatementKind, ExpressionRef) {
+        let (kind, expr) = self.lower_stmt_kind(stmt);
+        let docs = stmt.docs.iter().map(String::as_str).collect();
+        self.add_docs(expr, docs);
+        (kind, expr)
+    }
+
+    fn lower_stmt_kind(&mut self, stmt: &'a ast::Stmt) -> (StatementKind, ExpressionRef) {
+        match &stmt.kind {
+            ast::StmtKind::Expr(expr) => (StatementKind::Expr, self.lower_expr(expr)),
+            ast::StmtKind::Binding(ident, value) => {
+                let value = self.lower_expr(value);
+                let expr = Expression::Binding { ident, value };
+                (StatementKind::Binding, self.lower(expr, stmt.span))
+            }
+            ast::StmtKind::Assign(ident, operator, value) => {
+                let value = self.lower_expr(value);
+                let expr = Expression::Assign {
+                    ident,
+                    operator: *operator,
+                    value,
+                };
+                (StatementKind::Assign, self.lower(expr, stmt.span))
+            }
+            ast::StmtKind::Return(value) => {
+                let value = self.lower_expr(value);
+                let expr = Expression::Return(value);
+                (StatementKind::Return, self.lower(expr, stmt.span))
+            }
+            ast::StmtKind::Signature(signature) => {
+                let expr = self.lower_signature(signature, stmt.span, &[]);
+                (StatementKind::Signature, expr)
+            }
+            ast::StmtKind::TypeDecl(decl) => {
+                let params = param_names(&decl.generics);
+
+                let kind = match &decl.kind {
+                    ast::TypeDeclKind::Struct(fields) => {
+                        TypeDeclKind::Struct(self.lower_field_decls(fields, &params))
+                    }
+                    ast::TypeDeclKind::Enum(variants) => TypeDeclKind::Enum(
+                        variants
+                            .iter()
+                            .map(|variant| Variant {
+                                ident: &variant.ident,
+                                fields: self.lower_field_decls(&variant.fields, &params),
+                            })
+                            .collect(),
+                    ),
+                };
+
+                let decl = TypeDecl {
+                    ident: &decl.ident,
+                    generics: self.lower_generics(&decl.generics, &params),
+                    kind,
+                    derives: decl.derives.iter().map(String::as_str).collect(),
+                };
+                let expr = self.lower(Expression::TypeDecl(Box::new(decl)), stmt.span);
+                (StatementKind::TypeDecl, expr)
+            }
+            ast::StmtKind::Trait(declared) => {
+                let params = param_names(&declared.generics);
+                let items = self.lower_items(&declared.items, &params);
+
+                let declared = Trait {
+                    ident: &declared.ident,
+                    generics: self.lower_generics(&declared.generics, &params),
+                    items,
+                };
+                let expr = self.lower(Expression::Trait(Box::new(declared)), stmt.span);
+                (StatementKind::Trait, expr)
+            }
+            ast::StmtKind::Impl(implemented) => {
+                let params = param_names(&implemented.generics);
+                let items = self.lower_items(&implemented.items, &params);
+
+                let implemented = Impl {
+                    generics: self.lower_generics(&implemented.generics, &params),
+                    trait_: implemented
+                        .trait_
+                        .as_ref()
+                        .map(|trait_| self.lower_type(trait_, &params)),
+                    ty: self.lower_type(&implemented.ty, &params),
+                    predicates: self.lower_generics(&implemented.predicates, &params),
+                    items,
+                };
+                let expr = self.lower(Expression::Impl(Box::new(implemented)), stmt.span);
+                (StatementKind::Impl, expr)
+            }
+            ast::StmtKind::Break(label, value) => {
+                let value = value.as_ref().map(|value| self.lower_expr(value));
+                let label = label.as_deref();
+                let expr = self.lower(Expression::Break { label, value }, stmt.span);
+                (StatementKind::Break, expr)
+            }
+            ast::StmtKind::Continue(label) => {
+                let expr = self.lower(Expression::Continue(label.as_deref()), stmt.span);
+                (StatementKind::Continue, expr)
+            }
+            ast::StmtKind::Error => {
+                let skipped = self.run_since(self.exprs.len());
+                let expr = self.lower(Expression::Error(skipped), stmt.span);
+                (StatementKind::Error, expr)
+            }
+        }
+    }
+
+    // `outer` are the type parameters in scope around the signature, such as
+    // those of the impl it is in
+    fn lower_signature(
+        &mut self,
+        signature: &'a ast::Signature,
+        span: Span,
+        outer: &[&'a str],
+    ) -> ExpressionRef {
+        let binding = signature
+            .binding
+            .as_ref()
+            .map(|binding| self.lower_stmt(binding).1);
+
+        let mut params = outer.to_vec();
+        params.extend(param_names(&signature.generics));
+
+        let expr = Expression::Signature {
+            ident: &signature.ident,
+            generics: self.lower_generics(&signature.generics, &params),
+            ty: self.lower_type(&signature.ty, &params),
+            binding,
+        };
+        self.lower(expr, span)
+    }
+
+    // the methods of a trait or impl, in whose signatures `Self` and the
+    // `params` of the block are type parameters
+    fn lower_items(&mut self, items: &'a [ast::Stmt], params: &[&'a str]) -> ExpressionRef {
+        let start = self.exprs.len();
+        let mut params = params.to_vec();
+        params.push("Self");
+
+        for item in items {
+            let expr = match &item.kind {
+                ast::StmtKind::Signature(signature) => {
+                    self.lower_signature(signature, item.span, &params)
+                }
+                _ => self.lower_stmt_kind(item).1,
+            };
+            let docs = item.docs.iter().map(String::as_str).collect();
+            self.add_docs(expr, docs);
+        }
+
+        self.run_since(start)
+    }
+
+    fn lower_block(&mut self, stmts: &'a [ast::Stmt], span: Span) -> ExpressionRef {
+        let start = self.exprs.len();
+
+        for stmt in stmts {
+            self.lower_stmt(stmt);
+        }
+
+        let stmts = self.run_since(start);
+        self.lower(Expression::Block(stmts), span)
+    }
+
+    fn lower_expr(&mut self, expr: &'a ast::Expr) -> ExpressionRef {
+        let lowered = match &expr.kind {
+            ast::ExprKind::Unit => Expression::Unit,
+            ast::ExprKind::Int(literal) => Expression::Int(literal),
+            ast::ExprKind::Float(literal) => Expression::Float(literal),
+            ast::ExprKind::Bool(literal) => Expression::Bool(literal),
+            ast::ExprKind::Char(literal) => Expression::Char(literal),
+            ast::ExprKind::Str(literal) => Expression::Str(literal),
+            ast::ExprKind::RawStr(literal) => Expression::RawStr(literal),
+            ast::ExprKind::Ident(ident) => Expression::Ident(ident),
+            ast::ExprKind::Unary(unary) => Expression::Prefix {
+                operator: unary.operator,
+                operand: self.lower_expr(&unary.operand),
+            },
+            ast::ExprKind::Binary(binary) => Expression::Infix {
+                operator: binary.operator,
+                left: self.lower_expr(&binary.left_operand),
+                right: self.lower_expr(&binary.right_operand),
+            },
+            // the branches of the boxed AST are bare statements, so the blocks
+            // get the span of the whole conditional. An `else if` is lowered
+            // to the nested `If` itself, like the flat parser keeps it.
+            ast::ExprKind::Conditional(conditional) => Expression::If {
+                condition: self.lower_expr(&conditional.condition),
+                consequent: self.lower_block(&conditional.consequent, expr.span),
+                alternative: conditional
+                    .alternative
+                    .as_ref()
+                    .map(|alternative| match alternative.as_slice() {
+                        [ast::Stmt {
+                            kind: ast::StmtKind::Expr(nested),
+                            ..
+                        }] if matches!(nested.kind, ast::ExprKind::Conditional(_)) => {
+                            self.lower_expr(nested)
+                        }
+                        _ => self.lower_block(alternative, expr.span),
+                    }),
+            },
+            ast::ExprKind::Function(function) => {
+                let start = self.exprs.len();
+
+                for arg in &function.args {
+                    let lowered = Expression::Arg {
+                        ident: &arg.ident,
+                        mutable: arg.mutable,
+                    };
+                    self.lower(lowered, arg.span);
+                }
+
+                Expression::Function {
+                    args: self.run_since(start),
+                    body: self.lower_expr(&function.body),
+                }
+            }
+            ast::ExprKind::Call(call) => {
+                let callee = self.lower_expr(&call.callee);
+                let start = self.exprs.len();
+
+                for arg in &call.args {
+                    self.lower_expr(arg);
+                }
+
+                Expression::Call {
+                    callee,
+                    args: self.run_since(start),
+                }
+            }
+            ast::ExprKind::Range(range) => Expression::Range {
+                from: self.lower_expr(&range.from),
+                to: self.lower_expr(&range.to),
+                inclusive: range.inclusive,
+            },
+            ast::ExprKind::Scope(stmts) => return self.lower_block(stmts, expr.span),
+            // loop bodies are bare statements too, so their blocks get the
+            // span of the whole loop
+            ast::ExprKind::Loop(looped) => Expression::Loop {
+                label: looped.label.as_deref(),
+                body: self.lower_block(&looped.body, expr.span),
+            },
+            ast::ExprKind::While(looped) => Expression::While {
+                label: looped.label.as_deref(),
+                pattern: looped
+                    .pattern
+                    .as_ref()
+                    .map(|pattern| self.lower_pattern(pattern)),
+                condition: self.lower_expr(&looped.condition),
+                body: self.lower_block(&looped.body, expr.span),
+            },
+            ast::ExprKind::For(looped) => Expression::For {
+                label: looped.label.as_deref(),
+                pattern: self.lower_pattern(&looped.pattern),
+                iterable: self.lower_expr(&looped.iterable),
+                body: self.lower_block(&looped.body, expr.span),
+            },
+            ast::ExprKind::Tuple(exprs) => {
+                let start = self.exprs.len();
+
+                for expr in exprs {
+                    self.lower_expr(expr);
+                }
+
+                Expression::Tuple(self.run_since(start))
+            }
+            ast::ExprKind::Match(matched) => {
+                let lowered = self.lower_expr(&matched.matched);
+                let start = self.exprs.len();
+
+                for arm in &matched.arms {
+                    let lowered = Expression::Arm {
+                        pattern: self.lower_pattern(&arm.pattern),
+                        guard: arm.guard.as_ref().map(|guard| self.lower_expr(guard)),
+                        body: self.lower_expr(&arm.body),
+                    };
+                    self.lower(lowered, arm.span);
+                }
+
+                Expression::Match {
+                    matched: lowered,
+                    arms: self.run_since(start),
+                }
+            }
+            ast::ExprKind::Struct(literal) => {
+                let start = self.exprs.len();
+
+                for (ident, value) in &literal.fields {
+                    let value = self.lower_expr(value);
+                    let span = self.get_span(value.root());
+                    self.lower(Expression::NamedField { ident, value }, span);
+                }
+
+                Expression::Struct {
+                    ident: &literal.ident,
+                    fields: self.run_since(start),
+                }
+            }
+            ast::ExprKind::Field(field) => Expression::Field {
+                target: self.lower_expr(&field.target),
+                field: self.lower_expr(&field.field),
+            },
+            ast::ExprKind::Try(try_) => Expression::Try {
+                expr: self.lower_expr(&try_.expr),
+                default: try_
+                    .default
+                    .as_ref()
+                    .map(|default| self.lower_expr(default)),
+            },
+        };
+
+        self.lower(lowered, expr.span)
+    }
+
+    fn lower_pattern(&mut self, pattern: &'a ast::Pattern) -> ExpressionRef {
+        let lowered = match &pattern.kind {
+            ast::PatternKind::Wildcard => Expression::Dash,
+            ast::PatternKind::Binding(ident) => Expression::Ident(ident),
+            ast::PatternKind::Literal(literal) => return self.lower_expr(literal),
+            ast::PatternKind::Range(range) => Expression::Range {
+                from: self.lower_expr(&range.from),
+                to: self.lower_expr(&range.to),
+                inclusive: range.inclusive,
+            },
+            ast::PatternKind::Tuple(patterns) => {
+                Expression::Tuple(self.lower_patterns(patterns.iter()))
+            }
+            ast::PatternKind::Constructor(ident, fields) => {
+                let start = self.exprs.len();
+
+                for field in fields {
+                    let value = self.lower_pattern(&field.pattern);
+
+                    if let Some(name) = &field.name {
+                        let span = self.get_span(value.root());
+                        self.lower(Expression::NamedField { ident: name, value }, span);
+                    }
+                }
+
+                Expression::Constructor {
+                    ident,
+                    fields: self.run_since(start),
+                }
+            }
+            ast::PatternKind::Or(patterns) => Expression::Or(self.lower_patterns(patterns.iter())),
+            ast::PatternKind::At(ident, inner) => Expression::At {
+                ident,
+                pattern: self.lower_pattern(inner),
+            },
+        };
+
+        self.lower(lowered, pattern.span)
+    }
+
+    fn lower_generics(
+        &mut self,
+        generics: &'a [ast::Generic],
+        params: &[&str],
+    ) -> Vec<Generic<'a>> {
+        generics
+            .iter()
+            .map(|generic| Generic {
+                ident: &generic.ident,
+                bounds: generic
+                    .bounds
+                    .iter()
+                    .map(|bound| self.lower_type(bound, params))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn lower_field_decls(
+        &mut self,
+        fields: &'a [ast::FieldDecl],
+        params: &[&str],
+    ) -> Vec<FieldDecl<'a>> {
+        fields
+            .iter()
+            .map(|field| FieldDecl {
+                name: field.name.as_deref(),
+                ty: self.lower_type(&field.ty, params),
+            })
+            .collect()
     }
+
+    fn lower_patterns(
+        &mut self,
+        patterns: impl Iterator<Item = &'a ast::Pattern>,
+    ) -> ExpressionRef {
+        let start = self.exprs.len();
+
+        for pattern in patterns {
+            self.lower_pattern(pattern);
+        }
+
+        self.run_since(start)
+    }
+
+    // names listed in `params` are type parameters
+    fn lower_type(&mut self, ty: &'a ast::Type, params: &[&str]) -> TypeRef {
+        let lowered = match ty {
+            ast::Type::Unit => Type::Unit,
+            ast::Type::Never => Type::Never,
+            ast::Type::Bool => Type::Bool,
+            ast::Type::Int => Type::Int,
+            ast::Type::Float => Type::Float,
+            ast::Type::Char => Type::Char,
+            ast::Type::String => Type::String,
+            ast::Type::Tuple(types) => {
+                Type::Tuple(types.iter().map(|ty| self.lower_type(ty, params)).collect())
+            }
+            ast::Type::Function(args, ret) => {
+                let args = args.iter().map(|ty| self.lower_type(ty, params)).collect();
+                let args = self.add_type(Type::Tuple(args));
+                Type::Function(args, self.lower_type(ret, params))
+            }
+            ast::Type::NewType(ident, args)
+                if args.is_empty() && params.contains(&ident.as_str()) =>
+            {
+                Type::Param(ident)
+            }
+            ast::Type::NewType(ident, args) => {
+                let args = args.iter().map(|ty| self.lower_type(ty, params)).collect();
+                Type::NewType(ident, args)
+            }
+        };
+
+        self.add_type(lowered)
+    }
+}
+
+// the names of the type parameters declared by `generics`
+fn param_names(generics: &[ast::Generic]) -> Vec<&str> {
+    generics
+        .iter()
+        .map(|generic| generic.ident.as_str())
+        .collect()
 }
 
-trait ToLisp {
-    fn to_lisp(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
+impl From<&Ast<'_>> for ast::Ast {
+    fn from(flat: &Ast<'_>) -> Self {
+        let mut ast = ast::Ast::new();
+
+        for stmt in flat.statements() {
+            ast.push(flat.raise_stmt(stmt.expr()));
+        }
+
+        ast.trivia = flat.trivia().copied().collect();
+        ast
+    }
 }
 
-impl std::fmt::Display for dyn ToLisp + '_ {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Ast<'_> {
+    fn raise_stmt(&self, expr: ExpressionRef) -> ast::Stmt {
+        let span = self.get_span(expr.root());
+
+        let kind = match self.get_expression(expr.root()) {
+            Expression::Binding { ident, value } => {
+                ast::StmtKind::Binding(ident.to_string(), self.raise_expr(*value))
+            }
+            Expression::Assign {
+                ident,
+                operator,
+                value,
+            } => ast::StmtKind::Assign(ident.to_string(), *operator, self.raise_expr(*value)),
+            Expression::Return(value) => ast::StmtKind::Return(self.raise_expr(*value)),
+            Expression::Break { label, value } => ast::StmtKind::Break(
+                label.map(str::to_owned),
+                value.map(|value| self.raise_expr(value)),
+            ),
+            Expression::Continue(label) => ast::StmtKind::Continue(label.map(str::to_owned)),
+            Expression::Signature {
+                ident,
+                generics,
+                ty,
+                binding,
+            } => ast::StmtKind::Signature(Box::new(ast::Signature {
+                ident: ident.to_string(),
+                generics: self.raise_generics(generics),
+                ty: self.raise_type(*ty),
+                binding: binding.map(|binding| self.raise_stmt(binding)),
+            })),
+            Expression::TypeDecl(decl) => {
+                let kind = match &decl.kind {
+                    TypeDeclKind::Struct(fields) => {
+                        ast::TypeDeclKind::Struct(self.raise_field_decls(fields))
+                    }
+                    TypeDeclKind::Enum(variants) => ast::TypeDeclKind::Enum(
+                        variants
+                            .iter()
+                            .map(|variant| ast::Variant {
+                                ident: variant.ident.to_string(),
+                                fields: self.raise_field_decls(&variant.fields),
+                            })
+                            .collect(),
+                    ),
+                };
+
+                ast::StmtKind::TypeDecl(Box::new(ast::TypeDecl {
+                    ident: decl.ident.to_string(),
+                    generics: self.raise_generics(&decl.generics),
+                    kind,
+                    derives: decl.derives.iter().map(|ident| ident.to_string()).collect(),
+                }))
+            }
+            Expression::Trait(declared) => ast::StmtKind::Trait(Box::new(ast::Trait {
+                ident: declared.ident.to_string(),
+                generics: self.raise_generics(&declared.generics),
+                items: self.raise_items(declared.items),
+            })),
+            Expression::Impl(implemented) => ast::StmtKind::Impl(Box::new(ast::Impl {
+                generics: self.raise_generics(&implemented.generics),
+                trait_: implemented.trait_.map(|trait_| self.raise_type(trait_)),
+                ty: self.raise_type(implemented.ty),
+                predicates: self.raise_generics(&implemented.predicates),
+                items: self.raise_items(implemented.items),
+            })),
+            Expression::Error(_) => ast::StmtKind::Error,
+            _ => ast::StmtKind::Expr(self.raise_expr(expr)),
+        };
+
+        let docs = self.get_docs(expr.root());
+        ast::Stmt::new(kind, span).with_docs(docs.iter().map(|line| line.to_string()).collect())
+    }
+
+    fn raise_block(&self, expr: ExpressionRef) -> Vec<ast::Stmt> {
+        match self.get_expression(expr.root()) {
+            Expression::Block(stmts) => self
+                .children(*stmts)
+                .into_iter()
+                .map(|stmt| self.raise_stmt(stmt))
+                .collect(),
+            _ => vec![self.raise_stmt(expr)],
+        }
+    }
+
+    fn raise_items(&self, items: ExpressionRef) -> Vec<ast::Stmt> {
+        self.children(items)
+            .into_iter()
+            .map(|item| self.raise_stmt(item))
+            .collect()
+    }
+
+    fn raise_expr(&self, expr: ExpressionRef) -> ast::Expr {
+        let span = self.get_span(expr.root());
+
+        let kind = match self.get_expression(expr.root()) {
+            Expression::Unit => ast::ExprKind::Unit,
+            Expression::Int(literal) => ast::ExprKind::Int(literal.to_string()),
+            Expression::Float(literal) => ast::ExprKind::Float(literal.to_string()),
+            Expression::Bool(literal) => ast::ExprKind::Bool(literal.to_string()),
+            Expression::Char(literal) => ast::ExprKind::Char(literal.to_string()),
+            Expression::Str(literal) => ast::ExprKind::Str(literal.to_string()),
+            Expression::RawStr(literal) => ast::ExprKind::RawStr(literal.to_string()),
+            Expression::Ident(ident) => ast::ExprKind::Ident(ident.to_string()),
+            Expression::Prefix { operator, operand } => {
+                ast::ExprKind::Unary(Box::new(ast::Unary {
+                    operator: *operator,
+                    operand: self.raise_expr(*operand),
+                }))
+            }
+            Expression::Infix {
+                operator,
+                left,
+                right,
+            } => ast::ExprKind::Binary(Box::new(ast::Binary {
+                operator: *operator,
+                left_operand: self.raise_expr(*left),
+                right_operand: self.raise_expr(*right),
+            })),
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => ast::ExprKind::Conditional(Box::new(ast::Conditional {
+                condition: self.raise_expr(*condition),
+                consequent: self.raise_block(*consequent),
+                alternative: alternative.map(|alternative| self.raise_block(alternative)),
+            })),
+            Expression::Function { args, body } => {
+                let args = self
+                    .children(*args)
+                    .into_iter()
+                    .map(|arg| match self.get_expression(arg.root()) {
+                        Expression::Arg { ident, mutable } => ast::Arg {
+                            ident: ident.to_string(),
+                            mutable: *mutable,
+                            span: self.get_span(arg.root()),
+                        },
+                        arg => unreachable!("{:?} is not an argument", arg),
+                    })
+                    .collect();
+
+                ast::ExprKind::Function(Box::new(ast::Function {
+                    args,
+                    body: Rc::new(self.raise_expr(*body)),
+                }))
+            }
+            Expression::Call { callee, args } => ast::ExprKind::Call(Box::new(ast::Call {
+                callee: self.raise_expr(*callee),
+                args: self
+                    .children(*args)
+                    .into_iter()
+                    .map(|arg| self.raise_expr(arg))
+                    .collect(),
+            })),
+            // the boxed AST desugars pipelines into calls
+            Expression::Pipeline { value, call } => {
+                let value = self.raise_expr(*value);
+
+                match self.get_expression(call.root()) {
+                    Expression::Call { .. } => match self.raise_expr(*call).kind {
+                        ast::ExprKind::Call(mut call) => {
+                            call.args.insert(0, value);
+                            ast::ExprKind::Call(call)
+                        }
+                        _ => unreachable!(),
+                    },
+                    _ => ast::ExprKind::Call(Box::new(ast::Call {
+                        callee: self.raise_expr(*call),
+                        args: vec![value],
+                    })),
+                }
+            }
+            Expression::Block(_) => ast::ExprKind::Scope(self.raise_block(expr)),
+            Expression::Range {
+                from,
+                to,
+                inclusive,
+            } => ast::ExprKind::Range(Box::new(ast::Range {
+                from: self.raise_expr(*from),
+                to: self.raise_expr(*to),
+                inclusive: *inclusive,
+            })),
+            Expression::Loop { label, body } => ast::ExprKind::Loop(Box::new(ast::Loop {
+                label: label.map(str::to_owned),
+                body: self.raise_block(*body),
+            })),
+            Expression::While {
+                label,
+                pattern,
+                condition,
+                body,
+            } => ast::ExprKind::While(Box::new(ast::While {
+                label: label.map(str::to_owned),
+                pattern: pattern.map(|pattern| self.raise_pattern(pattern)),
+                condition: self.raise_expr(*condition),
+                body: self.raise_block(*body),
+            })),
+            Expression::For {
+                label,
+                pattern,
+                iterable,
+                body,
+            } => ast::ExprKind::For(Box::new(ast::For {
+                label: label.map(str::to_owned),
+                pattern: self.raise_pattern(*pattern),
+                iterable: self.raise_expr(*iterable),
+                body: self.raise_block(*body),
+            })),
+            Expression::Tuple(exprs) => ast::ExprKind::Tuple(
+                self.children(*exprs)
+                    .into_iter()
+                    .map(|expr| self.raise_expr(expr))
+                    .collect(),
+            ),
+            Expression::Match { matched, arms } => ast::ExprKind::Match(Box::new(ast::Match {
+                matched: self.raise_expr(*matched),
+                arms: self
+                    .children(*arms)
+                    .into_iter()
+                    .map(|arm| match self.get_expression(arm.root()) {
+                        Expression::Arm {
+                            pattern,
+                            guard,
+                            body,
+                        } => ast::Arm {
+                            pattern: self.raise_pattern(*pattern),
+                            guard: guard.map(|guard| self.raise_expr(guard)),
+                            body: self.raise_expr(*body),
+                            span: self.get_span(arm.root()),
+                        },
+                        arm => unreachable!("{:?} is not an arm", arm),
+                    })
+                    .collect(),
+            })),
+            Expression::Struct { ident, fields } => ast::ExprKind::Struct(Box::new(ast::Struct {
+                ident: ident.to_string(),
+                fields: self
+                    .children(*fields)
+                    .into_iter()
+                    .map(|field| match self.get_expression(field.root()) {
+                        Expression::NamedField { ident, value } => {
+                            (ident.to_string(), self.raise_expr(*value))
+                        }
+                        field => unreachable!("{:?} is not a named field", field),
+                    })
+                    .collect(),
+            })),
+            Expression::Field { target, field } => ast::ExprKind::Field(Box::new(ast::Field {
+                target: self.raise_expr(*target),
+                field: self.raise_expr(*field),
+            })),
+            Expression::Try { expr, default } => ast::ExprKind::Try(Box::new(ast::Try {
+                expr: self.raise_expr(*expr),
+                default: default.map(|default| self.raise_expr(default)),
+            })),
+            // statements, patterns, arms, arguments and named fields are raised
+            // by their parents
+            node => unreachable!("{:?} is not an expression", node),
+        };
+
+        ast::Expr::new(kind, span)
+    }
+
+    fn raise_pattern(&self, pattern: ExpressionRef) -> ast::Pattern {
+        let kind = match self.get_expression(pattern.root()) {
+            Expression::Dash => ast::PatternKind::Wildcard,
+            Expression::Ident(ident) => ast::PatternKind::Binding(ident.to_string()),
+            Expression::Range {
+                from,
+                to,
+                inclusive,
+            } => ast::PatternKind::Range(Box::new(ast::Range {
+                from: self.raise_expr(*from),
+                to: self.raise_expr(*to),
+                inclusive: *inclusive,
+            })),
+            Expression::Tuple(patterns) => ast::PatternKind::Tuple(self.raise_patterns(*patterns)),
+            Expression::Constructor { ident, fields } => ast::PatternKind::Constructor(
+                ident.to_string(),
+                self.children(*fields)
+                    .into_iter()
+                    .map(|field| match self.get_expression(field.root()) {
+                        Expression::NamedField { ident, value } => ast::FieldPattern {
+                            name: Some(ident.to_string()),
+                            pattern: self.raise_pattern(*value),
+                        },
+                        _ => ast::FieldPattern {
+                            name: None,
+                            pattern: self.raise_pattern(field),
+                        },
+                    })
+                    .collect(),
+            ),
+            Expression::Or(patterns) => ast::PatternKind::Or(self.raise_patterns(*patterns)),
+            Expression::At { ident, pattern } => {
+                ast::PatternKind::At(ident.to_string(), Box::new(self.raise_pattern(*pattern)))
+            }
+            _ => ast::PatternKind::Literal(self.raise_expr(pattern)),
+        };
+
+        ast::Pattern {
+            kind,
+            span: self.get_span(pattern.root()),
+        }
+    }
+
+    fn raise_generics(&self, generics: &[Generic]) -> Vec<ast::Generic> {
+        generics
+            .iter()
+            .map(|generic| ast::Generic {
+                ident: generic.ident.to_string(),
+                bounds: generic
+                    .bounds
+                    .iter()
+                    .map(|ty| self.raise_type(*ty))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn raise_field_decls(&self, fields: &[FieldDecl]) -> Vec<ast::FieldDecl> {
+        fields
+            .iter()
+            .map(|field| ast::FieldDecl {
+                name: field.name.map(str::to_owned),
+                ty: self.raise_type(field.ty),
+            })
+            .collect()
+    }
+
+    fn raise_patterns(&self, patterns: ExpressionRef) -> Vec<ast::Pattern> {
+        self.children(patterns)
+            .into_iter()
+            .map(|pattern| self.raise_pattern(pattern))
+            .collect()
+    }
+
+    fn raise_type(&self, ty: TypeRef) -> ast::Type {
+        match self.get_type_of(ty) {
+            Type::Unit => ast::Type::Unit,
+            Type::Never => ast::Type::Never,
+            Type::Bool => ast::Type::Bool,
+            Type::Int => ast::Type::Int,
+            Type::Float => ast::Type::Float,
+            Type::Char => ast::Type::Char,
+            Type::String => ast::Type::String,
+            Type::Tuple(types) => {
+                ast::Type::Tuple(types.iter().map(|ty| self.raise_type(*ty)).collect())
+            }
+            Type::Function(args, ret) => {
+                let args = match self.raise_type(*args) {
+                    ast::Type::Tuple(args) => args,
+                    ty => vec![ty],
+                };
+                ast::Type::Function(args, Box::new(self.raise_type(*ret)))
+            }
+            Type::NewType(ident, args) => ast::Type::NewType(
+                ident.to_string(),
+                args.iter().map(|ty| self.raise_type(*ty)).collect(),
+            ),
+            Type::Param(ident) => ast::Type::NewType(ident.to_string(), Vec::new()),
+            Type::Var(var) => unreachable!("unsolved type variable {}", var),
+        }
+    }
+}
+
+// Prints nodes as S-expressions: `add = a, b -> a + b` is
+// `(bind add (fn (a b) (+ a b)))`
+pub trait ToLisp {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+impl fmt::Display for dyn ToLisp + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.to_lisp(f)
     }
 }
+
+// A node together with the arena it lives in, which it needs to print its children
+pub struct Lisp<'ast, 'a, T> {
+    ast: &'ast Ast<'a>,
+    node: T,
+}
+
+impl<'a> Ast<'a> {
+    pub fn lisp<T>(&self, node: T) -> Lisp<'_, 'a, T> {
+        Lisp { ast: self, node }
+    }
+}
+
+impl<T> fmt::Display for Lisp<'_, '_, T>
+where
+    Self: ToLisp,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_lisp(f)
+    }
+}
+
+impl fmt::Display for Ast<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_lisp(f)
+    }
+}
+
+// one statement per line
+impl ToLisp for Ast<'_> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stmt) in self.statements().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", self.lisp(*stmt))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ToLisp for Lisp<'_, '_, StatementRef> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ast.lisp(self.node.expr()))
+    }
+}
+
+impl ToLisp for Lisp<'_, '_, ExpressionRef> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ast = self.ast;
+
+        // `(head child...)`
+        let list =
+            |f: &mut fmt::Formatter<'_>, head: &dyn fmt::Display, children: &[ExpressionRef]| {
+                write!(f, "({}", head)?;
+                for child in children {
+                    write!(f, " {}", ast.lisp(*child))?;
+                }
+                write!(f, ")")
+            };
+
+        match ast.get_expression(self.node.root()) {
+            Expression::Unit => write!(f, "()"),
+            Expression::Dash => write!(f, "_"),
+            Expression::Char(literal) => write!(f, "'{}'", literal),
+            Expression::Str(literal) => write!(f, "\"{}\"", literal),
+            Expression::RawStr(literal) => write!(f, "r#\"{}\"#", literal),
+            Expression::Int(literal)
+            | Expression::Float(literal)
+            | Expression::Bool(literal)
+            | Expression::Ident(literal) => write!(f, "{}", literal),
+            Expression::Prefix { operator, operand } => list(f, operator, &[*operand]),
+            Expression::Infix {
+                operator,
+                left,
+                right,
+            } => list(f, operator, &[*left, *right]),
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => {
+                let mut children = vec![*condition, *consequent];
+                children.extend(*alternative);
+                list(f, &"if", &children)
+            }
+            Expression::Match { matched, arms } => {
+                let mut children = vec![*matched];
+                children.extend(ast.children(*arms));
+                list(f, &"match", &children)
+            }
+            // `(=> pattern body)`, or `(=> pattern (when guard) body)`
+            Expression::Arm {
+                pattern,
+                guard: Some(guard),
+                body,
+            } => write!(
+                f,
+                "(=> {} (when {}) {})",
+                ast.lisp(*pattern),
+                ast.lisp(*guard),
+                ast.lisp(*body)
+            ),
+            Expression::Arm { pattern, body, .. } => list(f, &"=>", &[*pattern, *body]),
+            Expression::Function { args, body } => {
+                write!(f, "(fn (")?;
+                for (i, arg) in ast.children(*args).into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", ast.lisp(arg))?;
+                }
+                write!(f, ") {})", ast.lisp(*body))
+            }
+            Expression::Arg { ident, mutable } if *mutable => write!(f, "(mut {})", ident),
+            Expression::Arg { ident, .. } => write!(f, "{}", ident),
+            Expression::Call { callee, args } => list(f, &ast.lisp(*callee), &ast.children(*args)),
+            // printed as the call it stands for, like the boxed AST stores it
+            Expression::Pipeline { value, call } => match ast.get_expression(call.root()) {
+                Expression::Call { callee, args } => {
+                    let mut children = vec![*value];
+                    children.extend(ast.children(*args));
+                    list(f, &ast.lisp(*callee), &children)
+                }
+                _ => list(f, &ast.lisp(*call), &[*value]),
+            },
+            Expression::Binding { ident, value } => list(f, &format!("bind {}", ident), &[*value]),
+            Expression::Assign {
+                ident,
+                operator,
+                value,
+            } => list(f, &format!("{}= {}", operator, ident), &[*value]),
+            Expression::Return(value) => list(f, &"return", &[*value]),
+            Expression::Loop { label, body } => list(f, &labeled("loop", label), &[*body]),
+            Expression::While {
+                label,
+                pattern: None,
+                condition,
+                body,
+            } => list(f, &labeled("while", label), &[*condition, *body]),
+            // `while pattern = value` reads as `(while (= pattern value) body)`
+            Expression::While {
+                label,
+                pattern: Some(pattern),
+                condition,
+                body,
+            } => write!(
+                f,
+                "({} (= {} {}) {})",
+                labeled("while", label),
+                ast.lisp(*pattern),
+                ast.lisp(*condition),
+                ast.lisp(*body)
+            ),
+            Expression::For {
+                label,
+                pattern,
+                iterable,
+                body,
+            } => list(f, &labeled("for", label), &[*pattern, *iterable, *body]),
+            Expression::Break { label, value } => {
+                let value: Vec<_> = value.iter().copied().collect();
+                list(f, &labeled("break", label), &value)
+            }
+            Expression::Continue(label) => list(f, &labeled("continue", label), &[]),
+            Expression::Constructor { ident, fields } => list(f, ident, &ast.children(*fields)),
+            Expression::NamedField { ident, value } => list(f, &format!("= {}", ident), &[*value]),
+            Expression::Struct { ident, fields } => list(f, ident, &ast.children(*fields)),
+            Expression::Field { target, field } => list(f, &".", &[*target, *field]),
+            Expression::Try { expr, default } => {
+                let mut operands = vec![*expr];
+                operands.extend(default);
+                list(f, &"?", &operands)
+            }
+            Expression::TypeDecl(decl) => write!(f, "{}", ast.lisp(&**decl)),
+            Expression::Trait(declared) => write!(f, "{}", ast.lisp(&**declared)),
+            Expression::Impl(implemented) => write!(f, "{}", ast.lisp(&**implemented)),
+            Expression::Or(patterns) => list(f, &"|", &ast.children(*patterns)),
+            Expression::At { ident, pattern } => list(f, &format!("@ {}", ident), &[*pattern]),
+            Expression::Signature {
+                ident,
+                generics,
+                ty,
+                binding,
+            } => {
+                write!(f, "(sig {} ", ident)?;
+
+                if generics.is_empty() {
+                    write!(f, "{}", ast.lisp(*ty))?;
+                } else {
+                    write!(f, "(forall (")?;
+                    for (i, generic) in generics.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, " ")?;
+                        }
+                        write!(f, "{}", ast.lisp(generic))?;
+                    }
+                    write!(f, ") {})", ast.lisp(*ty))?;
+                }
+
+                if let Some(binding) = binding {
+                    write!(f, " {}", ast.lisp(*binding))?;
+                }
+
+                write!(f, ")")
+            }
+            Expression::Block(stmts) => list(f, &"do", &ast.children(*stmts)),
+            Expression::Range {
+                from,
+                to,
+                inclusive,
+            } => {
+                let operator = if *inclusive { "..=" } else { ".." };
+                list(f, &operator, &[*from, *to])
+            }
+            Expression::Tuple(exprs) => list(f, &"tuple", &ast.children(*exprs)),
+            Expression::Error(_) => write!(f, "(error)"),
+        }
+    }
+}
+
+// the head of a loop or of a jump to one: `loop 'outer`
+fn labeled(head: &str, label: &Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{} '{}", head, label),
+        None => head.to_owned(),
+    }
+}
+
+// `T`, or `(T Format Clone)` with its bounds
+impl ToLisp for Lisp<'_, '_, &Generic<'_>> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let generic = self.node;
+
+        if generic.bounds.is_empty() {
+            return write!(f, "{}", generic.ident);
+        }
+
+        write!(f, "({}", generic.ident)?;
+        for bound in &generic.bounds {
+            write!(f, " {}", self.ast.lisp(*bound))?;
+        }
+        write!(f, ")")
+    }
+}
+
+// `(struct (Point T) (x T) (y T))` and `(enum Option (Some T) (None))`, then
+// `(derive Format)` if there is anything to derive
+impl ToLisp for Lisp<'_, '_, &TypeDecl<'_>> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ast, decl) = (self.ast, self.node);
+
+        let fields = |f: &mut fmt::Formatter<'_>, fields: &[FieldDecl]| {
+            for field in fields {
+                match field.name {
+                    Some(name) => write!(f, " ({} {})", name, ast.lisp(field.ty))?,
+                    None => write!(f, " {}", ast.lisp(field.ty))?,
+                }
+            }
+            Ok(())
+        };
+
+        let head = match decl.kind {
+            TypeDeclKind::Struct(_) => "struct",
+            TypeDeclKind::Enum(_) => "enum",
+        };
+
+        if decl.generics.is_empty() {
+            write!(f, "({} {}", head, decl.ident)?;
+        } else {
+            write!(f, "({} ({}", head, decl.ident)?;
+            for generic in &decl.generics {
+                write!(f, " {}", ast.lisp(generic))?;
+            }
+            write!(f, ")")?;
+        }
+
+        match &decl.kind {
+            TypeDeclKind::Struct(decls) => fields(f, decls)?,
+            TypeDeclKind::Enum(variants) => {
+                for variant in variants {
+                    write!(f, " ({}", variant.ident)?;
+                    fields(f, &variant.fields)?;
+                    write!(f, ")")?;
+                }
+            }
+        }
+
+        if !decl.derives.is_empty() {
+            write!(f, " (derive {})", decl.derives.join(" "))?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+// `(trait ToString (sig to_string (-> (Self) String)))`, or `(trait (Parser R)
+// ...)` with type parameters
+impl ToLisp for Lisp<'_, '_, &Trait<'_>> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ast, declared) = (self.ast, self.node);
+
+        if declared.generics.is_empty() {
+            write!(f, "(trait {}", declared.ident)?;
+        } else {
+            write!(f, "(trait ({}", declared.ident)?;
+            for generic in &declared.generics {
+                write!(f, " {}", ast.lisp(generic))?;
+            }
+            write!(f, ")")?;
+        }
+
+        for item in ast.children(declared.items) {
+            write!(f, " {}", ast.lisp(item))?;
+        }
+        write!(f, ")")
+    }
+}
+
+// `(impl (Point T) items...)`, or `(impl (generics T) (for Format (Node T))
+// (where (T Format)) items...)` for a trait with type parameters and bounds
+impl ToLisp for Lisp<'_, '_, &Impl<'_>> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ast, implemented) = (self.ast, self.node);
+
+        let generics = |f: &mut fmt::Formatter<'_>, head: &str, generics: &[Generic]| {
+            if generics.is_empty() {
+                return Ok(());
+            }
+
+            write!(f, " ({}", head)?;
+            for generic in generics {
+                write!(f, " {}", ast.lisp(generic))?;
+            }
+            write!(f, ")")
+        };
+
+        write!(f, "(impl")?;
+        generics(f, "generics", &implemented.generics)?;
+
+        match implemented.trait_ {
+            Some(trait_) => write!(
+                f,
+                " (for {} {})",
+                ast.lisp(trait_),
+                ast.lisp(implemented.ty)
+            )?,
+            None => write!(f, " {}", ast.lisp(implemented.ty))?,
+        }
+
+        generics(f, "where", &implemented.predicates)?;
+
+        for item in ast.children(implemented.items) {
+            write!(f, " {}", ast.lisp(item))?;
+        }
+        write!(f, ")")
+    }
+}
+
+// `(-> (Int Int) Int)`, `(tuple Int Bool)` and `(Option T)`
+impl ToLisp for Lisp<'_, '_, TypeRef> {
+    fn to_lisp(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ast = self.ast;
+
+        let list = |f: &mut fmt::Formatter<'_>, types: &[TypeRef]| {
+            for (i, ty) in types.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}", ast.lisp(*ty))?;
+            }
+            Ok(())
+        };
+
+        match ast.get_type_of(self.node) {
+            Type::Unit => write!(f, "()"),
+            Type::Never => write!(f, "Never"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Char => write!(f, "Char"),
+            Type::String => write!(f, "String"),
+            Type::Tuple(types) => {
+                write!(f, "(tuple")?;
+                for ty in types {
+                    write!(f, " {}", ast.lisp(*ty))?;
+                }
+                write!(f, ")")
+            }
+            Type::Function(args, ret) => {
+                write!(f, "(-> (")?;
+                match ast.get_type_of(*args) {
+                    Type::Tuple(args) => list(f, args)?,
+                    _ => write!(f, "{}", ast.lisp(*args))?,
+                }
+                write!(f, ") {})", ast.lisp(*ret))
+            }
+            Type::NewType(ident, args) if args.is_empty() => write!(f, "{}", ident),
+            Type::NewType(ident, args) => {
+                write!(f, "({} ", ident)?;
+                list(f, args)?;
+                write!(f, ")")
+            }
+            Type::Param(ident) => write!(f, "{}", ident),
+            Type::Var(var) => write!(f, "?{}", var),
+        }
+    }
+}
@@ -0,0 +1,1519 @@
+use super::flat_ast::{
+    Ast, Expression, ExpressionRef, FieldDecl, Generic, Impl, Location, StatementKind, Trait, Type,
+    TypeDecl, TypeDeclKind, TypeRef, Variant,
+};
+use super::literal::{scan_string, Part};
+use super::POWER_PRECEDENCE;
+use super::{
+    assign_operator, binary_operator, Diagnostic, LoopScope, Operator, Parser, Result, Span, Token,
+    TokenKind::*,
+};
+use super::{interpolation_span, is_constructor, is_literal, is_wildcard, text_span};
+use super::{invalid_tuple_field, tuple_fields};
+use crate::builtins::PRELUDE;
+
+// Parses into the flat AST, borrowing identifiers and literals from the source
+// instead of copying them. The grammar is the same as `Parser`'s, which also
+// supplies the tokens.
+pub struct FlatParser<'a> {
+    source: &'a str,
+    tokens: Parser<'a>,
+    ast: Ast<'a>,
+    // the type parameters in scope, from the signature, type declaration,
+    // trait or impl being parsed
+    params: Vec<&'a str>,
+}
+
+impl<'a> FlatParser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            tokens: Parser::new(source),
+            ast: Ast::new(),
+            params: Vec::new(),
+        }
+    }
+
+    // see `Parser::parse`
+    pub fn parse(&mut self) -> (Ast<'a>, Vec<Diagnostic>) {
+        while let Some(token) = &self.tokens.curr {
+            match token.kind {
+                Semicolon => {
+                    self.bump();
+                }
+                CloseBrace => self.tokens.skip_unmatched_brace(),
+                _ => {
+                    let (kind, expr) = self.parse_stmt_or_recover();
+                    self.ast.add_statement(kind, expr);
+                }
+            }
+        }
+
+        for trivia in std::mem::take(&mut self.tokens.trivia) {
+            self.ast.trivia.push(trivia);
+        }
+
+        let diagnostics = std::mem::take(&mut self.tokens.diagnostics);
+        (std::mem::replace(&mut self.ast, Ast::new()), diagnostics)
+    }
+
+    // parse the prelude into `ast`, after the nodes it has, and return its
+    // statements, which aren't added to those of `ast`
+    pub(crate) fn parse_prelude(ast: &mut Ast<'a>) -> Vec<ExpressionRef> {
+        let mut parser = FlatParser::new(PRELUDE);
+        std::mem::swap(&mut parser.ast, ast);
+        let mut stmts = Vec::new();
+
+        while let Some(token) = &parser.tokens.curr {
+            match token.kind {
+                Semicolon => {
+                    parser.bump();
+                }
+                _ => stmts.push(parser.parse_stmt_or_recover().1),
+            }
+        }
+
+        std::mem::swap(&mut parser.ast, ast);
+        stmts
+    }
+
+    // a statement that fails to parse becomes an `Error` node, which owns the
+    // nodes added before the failure
+    fn parse_stmt_or_recover(&mut self) -> (StatementKind, ExpressionRef) {
+        let (start, depth, location) = (self.tokens.start(), self.tokens.depth, self.location());
+        let first = self.ast.exprs.len();
+
+        match self.parse_stmt() {
+            Ok(stmt) => stmt,
+            Err(error) => {
+                let span = self.tokens.recover(error, start, depth);
+                let skipped = self.ast.run_since(first);
+                let expr = self.add(Expression::Error(skipped), span, location);
+                (StatementKind::Error, expr)
+            }
+        }
+    }
+
+    fn bump(&mut self) -> Option<Token<'a>> {
+        self.tokens.bump()
+    }
+
+    fn span(&self, expr: ExpressionRef) -> Span {
+        self.ast.get_span(expr.root())
+    }
+
+    // where the current token starts, or the end of the input
+    fn location(&self) -> Location {
+        match &self.tokens.curr {
+            Some(token) => token_location(token),
+            None => Location::default(),
+        }
+    }
+
+    fn add(&mut self, expr: Expression<'a>, span: Span, location: Location) -> ExpressionRef {
+        self.ast.add_expression(expr, span, location)
+    }
+
+    fn parse_stmt(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let kind = self.tokens.curr.as_ref().map(|token| token.kind.clone());
+
+        match kind {
+            Some(Identifier) => self.parse_ident(),
+            Some(Return) => self.parse_return(),
+            Some(Break | Continue) => self.parse_break(),
+            Some(super::TokenKind::Trait) => self.parse_trait(),
+            Some(super::TokenKind::Impl) => self.parse_impl(),
+            _ => self.parse_expr_stmt(),
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let kind = self.tokens.peek.as_ref().map(|token| token.kind.clone());
+
+        match kind {
+            Some(Assign) => {
+                let token = self.bump().unwrap();
+                self.bump();
+                let value = self.parse_expr()?;
+                let span = self.tokens.span_from(token.span.start);
+                self.tokens.expect_terminator()?;
+
+                let ident = token.literal;
+                let binding = Expression::Binding { ident, value };
+                let expr = self.add(binding, span, token_location(&token));
+
+                let docs = self.tokens.take_docs(token.span.start);
+                self.ast.add_docs(expr, docs);
+                Ok((StatementKind::Binding, expr))
+            }
+            Some(
+                kind @ (PlusAssign | MinusAssign | MultiplyAssign | DivideAssign | ModuloAssign
+                | PowerAssign | BitAndAssign | BitOrAssign | BitXorAssign | LeftShiftAssign
+                | RightShiftAssign),
+            ) => {
+                let token = self.bump().unwrap();
+                self.bump();
+                let value = self.parse_expr()?;
+                let span = self.tokens.span_from(token.span.start);
+                self.tokens.expect_terminator()?;
+
+                let expr = Expression::Assign {
+                    ident: token.literal,
+                    operator: assign_operator(&kind),
+                    value,
+                };
+                let expr = self.add(expr, span, token_location(&token));
+                Ok((StatementKind::Assign, expr))
+            }
+            Some(Pipe) => self.parse_type_signature(),
+            Some(DoubleColon) => self.parse_type_decl(),
+            Some(LessThan) if self.tokens.generics_follow(DoubleColon) => self.parse_type_decl(),
+            Some(LessThan) if self.tokens.generics_follow(Pipe) => self.parse_type_signature(),
+            _ => self.parse_expr_stmt(),
+        }
+    }
+
+    // see `Parser::parse_type_decl`
+    fn parse_type_decl(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let token = self.bump().unwrap();
+        let mut generics = Vec::new();
+
+        if self.tokens.curr_is(LessThan) {
+            self.bump();
+            generics = self.parse_generics()?;
+            self.tokens.expect_closing_angle()?;
+        }
+
+        self.tokens.expect(DoubleColon)?;
+
+        if generics.is_empty() && self.tokens.curr_is(Identifier) {
+            generics = self.parse_generics()?;
+            self.tokens.expect(Arrow)?;
+        }
+
+        let outer = self.params.len();
+        self.params
+            .extend(generics.iter().map(|generic| generic.ident));
+        let kind = match &self.tokens.curr {
+            Some(token) if matches!(token.kind, OpenBrace | OpenParen) => {
+                self.parse_field_decls().map(TypeDeclKind::Struct)
+            }
+            Some(token) if token.kind == Pipe => self.parse_variants().map(TypeDeclKind::Enum),
+            _ => Err(self.tokens.expected("`{`, `(` or `|`")),
+        };
+        self.params.truncate(outer);
+        let kind = kind?;
+
+        let mut derives = Vec::new();
+
+        if self.tokens.curr_is(Derive) {
+            self.bump();
+
+            loop {
+                derives.push(self.tokens.expect(Identifier)?.literal);
+
+                if !self.tokens.curr_is(Comma) {
+                    break;
+                }
+                self.bump();
+            }
+        }
+
+        let span = self.tokens.span_from(token.span.start);
+        self.tokens.expect_terminator()?;
+
+        let decl = TypeDecl {
+            ident: token.literal,
+            generics,
+            kind,
+            derives,
+        };
+        let expr = Expression::TypeDecl(Box::new(decl));
+        let expr = self.add(expr, span, token_location(&token));
+
+        let docs = self.tokens.take_docs(token.span.start);
+        self.ast.add_docs(expr, docs);
+        Ok((StatementKind::TypeDecl, expr))
+    }
+
+    // see `Parser::parse_trait`
+    fn parse_trait(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let token = self.bump().unwrap();
+        let ident = self.tokens.expect(Identifier)?.literal;
+        let mut generics = Vec::new();
+
+        if self.tokens.curr_is(LessThan) {
+            self.bump();
+            generics = self.parse_generics()?;
+            self.tokens.expect_closing_angle()?;
+        }
+
+        self.tokens.expect(Assign)?;
+        let items = self.parse_items(&generics, true)?;
+        let span = self.tokens.span_from(token.span.start);
+        self.tokens.expect_terminator()?;
+
+        let declared = Trait {
+            ident,
+            generics,
+            items,
+        };
+        let expr = Expression::Trait(Box::new(declared));
+        let expr = self.add(expr, span, token_location(&token));
+
+        let docs = self.tokens.take_docs(token.span.start);
+        self.ast.add_docs(expr, docs);
+        Ok((StatementKind::Trait, expr))
+    }
+
+    // see `Parser::parse_impl`
+    fn parse_impl(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let token = self.bump().unwrap();
+        let mut generics = Vec::new();
+
+        if self.tokens.curr_is(LessThan) {
+            self.bump();
+            generics = self.parse_generics()?;
+            self.tokens.expect_closing_angle()?;
+        }
+
+        let outer = self.params.len();
+        self.params
+            .extend(generics.iter().map(|generic| generic.ident));
+        let header = self.parse_impl_header(&mut generics);
+        self.params.truncate(outer);
+        let (trait_, ty, predicates) = header?;
+
+        let items = self.parse_items(&generics, false)?;
+        let span = self.tokens.span_from(token.span.start);
+        self.tokens.expect_terminator()?;
+
+        let implemented = Impl {
+            generics,
+            trait_,
+            ty,
+            predicates,
+            items,
+        };
+        let expr = Expression::Impl(Box::new(implemented));
+        let expr = self.add(expr, span, token_location(&token));
+
+        let docs = self.tokens.take_docs(token.span.start);
+        self.ast.add_docs(expr, docs);
+        Ok((StatementKind::Impl, expr))
+    }
+
+    // `Format for Node<T> where T: Format`, or just the type
+    fn parse_impl_header(
+        &mut self,
+        generics: &mut Vec<Generic<'a>>,
+    ) -> Result<(Option<TypeRef>, TypeRef, Vec<Generic<'a>>)> {
+        let mut ty = self.parse_impl_type(generics)?;
+        let mut trait_ = None;
+
+        if self.tokens.curr_is(super::TokenKind::For) {
+            self.bump();
+            trait_ = Some(ty);
+            ty = self.parse_impl_type(generics)?;
+        }
+
+        let mut predicates = Vec::new();
+
+        if self.tokens.curr_is(Where) {
+            self.bump();
+            predicates = self.parse_generics()?;
+        }
+
+        Ok((trait_, ty, predicates))
+    }
+
+    // see `Parser::parse_impl_type`. The type parameters it declares are in
+    // scope for the rest of the impl.
+    fn parse_impl_type(&mut self, generics: &mut Vec<Generic<'a>>) -> Result<TypeRef> {
+        if !generics.is_empty() || !self.tokens.bounded_args_follow() {
+            return self.parse_type_primary();
+        }
+
+        let ident = self.bump().unwrap().literal;
+        self.bump();
+        *generics = self.parse_generics()?;
+        self.tokens.expect_closing_angle()?;
+
+        self.params
+            .extend(generics.iter().map(|generic| generic.ident));
+        let args = generics
+            .iter()
+            .map(|generic| self.ast.add_type(Type::Param(generic.ident)))
+            .collect();
+        Ok(self.ast.add_type(Type::NewType(ident, args)))
+    }
+
+    // see `Parser::parse_items`. `Self` and the type parameters of the trait
+    // or impl are in scope.
+    fn parse_items(&mut self, generics: &[Generic<'a>], required: bool) -> Result<ExpressionRef> {
+        let outer = self.params.len();
+        self.params.push("Self");
+        self.params
+            .extend(generics.iter().map(|generic| generic.ident));
+        let items = self.parse_item_run(required);
+        self.params.truncate(outer);
+        items
+    }
+
+    fn parse_item_run(&mut self, required: bool) -> Result<ExpressionRef> {
+        self.tokens.expect(OpenBrace)?;
+        let first = self.ast.exprs.len();
+
+        loop {
+            match (&self.tokens.curr, &self.tokens.peek) {
+                (Some(token), _) if token.kind == CloseBrace => break,
+                (Some(token), _) if token.kind == Semicolon => {
+                    self.bump();
+                }
+                (Some(token), Some(peek))
+                    if token.kind == Identifier && matches!(peek.kind, Assign | Pipe) =>
+                {
+                    let (kind, item) = self.parse_ident()?;
+
+                    if let Expression::Signature {
+                        ident,
+                        binding: None,
+                        ..
+                    } = self.ast.get_expression(item.root())
+                    {
+                        if kind == StatementKind::Signature && !required {
+                            let message = format!("method `{}` has no body", ident);
+                            let span = self.span(item);
+                            return Err(Diagnostic::error("syntax-error", message, span));
+                        }
+                    }
+                }
+                _ => return Err(self.tokens.expected("method")),
+            }
+        }
+
+        self.bump();
+        Ok(self.ast.run_since(first))
+    }
+
+    // see `Parser::parse_generics`
+    fn parse_generics(&mut self) -> Result<Vec<Generic<'a>>> {
+        let mut generics = Vec::new();
+
+        loop {
+            let ident = self.tokens.expect(Identifier)?.literal;
+            let mut bounds = Vec::new();
+
+            if self.tokens.curr_is(Colon) {
+                self.bump();
+
+                loop {
+                    bounds.push(self.parse_type_primary()?);
+
+                    if !self.tokens.curr_is(Plus) {
+                        break;
+                    }
+                    self.bump();
+                }
+            }
+
+            generics.push(Generic { ident, bounds });
+
+            if !self.tokens.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        Ok(generics)
+    }
+
+    // see `Parser::parse_variants`
+    fn parse_variants(&mut self) -> Result<Vec<Variant<'a>>> {
+        let mut variants = Vec::new();
+
+        while self.tokens.curr_is(Pipe) {
+            self.bump();
+
+            let ident = match &self.tokens.curr {
+                Some(token) if is_constructor(token) => self.bump().unwrap().literal,
+                _ => return Err(self.tokens.expected("variant")),
+            };
+
+            let fields = match self.tokens.curr_is(OpenBrace) || self.tokens.curr_is(OpenParen) {
+                true => self.parse_field_decls()?,
+                false => Vec::new(),
+            };
+
+            variants.push(Variant { ident, fields });
+        }
+
+        Ok(variants)
+    }
+
+    // see `Parser::parse_field_decls`
+    fn parse_field_decls(&mut self) -> Result<Vec<FieldDecl<'a>>> {
+        let close = match self.tokens.curr_is(OpenParen) {
+            true => CloseParen,
+            false => CloseBrace,
+        };
+
+        self.bump();
+        let mut fields = Vec::new();
+
+        while !self.tokens.curr_is(close.clone()) {
+            let name = match self.tokens.curr_is(Identifier) && self.tokens.peek_is(Colon) {
+                true => {
+                    let name = self.bump().unwrap().literal;
+                    self.bump();
+                    Some(name)
+                }
+                false => None,
+            };
+
+            let ty = self.parse_type_primary()?;
+            fields.push(FieldDecl { name, ty });
+
+            if !self.tokens.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        self.tokens.expect(close)?;
+        Ok(fields)
+    }
+
+    fn parse_type_signature(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let token = self.bump().unwrap();
+        let mut generics = Vec::new();
+
+        if self.tokens.curr_is(LessThan) {
+            self.bump();
+            generics = self.parse_generics()?;
+            self.tokens.expect_closing_angle()?;
+        }
+
+        self.tokens.expect(Pipe)?;
+
+        let outer = self.params.len();
+        self.params
+            .extend(generics.iter().map(|generic| generic.ident));
+        let scheme = self.parse_type_scheme();
+        self.params.truncate(outer);
+
+        let (bounded, ty) = scheme?;
+        generics.extend(bounded);
+        let span = self.tokens.span_from(token.span.start);
+        self.tokens.expect_terminator()?;
+
+        // attach the signature to the binding that follows it
+        let binding = match (&self.tokens.curr, &self.tokens.peek) {
+            (Some(curr), Some(peek))
+                if curr.kind == Identifier
+                    && curr.literal == token.literal
+                    && peek.kind == Assign =>
+            {
+                Some(self.parse_ident()?.1)
+            }
+            _ => None,
+        };
+
+        let expr = Expression::Signature {
+            ident: token.literal,
+            generics,
+            ty,
+            binding,
+        };
+        let expr = self.add(expr, span, token_location(&token));
+
+        let docs = self.tokens.take_docs(token.span.start);
+        self.ast.add_docs(expr, docs);
+        Ok((StatementKind::Signature, expr))
+    }
+
+    // a type, optionally preceded by its bounded type parameters:
+    // `P1: Parser<R1>, R1 -> P1 -> Parser<R1>`
+    fn parse_type_scheme(&mut self) -> Result<(Vec<Generic<'a>>, TypeRef)> {
+        let mut params = Vec::new();
+        let mut bounded = false;
+
+        loop {
+            let ty = self.parse_type_primary()?;
+            let mut bounds = Vec::new();
+
+            if self.tokens.curr_is(Colon) {
+                self.bump();
+                bounded = true;
+
+                loop {
+                    bounds.push(self.parse_type_primary()?);
+
+                    if !self.tokens.curr_is(Plus) {
+                        break;
+                    }
+                    self.bump();
+                }
+            }
+
+            params.push((ty, bounds));
+
+            if !self.tokens.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        if !bounded {
+            let params = params.into_iter().map(|(ty, _)| ty).collect();
+            return Ok((Vec::new(), self.parse_type_arrow(params)?));
+        }
+
+        let mut generics = Vec::new();
+
+        for (ty, bounds) in params {
+            match self.ast.get_type_of(ty) {
+                Type::NewType(ident, args) if args.is_empty() => {
+                    generics.push(Generic { ident, bounds })
+                }
+                _ => return Err(self.tokens.expected("type parameter")),
+            }
+        }
+
+        self.tokens.expect(Arrow)?;
+
+        let outer = self.params.len();
+        self.params
+            .extend(generics.iter().map(|generic| generic.ident));
+        let ty = self.parse_type();
+        self.params.truncate(outer);
+
+        Ok((generics, ty?))
+    }
+
+    // `A, B -> C` is a function type, `A, B` on its own is a tuple
+    fn parse_type(&mut self) -> Result<TypeRef> {
+        let mut types = vec![self.parse_type_primary()?];
+
+        while self.tokens.curr_is(Comma) {
+            self.bump();
+            types.push(self.parse_type_primary()?);
+        }
+
+        self.parse_type_arrow(types)
+    }
+
+    fn parse_type_arrow(&mut self, mut types: Vec<TypeRef>) -> Result<TypeRef> {
+        if self.tokens.curr_is(Arrow) {
+            self.bump();
+            let ret = self.parse_type()?;
+
+            // `() -> T` takes no arguments
+            if let [arg] = types[..] {
+                if *self.ast.get_type_of(arg) == Type::Unit {
+                    types.clear();
+                }
+            }
+
+            let args = self.ast.add_type(Type::Tuple(types));
+            return Ok(self.ast.add_type(Type::Function(args, ret)));
+        }
+
+        match types.len() {
+            1 => Ok(types.pop().unwrap()),
+            _ => Ok(self.ast.add_type(Type::Tuple(types))),
+        }
+    }
+
+    fn parse_type_primary(&mut self) -> Result<TypeRef> {
+        if !self.tokens.curr_is(OpenParen)
+            && !self.tokens.curr_is(Identifier)
+            && !self.tokens.curr_is(SelfType)
+        {
+            return Err(self.tokens.expected("type"));
+        }
+
+        let token = self.bump().unwrap();
+
+        match token.kind {
+            OpenParen => {
+                if self.tokens.curr_is(CloseParen) {
+                    self.bump();
+                    return Ok(self.ast.add_type(Type::Unit));
+                }
+
+                let ty = self.parse_type()?;
+                self.tokens.expect(CloseParen)?;
+                Ok(ty)
+            }
+            _ => {
+                let mut end = token.span.end;
+
+                // paths into modules: `format.Formatter`
+                while self.tokens.curr_is(Dot) && self.tokens.peek_is(Identifier) {
+                    self.bump();
+                    end = self.bump().unwrap().span.end;
+                }
+
+                let ident = &self.source[token.span.start..end];
+                let mut args = Vec::new();
+
+                if self.tokens.curr_is(LessThan) {
+                    self.bump();
+
+                    loop {
+                        args.push(self.parse_type_primary()?);
+
+                        if !self.tokens.curr_is(Comma) {
+                            break;
+                        }
+                        self.bump();
+                    }
+
+                    self.tokens.expect_closing_angle()?;
+                }
+
+                let ty = match (ident, args.is_empty()) {
+                    ("Never", true) => Type::Never,
+                    ("Bool", true) => Type::Bool,
+                    ("Int", true) => Type::Int,
+                    ("Float", true) => Type::Float,
+                    ("Char", true) => Type::Char,
+                    ("String", true) => Type::String,
+                    (ident, true) if self.params.contains(&ident) => Type::Param(ident),
+                    _ => Type::NewType(ident, args),
+                };
+
+                Ok(self.ast.add_type(ty))
+            }
+        }
+    }
+
+    fn parse_return(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let (start, location) = (self.tokens.start(), self.location());
+        self.bump();
+        let value = self.parse_expr()?;
+        let span = self.tokens.span_from(start);
+        self.tokens.expect_terminator()?;
+
+        let expr = self.add(Expression::Return(value), span, location);
+        Ok((StatementKind::Return, expr))
+    }
+
+    // see `Parser::parse_break`
+    fn parse_break(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let (start, location) = (self.tokens.start(), self.location());
+        let keyword = self.bump().unwrap();
+        let label = self.tokens.parse_label_use();
+
+        let value = match &self.tokens.curr {
+            Some(token)
+                if keyword.kind == Break && !matches!(token.kind, Semicolon | CloseBrace) =>
+            {
+                Some(self.parse_expr()?)
+            }
+            _ => None,
+        };
+
+        let value_span = value.map(|value| self.span(value));
+        self.tokens
+            .check_loop_exit(&keyword, label.as_ref(), value_span)?;
+        let span = self.tokens.span_from(start);
+        self.tokens.expect_terminator()?;
+
+        let label = label.map(|label| label.literal);
+        let (kind, expr) = match keyword.kind {
+            Break => (StatementKind::Break, Expression::Break { label, value }),
+            _ => (StatementKind::Continue, Expression::Continue(label)),
+        };
+        Ok((kind, self.add(expr, span, location)))
+    }
+
+    // see `Parser::parse_loop`
+    fn parse_loop(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+        let (label, kind) = self.tokens.parse_label()?;
+
+        let expr = match kind {
+            Loop => Expression::Loop {
+                label,
+                body: self.parse_loop_body(label, true)?,
+            },
+            While => {
+                let pattern = match self.tokens.pattern_follows() {
+                    true => {
+                        let pattern = self.parse_pattern()?;
+                        self.tokens.expect(Assign)?;
+                        Some(pattern)
+                    }
+                    false => None,
+                };
+
+                Expression::While {
+                    label,
+                    pattern,
+                    condition: self.parse_head()?,
+                    body: self.parse_loop_body(label, false)?,
+                }
+            }
+            _ => {
+                let pattern = self.parse_pattern()?;
+                self.tokens.expect(In)?;
+
+                Expression::For {
+                    label,
+                    pattern,
+                    iterable: self.parse_head()?,
+                    body: self.parse_loop_body(label, false)?,
+                }
+            }
+        };
+
+        let span = self.tokens.span_from(start);
+        Ok(self.add(expr, span, location))
+    }
+
+    fn parse_head(&mut self) -> Result<ExpressionRef> {
+        self.with_brace_calls(false, Self::parse_expr)
+    }
+
+    fn parse_loop_body(&mut self, label: Option<&'a str>, value: bool) -> Result<ExpressionRef> {
+        self.tokens.loops.push(LoopScope { label, value });
+        let body = self.parse_block();
+        self.tokens.loops.pop();
+        body
+    }
+
+    // see `Parser::parse_if`. An `else if` is kept as the nested `If`, which
+    // converts to the same boxed AST as a block holding just that.
+    fn parse_if(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+        self.bump();
+
+        let condition = self.parse_head()?;
+        let consequent = self.parse_block()?;
+
+        let alternative = match self.tokens.curr_is(Else) {
+            true => {
+                self.bump();
+
+                match self.tokens.curr_is(If) {
+                    true => Some(self.parse_if()?),
+                    false => Some(self.parse_block()?),
+                }
+            }
+            false => None,
+        };
+
+        let expr = Expression::If {
+            condition,
+            consequent,
+            alternative,
+        };
+        let span = self.tokens.span_from(start);
+        Ok(self.add(expr, span, location))
+    }
+
+    // see `Parser::parse_match`
+    fn parse_match(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+        self.bump();
+
+        let matched = self.parse_head()?;
+        self.tokens.expect(OpenBrace)?;
+        let first_arm = self.ast.exprs.len();
+        self.with_brace_calls(true, Self::parse_arms)?;
+        self.tokens.expect(CloseBrace)?;
+
+        let arms = self.ast.run_since(first_arm);
+        let span = self.tokens.span_from(start);
+        Ok(self.add(Expression::Match { matched, arms }, span, location))
+    }
+
+    // see `Parser::parse_arms`
+    fn parse_arms(&mut self) -> Result<()> {
+        while !self.tokens.curr_is(CloseBrace) {
+            let (start, location) = (self.tokens.start(), self.location());
+            let pattern = self.parse_pattern()?;
+
+            let guard = match self.tokens.curr_is(If) {
+                true => {
+                    self.bump();
+                    Some(self.parse_expr()?)
+                }
+                false => None,
+            };
+
+            self.tokens.expect(ArrowRight)?;
+            let body = self.parse_expr()?;
+            let block = is_block_like(self.ast.get_expression(body.root()));
+
+            let arm = Expression::Arm {
+                pattern,
+                guard,
+                body,
+            };
+            let span = self.tokens.span_from(start);
+            self.add(arm, span, location);
+
+            match self.tokens.curr_is(Comma) {
+                true => {
+                    self.bump();
+                }
+                false if block => {}
+                false => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    // see `Parser::parse_pattern`
+    fn parse_pattern(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+        let first = self.parse_pattern_at()?;
+
+        if !self.tokens.curr_is(Pipe) {
+            return Ok(first);
+        }
+
+        while self.tokens.curr_is(Pipe) {
+            self.bump();
+            self.parse_pattern_at()?;
+        }
+
+        let patterns = self.ast.run_since(first.index);
+        let span = self.tokens.span_from(start);
+        Ok(self.add(Expression::Or(patterns), span, location))
+    }
+
+    fn parse_pattern_at(&mut self) -> Result<ExpressionRef> {
+        if !self.tokens.curr_is(Identifier) || !self.tokens.peek_is(At) {
+            return self.parse_pattern_primary();
+        }
+
+        let (start, location) = (self.tokens.start(), self.location());
+        let ident = self.bump().unwrap().literal;
+        self.bump();
+        let pattern = self.parse_pattern_primary()?;
+
+        let span = self.tokens.span_from(start);
+        Ok(self.add(Expression::At { ident, pattern }, span, location))
+    }
+
+    fn parse_pattern_primary(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+
+        let expr = match &self.tokens.curr {
+            Some(token) if is_wildcard(token) => {
+                self.bump();
+                Expression::Dash
+            }
+            Some(token) if is_constructor(token) => {
+                let ident = self.bump().unwrap().literal;
+                let first_field = self.ast.exprs.len();
+                self.parse_field_patterns()?;
+
+                Expression::Constructor {
+                    ident,
+                    fields: self.ast.run_since(first_field),
+                }
+            }
+            Some(token) if token.kind == Identifier => {
+                Expression::Ident(self.bump().unwrap().literal)
+            }
+            Some(token) if token.kind == OpenParen => {
+                self.bump();
+                let first = self.ast.exprs.len();
+                let (mut count, mut tuple) = (0, false);
+
+                while !self.tokens.curr_is(CloseParen) {
+                    self.parse_pattern()?;
+                    count += 1;
+
+                    if !self.tokens.curr_is(Comma) {
+                        break;
+                    }
+                    self.bump();
+                    tuple = true;
+                }
+
+                self.tokens.expect(CloseParen)?;
+                let patterns = self.ast.run_since(first);
+
+                match count {
+                    0 => Expression::Unit,
+                    1 if !tuple => return Ok(patterns),
+                    _ => Expression::Tuple(patterns),
+                }
+            }
+            _ => {
+                let from = self.parse_pattern_literal()?;
+
+                let inclusive = match &self.tokens.curr {
+                    Some(token) if token.kind == DoubleDot => false,
+                    Some(token) if token.kind == DoubleDotEqual => true,
+                    _ => return Ok(from),
+                };
+
+                self.bump();
+                let to = self.parse_pattern_literal()?;
+
+                Expression::Range {
+                    from,
+                    to,
+                    inclusive,
+                }
+            }
+        };
+
+        let span = self.tokens.span_from(start);
+        Ok(self.add(expr, span, location))
+    }
+
+    // see `Parser::parse_field_patterns`
+    fn parse_field_patterns(&mut self) -> Result<()> {
+        let close = match &self.tokens.curr {
+            Some(token) if token.kind == OpenBrace => CloseBrace,
+            Some(token) if token.kind == OpenParen => CloseParen,
+            _ => return Ok(()),
+        };
+
+        self.bump();
+
+        while !self.tokens.curr_is(close.clone()) {
+            if self.tokens.curr_is(Identifier) && self.tokens.peek_is(Assign) {
+                let (start, location) = (self.tokens.start(), self.location());
+                let ident = self.bump().unwrap().literal;
+                self.bump();
+
+                let value = self.parse_pattern()?;
+                let span = self.tokens.span_from(start);
+                self.add(Expression::NamedField { ident, value }, span, location);
+            } else {
+                self.parse_pattern()?;
+            }
+
+            if !self.tokens.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        self.tokens.expect(close)?;
+        Ok(())
+    }
+
+    // see `Parser::parse_pattern_literal`
+    fn parse_pattern_literal(&mut self) -> Result<ExpressionRef> {
+        let negated =
+            self.tokens.curr_is(Minus) && (self.tokens.peek_is(Int) || self.tokens.peek_is(Float));
+
+        match &self.tokens.curr {
+            _ if negated => self.parse_unary(),
+            Some(token) if is_literal(&token.kind) => self.parse_primary(),
+            _ => Err(self.tokens.expected("pattern")),
+        }
+    }
+
+    fn parse_expr_stmt(&mut self) -> Result<(StatementKind, ExpressionRef)> {
+        let expr = self.parse_expr()?;
+        self.tokens.expect_terminator()?;
+        Ok((StatementKind::Expr, expr))
+    }
+
+    fn parse_expr(&mut self) -> Result<ExpressionRef> {
+        if self.tokens.at_function() {
+            return self.parse_function();
+        }
+
+        self.parse_pipeline()
+    }
+
+    // `a : f { b }` is kept as a pipeline, since `a` is already in the arena
+    // by the time the call is parsed
+    fn parse_pipeline(&mut self) -> Result<ExpressionRef> {
+        let mut value = self.parse_range()?;
+
+        while self.tokens.curr_is(Colon) {
+            let location = self.location();
+            self.bump();
+
+            let call = self.parse_postfix_chain(false)?;
+            let span = self.span(value).to(self.span(call));
+            value = self.add(Expression::Pipeline { value, call }, span, location);
+
+            while self.tokens.curr_is(Question) {
+                value = self.parse_try(value)?;
+            }
+        }
+
+        Ok(value)
+    }
+
+    // see `Parser::parse_try`
+    fn parse_try(&mut self, expr: ExpressionRef) -> Result<ExpressionRef> {
+        let location = self.location();
+        let question = self.bump().unwrap();
+        let mut span = self.span(expr).to(question.span);
+
+        let default = match self.tokens.default_follows(self.span(expr), question.span) {
+            true => {
+                let default = self.parse_unary()?;
+                span = span.to(self.span(default));
+                Some(default)
+            }
+            false => None,
+        };
+
+        Ok(self.add(Expression::Try { expr, default }, span, location))
+    }
+
+    fn parse_range(&mut self) -> Result<ExpressionRef> {
+        let from = self.parse_binary(0)?;
+
+        let inclusive = match &self.tokens.curr {
+            Some(token) if token.kind == DoubleDot => false,
+            Some(token) if token.kind == DoubleDotEqual => true,
+            _ => return Ok(from),
+        };
+
+        let location = self.location();
+        self.bump();
+        let to = self.parse_binary(0)?;
+        let span = self.span(from).to(self.span(to));
+
+        let range = Expression::Range {
+            from,
+            to,
+            inclusive,
+        };
+        Ok(self.add(range, span, location))
+    }
+
+    fn parse_function(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+        let parenthesised = self.tokens.curr_is(OpenParen);
+        if parenthesised {
+            self.bump();
+        }
+
+        let first_arg = self.ast.exprs.len();
+
+        while self.tokens.curr_is(Identifier) || self.tokens.curr_is(SelfValue) {
+            let token = self.bump().unwrap();
+            let mutable = self.tokens.curr_is(Mut);
+            if mutable {
+                self.bump();
+            }
+
+            let arg = Expression::Arg {
+                ident: token.literal,
+                mutable,
+            };
+            let span = self.tokens.span_from(token.span.start);
+            self.add(arg, span, token_location(&token));
+
+            if !self.tokens.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        let args = self.ast.run_since(first_arg);
+
+        if parenthesised {
+            self.tokens.expect(CloseParen)?;
+        }
+
+        self.tokens.expect(Arrow)?;
+
+        let loops = std::mem::take(&mut self.tokens.loops);
+        let body = self.parse_expr();
+        self.tokens.loops = loops;
+
+        let body = body?;
+        let span = self.tokens.span_from(start);
+
+        Ok(self.add(Expression::Function { args, body }, span, location))
+    }
+
+    fn parse_block(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+        self.tokens.expect(OpenBrace)?;
+        let first_stmt = self.ast.exprs.len();
+        self.with_brace_calls(true, Self::parse_stmts)?;
+
+        self.bump();
+        let stmts = self.ast.run_since(first_stmt);
+        let span = self.tokens.span_from(start);
+        Ok(self.add(Expression::Block(stmts), span, location))
+    }
+
+    fn parse_stmts(&mut self) -> Result<()> {
+        loop {
+            match &self.tokens.curr {
+                Some(token) if token.kind == CloseBrace => return Ok(()),
+                Some(token) if token.kind == Semicolon => {
+                    self.bump();
+                }
+                Some(_) => {
+                    self.parse_stmt_or_recover();
+                }
+                None => return Err(self.tokens.expected(&CloseBrace.name())),
+            }
+        }
+    }
+
+    // see `Parser::with_brace_calls`
+    fn with_brace_calls<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let brace_calls = std::mem::replace(&mut self.tokens.brace_calls, allowed);
+        let result = parse(self);
+        self.tokens.brace_calls = brace_calls;
+        result
+    }
+
+    // precedence climbing, see `Parser::parse_binary`
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<ExpressionRef> {
+        let mut left = self.parse_unary()?;
+
+        while let Some((operator, precedence)) = self.tokens.curr.as_ref().and_then(binary_operator)
+        {
+            if precedence < min_precedence {
+                break;
+            }
+
+            let location = self.location();
+            self.bump();
+
+            let right = if operator == Operator::Power {
+                self.parse_binary(precedence)?
+            } else {
+                self.parse_binary(precedence + 1)?
+            };
+
+            let span = self.span(left).to(self.span(right));
+            let infix = Expression::Infix {
+                operator,
+                left,
+                right,
+            };
+            left = self.add(infix, span, location);
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<ExpressionRef> {
+        let operator = match self.tokens.curr.as_ref().map(|token| &token.kind) {
+            Some(Minus) => Operator::Minus,
+            Some(Not) => Operator::Not,
+            Some(Tilde) => Operator::BitNot,
+            _ => return self.parse_postfix(),
+        };
+
+        let (start, location) = (self.tokens.start(), self.location());
+        self.bump();
+
+        let operand = self.parse_binary(POWER_PRECEDENCE)?;
+        let span = self.tokens.span_from(start);
+        Ok(self.add(Expression::Prefix { operator, operand }, span, location))
+    }
+
+    fn parse_postfix(&mut self) -> Result<ExpressionRef> {
+        self.parse_postfix_chain(true)
+    }
+
+    // see `Parser::parse_postfix_chain`
+    fn parse_postfix_chain(&mut self, tries: bool) -> Result<ExpressionRef> {
+        let mut expr = self.parse_primary()?;
+
+        let block_like = is_block_like(self.ast.get_expression(expr.root()));
+        let brace_calls = self.tokens.brace_calls && !block_like;
+        let fields = !matches!(
+            self.ast.get_expression(expr.root()),
+            Expression::Int(_) | Expression::Float(_)
+        );
+
+        loop {
+            let close = match &self.tokens.curr {
+                Some(token) if token.kind == OpenBrace && brace_calls => CloseBrace,
+                Some(token) if token.kind == OpenParen => CloseParen,
+                Some(token) if token.kind == Dot && fields => {
+                    expr = self.parse_field(expr)?;
+                    continue;
+                }
+                Some(token) if token.kind == Question && tries => {
+                    expr = self.parse_try(expr)?;
+                    continue;
+                }
+                _ => break,
+            };
+
+            let location = self.location();
+            self.bump();
+
+            let first_arg = self.ast.exprs.len();
+            self.parse_args(close)?;
+            let args = self.ast.run_since(first_arg);
+
+            let span = self.tokens.span_from(self.span(expr).start);
+            expr = self.add(Expression::Call { callee: expr, args }, span, location);
+        }
+
+        Ok(expr)
+    }
+
+    // see `Parser::parse_field`
+    fn parse_field(&mut self, mut target: ExpressionRef) -> Result<ExpressionRef> {
+        let location = self.location();
+        self.bump();
+
+        let token = match &self.tokens.curr {
+            Some(token) if matches!(token.kind, Identifier | Int | Float) => self.bump().unwrap(),
+            _ => return Err(self.tokens.expected("field")),
+        };
+
+        if token.kind == Identifier && token.literal == "try" {
+            let span = self.span(target).to(token.span);
+            let try_ = Expression::Try {
+                expr: target,
+                default: None,
+            };
+            return Ok(self.add(try_, span, location));
+        }
+
+        let fields = match token.kind {
+            Identifier => vec![(Expression::Ident(token.literal), token.span)],
+            _ => tuple_fields(&token)
+                .ok_or_else(|| invalid_tuple_field(&token))?
+                .into_iter()
+                .map(|(field, span)| (Expression::Int(field), span))
+                .collect(),
+        };
+
+        for (field, span) in fields {
+            let field = self.add(field, span, token_location(&token));
+            let span = Span::new(self.span(target).start, span.end);
+            target = self.add(Expression::Field { target, field }, span, location);
+        }
+
+        Ok(target)
+    }
+
+    // see `Parser::parse_struct`
+    fn parse_struct(&mut self) -> Result<ExpressionRef> {
+        let (start, location) = (self.tokens.start(), self.location());
+        let ident = self.bump().unwrap().literal;
+        self.bump();
+        let first_field = self.ast.exprs.len();
+
+        self.with_brace_calls(true, |parser| {
+            while !parser.tokens.curr_is(CloseBrace) {
+                let (start, location) = (parser.tokens.start(), parser.location());
+                let ident = parser.tokens.expect(Identifier)?.literal;
+                parser.tokens.expect(Colon)?;
+
+                let value = parser.parse_expr()?;
+                let span = parser.tokens.span_from(start);
+                parser.add(Expression::NamedField { ident, value }, span, location);
+
+                if !parser.tokens.curr_is(Comma) {
+                    break;
+                }
+                parser.bump();
+            }
+
+            Ok(())
+        })?;
+
+        self.tokens.expect(CloseBrace)?;
+        let fields = self.ast.run_since(first_field);
+        let span = self.tokens.span_from(start);
+        Ok(self.add(Expression::Struct { ident, fields }, span, location))
+    }
+
+    fn parse_args(&mut self, close: super::TokenKind) -> Result<()> {
+        while !self.tokens.curr_is(close.clone()) {
+            self.with_brace_calls(true, Self::parse_expr)?;
+
+            if !self.tokens.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        self.tokens.expect(close)?;
+        Ok(())
+    }
+
+    fn parse_primary(&mut self) -> Result<ExpressionRef> {
+        if self.tokens.curr_is(OpenBrace) {
+            return self.parse_block();
+        }
+
+        if self.tokens.struct_follows() {
+            return self.parse_struct();
+        }
+
+        let token = match &self.tokens.curr {
+            Some(token) => token,
+            None => return Err(self.tokens.expected("expression")),
+        };
+
+        let literal = token.literal;
+
+        let expr = match token.kind {
+            Int => Expression::Int(literal),
+            Float => Expression::Float(literal),
+            Bool => Expression::Bool(literal),
+            Char => Expression::Char(literal),
+            Str => Expression::Str(literal),
+            RawStr => Expression::RawStr(literal),
+            Identifier | SelfValue => Expression::Ident(literal),
+            Template => return self.parse_template(),
+            OpenParen => {
+                let (start, location) = (self.tokens.start(), self.location());
+                self.bump();
+
+                if self.tokens.curr_is(CloseParen) {
+                    self.bump();
+                    let span = self.tokens.span_from(start);
+                    return Ok(self.add(Expression::Unit, span, location));
+                }
+
+                let expr = self.with_brace_calls(true, Self::parse_expr)?;
+
+                if !self.tokens.curr_is(Comma) {
+                    self.tokens.expect(CloseParen)?;
+                    return Ok(expr);
+                }
+
+                return self.parse_tuple(start, location, expr);
+            }
+            Label | Loop | While | For => return self.parse_loop(),
+            If => return self.parse_if(),
+            Match => return self.parse_match(),
+            MissingDigits | InvalidDigit | InvalidSuffix => {
+                return Err(self.tokens.malformed_number())
+            }
+            InvalidEscape => return Err(self.tokens.invalid_escape()),
+            _ => return Err(self.tokens.expected("expression")),
+        };
+
+        let token = self.bump().unwrap();
+        Ok(self.add(expr, token.span, token_location(&token)))
+    }
+
+    // see `Parser::parse_tuple`
+    fn parse_tuple(
+        &mut self,
+        start: usize,
+        location: Location,
+        first: ExpressionRef,
+    ) -> Result<ExpressionRef> {
+        while self.tokens.curr_is(Comma) {
+            self.bump();
+
+            if self.tokens.curr_is(CloseParen) {
+                break;
+            }
+            self.with_brace_calls(true, Self::parse_expr)?;
+        }
+
+        self.tokens.expect(CloseParen)?;
+        let exprs = self.ast.run_since(first.index);
+        let span = self.tokens.span_from(start);
+        Ok(self.add(Expression::Tuple(exprs), span, location))
+    }
+
+    // see `Parser::parse_template`
+    fn parse_template(&mut self) -> Result<ExpressionRef> {
+        let token = self.bump().unwrap();
+        let location = token_location(&token);
+        let parts = scan_string(token.literal).parts;
+        let last = parts.len() - 1;
+        let mut template: Option<ExpressionRef> = None;
+
+        for (i, part) in parts.into_iter().enumerate() {
+            // the last node added is the root, which spans the whole template
+            let root = i == last;
+            let single = root && template.is_none();
+
+            let part = match part {
+                Part::Text(range) => {
+                    let span = text_span(&token, &range);
+                    let text = Expression::Str(&token.literal[range]);
+                    self.add(text, span, location)
+                }
+                Part::Interpolation(range) => {
+                    let span = interpolation_span(&token, &range);
+                    let callee = self.add(Expression::Ident("format"), span, location);
+
+                    let mut parser = self.tokens.interpolation_parser(&token, range);
+                    std::mem::swap(&mut self.tokens, &mut parser);
+                    let value = self.parse_interpolation();
+                    std::mem::swap(&mut self.tokens, &mut parser);
+
+                    let span = if single { token.span } else { span };
+                    let call = Expression::Call {
+                        callee,
+                        args: value?,
+                    };
+                    self.add(call, span, location)
+                }
+            };
+
+            template = Some(match template {
+                Some(left) => {
+                    let span = match root {
+                        true => token.span,
+                        false => self.span(left).to(self.span(part)),
+                    };
+                    let infix = Expression::Infix {
+                        operator: Operator::Plus,
+                        left,
+                        right: part,
+                    };
+                    self.add(infix, span, location)
+                }
+                None => part,
+            });
+        }
+
+        Ok(template.unwrap())
+    }
+
+    fn parse_interpolation(&mut self) -> Result<ExpressionRef> {
+        let expr = self.parse_expr()?;
+
+        match self.tokens.curr {
+            Some(_) => Err(self.tokens.expected(&CloseBrace.name())),
+            None => Ok(expr),
+        }
+    }
+}
+
+// see `super::is_block_like`
+fn is_block_like(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::Block(_)
+            | Expression::If { .. }
+            | Expression::Match { .. }
+            | Expression::Loop { .. }
+            | Expression::While { .. }
+            | Expression::For { .. }
+    )
+}
+
+fn token_location(token: &Token) -> Location {
+    Location {
+        line: token.line,
+        column: token.column,
+    }
+}
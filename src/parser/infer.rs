@@ -0,0 +1,3115 @@
+// Hindley-Milner type inference over the flat AST, see notes/hindley-milner.md
+//
+// This is algorithm J: type variables are solved in place by unification, and
+// each variable records the `let` nesting level it was created at so that
+// generalisation only has to look at the type itself, not the environment.
+
+use super::ast::Operator;
+use super::exhaustive::{check_match, Constructors};
+use super::flat_ast::{
+    Ast, Expression, ExpressionRef, FieldDecl, Generic, Impl, Location, Trait, Type, TypeDecl,
+    TypeDeclKind, TypeRef,
+};
+use super::{Diagnostic, Severity, Span};
+use crate::builtins::{DERIVABLE, IMPLEMENTED};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub severity: Severity,
+    // the code of its diagnostic
+    pub code: &'static str,
+    pub message: String,
+    pub locations: Vec<Location>,
+    // the spans of the same expressions as `locations`
+    pub spans: Vec<Span>,
+}
+
+impl TypeError {
+    // the first expression is the primary label, the others are secondary
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut spans = self.spans.iter();
+        let primary = spans.next().copied().unwrap_or_default();
+        let mut diagnostic = match self.severity {
+            Severity::Error => Diagnostic::error(self.code, self.message.clone(), primary),
+            Severity::Warning => Diagnostic::warning(self.code, self.message.clone(), primary),
+        };
+
+        for span in spans {
+            diagnostic = diagnostic.with_secondary(*span, "");
+        }
+
+        diagnostic
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "Type Error: {}", self.message)?,
+            Severity::Warning => write!(f, "Warning: {}", self.message)?,
+        }
+
+        for (i, location) in self.locations.iter().enumerate() {
+            let separator = if i == 0 { " at " } else { ", " };
+            write!(f, "{}{}:{}", separator, location.line, location.column)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn infer(ast: &Ast<'_>) -> (HashMap<ExpressionRef, TypeRef>, Vec<TypeError>) {
+    let mut infer = Infer::new(ast);
+
+    for stmt in &ast.prelude {
+        infer.infer(*stmt);
+    }
+
+    infer.declare_functions();
+
+    for stmt in ast.statements() {
+        infer.infer(stmt.expr());
+        infer.define_function(stmt.expr());
+    }
+
+    infer.check_tries(0);
+    infer.check_operands();
+    infer.check_constraints(0);
+    infer.finish()
+}
+
+// A type with some of its variables quantified. Signatures quantify all of
+// their type parameters instead, which the types they are instantiated with
+// have to satisfy the bounds of.
+#[derive(Clone)]
+struct Scheme<'a> {
+    vars: Vec<usize>,
+    params: bool,
+    bounds: Vec<(&'a str, TypeRef)>,
+    variadic: bool,
+    ty: TypeRef,
+}
+
+impl Scheme<'_> {
+    fn mono(ty: TypeRef) -> Self {
+        Scheme {
+            vars: Vec::new(),
+            params: false,
+            bounds: Vec::new(),
+            variadic: false,
+            ty,
+        }
+    }
+}
+
+enum UnifyError {
+    Mismatch,
+    Occurs,
+}
+
+// A method declared by a trait, with its type in terms of `Self`
+#[derive(Clone)]
+struct TraitMethod<'a> {
+    ident: &'a str,
+    scheme: Scheme<'a>,
+    // whether the impls may leave it out
+    default: bool,
+}
+
+// what `target.name` refers to
+enum Member {
+    Field(TypeRef),
+    // a method, whose first argument is the target
+    Method(TypeRef),
+}
+
+struct Infer<'a, 'ast> {
+    ast: &'ast Ast<'a>,
+    // the solution of each type variable, if it has been found yet
+    vars: Vec<Option<TypeRef>>,
+    levels: Vec<usize>,
+    level: usize,
+    env: Vec<(&'a str, Scheme<'a>)>,
+    // the top-level functions not bound yet, which those bound before them
+    // may call
+    forward: Vec<(&'a str, TypeRef)>,
+    // the declared types, with the type their values have in terms of their
+    // parameters
+    decls: HashMap<&'a str, (&'ast TypeDecl<'a>, TypeRef)>,
+    constructors: Constructors<'ast, 'a>,
+    // the methods of each type, by the name of the type as in `type_key`. The
+    // methods of `Self` are those of the trait being checked.
+    methods: HashMap<(&'a str, &'a str), Scheme<'a>>,
+    // the type parameters and the methods of each trait
+    traits: HashMap<&'a str, (Vec<&'a str>, Vec<TraitMethod<'a>>)>,
+    // the impl of each trait for each type, by their names. Derived impls and
+    // those of the builtin types have none.
+    impls: HashMap<(&'a str, &'a str), Option<ExpressionRef>>,
+    // the return type of each enclosing function
+    returns: Vec<TypeRef>,
+    // the `?`s whose value wasn't known to be an `Option` or a `Result` yet,
+    // with the number of enclosing functions and the types of the value and of
+    // what the `?` gives, checked once the enclosing function is inferred
+    tries: Vec<(
+        usize,
+        TypeRef,
+        TypeRef,
+        ExpressionRef,
+        Option<(TypeRef, ExpressionRef)>,
+    )>,
+    // the label and the type of the `break` values of each enclosing loop
+    loops: Vec<(Option<&'a str>, TypeRef)>,
+    // arithmetic operands, checked once all the variables are solved
+    operands: Vec<(Operator, TypeRef, ExpressionRef)>,
+    // the bounds of the type parameters in scope
+    bounds: Vec<(&'a str, TypeRef)>,
+    // the traits that types have to implement, checked once their variables
+    // are solved and while the bounds of their type parameters are in scope
+    constraints: Vec<(TypeRef, &'a str, ExpressionRef)>,
+    expr_types: Vec<(ExpressionRef, TypeRef)>,
+    errors: Vec<TypeError>,
+    unit: TypeRef,
+    bool: TypeRef,
+    int: TypeRef,
+    float: TypeRef,
+    char: TypeRef,
+    string: TypeRef,
+}
+
+impl<'a, 'ast> Infer<'a, 'ast> {
+    fn new(ast: &'ast Ast<'a>) -> Self {
+        let add = |ty| {
+            ast.types.push(ty);
+            TypeRef {
+                index: ast.types.len() - 1,
+            }
+        };
+
+        let mut infer = Infer {
+            ast,
+            vars: Vec::new(),
+            levels: Vec::new(),
+            level: 0,
+            env: Vec::new(),
+            forward: Vec::new(),
+            decls: HashMap::new(),
+            constructors: HashMap::new(),
+            methods: HashMap::new(),
+            traits: HashMap::new(),
+            impls: HashMap::new(),
+            returns: Vec::new(),
+            tries: Vec::new(),
+            loops: Vec::new(),
+            operands: Vec::new(),
+            bounds: Vec::new(),
+            constraints: Vec::new(),
+            expr_types: Vec::new(),
+            errors: Vec::new(),
+            unit: add(Type::Unit),
+            bool: add(Type::Bool),
+            int: add(Type::Int),
+            float: add(Type::Float),
+            char: add(Type::Char),
+            string: add(Type::String),
+        };
+
+        infer.define_builtins();
+        infer.define_derivable();
+        infer
+    }
+
+    fn define_builtins(&mut self) {
+        self.level += 1;
+
+        for (name, ret) in [
+            ("print", self.unit),
+            ("println", self.unit),
+            ("format", self.string),
+        ] {
+            let ty = self.function(vec![], ret);
+            let scheme = Scheme {
+                variadic: true,
+                ..Scheme::mono(ty)
+            };
+            self.env.push((name, scheme));
+        }
+
+        // the collections have methods rather than functions, since each has
+        // its own element type, and `Option` and `Result` have their own `map`
+        let a = self.fresh();
+        let array = self.add(Type::NewType("Array", vec![a]));
+        let range = self.add(Type::NewType("Range", Vec::new()));
+
+        for (key, receiver, element) in [
+            ("Array", array, a),
+            ("Range", range, self.int),
+            ("String", self.string, self.char),
+        ] {
+            let b = self.fresh();
+            let mapper = self.function(vec![element], b);
+            let mapped = self.add(Type::NewType("Array", vec![b]));
+            let map = self.function(vec![receiver, mapper], mapped);
+            self.methods.insert((key, "map"), self.generalize(map));
+
+            let predicate = self.function(vec![element], self.bool);
+            let filtered = self.add(Type::NewType("Array", vec![element]));
+            let filter = self.function(vec![receiver, predicate], filtered);
+            self.methods
+                .insert((key, "filter"), self.generalize(filter));
+
+            let len = self.function(vec![receiver], self.int);
+            self.methods.insert((key, "len"), self.generalize(len));
+        }
+
+        // the sum has the type of the elements, which are numbers
+        for (key, receiver, element) in [("Array", array, a), ("Range", range, self.int)] {
+            let sum = self.function(vec![receiver], element);
+            self.methods.insert((key, "sum"), self.generalize(sum));
+        }
+
+        self.level -= 1;
+    }
+
+    // the traits that can be derived, and their impls for the builtin types
+    fn define_derivable(&mut self) {
+        let receiver = self.add(Type::Param("Self"));
+
+        for (trait_, method, _) in DERIVABLE {
+            let method = TraitMethod {
+                ident: method,
+                scheme: Scheme {
+                    params: true,
+                    ..Scheme::mono(self.derived_method(method, receiver))
+                },
+                default: false,
+            };
+            self.traits.insert(trait_, (Vec::new(), vec![method]));
+        }
+
+        for (key, traits) in IMPLEMENTED {
+            self.level += 1;
+            let receiver = match key {
+                "Unit" => self.unit,
+                "Int" => self.int,
+                "Float" => self.float,
+                "Bool" => self.bool,
+                "Char" => self.char,
+                "String" => self.string,
+                "Array" => {
+                    let element = self.fresh();
+                    self.add(Type::NewType("Array", vec![element]))
+                }
+                _ => self.fresh(),
+            };
+            self.level -= 1;
+
+            for trait_ in traits {
+                self.derive(trait_, key, receiver);
+            }
+        }
+    }
+
+    // the type of the method of a derivable trait for values of type `receiver`
+    fn derived_method(&self, method: &str, receiver: TypeRef) -> TypeRef {
+        match method {
+            "format" => self.function(vec![receiver], self.string),
+            "eq" => self.function(vec![receiver, receiver], self.bool),
+            "cmp" => self.function(vec![receiver, receiver], self.int),
+            "hash" => self.function(vec![receiver], self.int),
+            _ => self.function(vec![receiver], receiver),
+        }
+    }
+
+    // implement a derivable trait for the type with the name `key`
+    fn derive(&mut self, trait_: &'a str, key: &'a str, receiver: TypeRef) {
+        let Some(&(_, method, _)) = DERIVABLE.iter().find(|(ident, ..)| *ident == trait_) else {
+            return;
+        };
+
+        let ty = self.derived_method(method, receiver);
+        let scheme = Scheme {
+            params: true,
+            ..self.generalize(ty)
+        };
+        self.methods.insert((key, method), scheme);
+        self.impls.insert((trait_, key), None);
+    }
+
+    // whether the values of `ty` implement `trait_`. Tuples and arrays do if
+    // their elements do, type parameters if a bound in scope says so, and
+    // unknown types are assumed to.
+    fn implements(&self, trait_: &str, ty: TypeRef) -> bool {
+        let ty = self.resolve(ty);
+        let elements = match self.get(ty) {
+            Type::Var(_) | Type::Never => return true,
+            Type::Param(param) => {
+                return self.bounds.iter().any(|(bounded, bound)| {
+                    bounded == param && self.bound_trait(*bound) == Some(trait_)
+                })
+            }
+            Type::Tuple(types) => types.as_slice(),
+            Type::NewType("Array", types) => types.as_slice(),
+            _ => &[],
+        };
+
+        let implemented = self
+            .type_key(ty)
+            .is_some_and(|key| self.impls.contains_key(&(trait_, key)));
+        implemented && elements.iter().all(|ty| self.implements(trait_, *ty))
+    }
+
+    fn finish(mut self) -> (HashMap<ExpressionRef, TypeRef>, Vec<TypeError>) {
+        let expr_types = std::mem::take(&mut self.expr_types)
+            .into_iter()
+            .map(|(expr, ty)| (expr, self.zonk(ty)))
+            .collect();
+
+        (expr_types, self.errors)
+    }
+
+    fn add(&self, ty: Type<'a>) -> TypeRef {
+        self.ast.types.push(ty);
+        TypeRef {
+            index: self.ast.types.len() - 1,
+        }
+    }
+
+    fn get(&self, ty: TypeRef) -> &'ast Type<'a> {
+        let ast = self.ast;
+        &ast.types[ty.index]
+    }
+
+    fn function(&self, args: Vec<TypeRef>, ret: TypeRef) -> TypeRef {
+        let args = self.add(Type::Tuple(args));
+        self.add(Type::Function(args, ret))
+    }
+
+    fn fresh(&mut self) -> TypeRef {
+        let var = self.vars.len();
+        self.vars.push(None);
+        self.levels.push(self.level);
+        self.add(Type::Var(var))
+    }
+
+    fn location(&self, expr: ExpressionRef) -> Location {
+        self.ast.get_location(expr.root())
+    }
+
+    fn error(&mut self, message: String, exprs: &[ExpressionRef]) {
+        self.report(Severity::Error, "type-error", message, exprs);
+    }
+
+    fn warning(&mut self, message: String, exprs: &[ExpressionRef]) {
+        self.report(Severity::Warning, "type-warning", message, exprs);
+    }
+
+    // an error of its own kind, since the REPL lets a line refer to the
+    // bindings of the lines after it
+    fn unknown_identifier(&mut self, ident: &str, expr: ExpressionRef) {
+        let message = format!("unknown identifier `{}`", ident);
+        self.report(Severity::Error, "unknown-identifier", message, &[expr]);
+    }
+
+    fn report(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        message: String,
+        exprs: &[ExpressionRef],
+    ) {
+        let locations = exprs.iter().map(|expr| self.location(*expr)).collect();
+        let spans = exprs
+            .iter()
+            .map(|expr| self.ast.get_span(expr.root()))
+            .collect();
+
+        self.errors.push(TypeError {
+            severity,
+            code,
+            message,
+            locations,
+            spans,
+        });
+    }
+
+    fn lookup(&self, ident: &str) -> Option<Scheme<'a>> {
+        match self.env.iter().rev().find(|(name, _)| *name == ident) {
+            Some((_, scheme)) => Some(scheme.clone()),
+            None => self
+                .forward
+                .iter()
+                .find(|(name, _)| *name == ident)
+                .map(|(_, ty)| Scheme::mono(*ty)),
+        }
+    }
+
+    // follow solved variables until reaching a type that isn't one
+    fn resolve(&self, mut ty: TypeRef) -> TypeRef {
+        while let Type::Var(var) = self.get(ty) {
+            match self.vars[*var] {
+                Some(solution) => ty = solution,
+                None => break,
+            }
+        }
+
+        ty
+    }
+
+    fn unify(&mut self, left: TypeRef, right: TypeRef) -> Result<(), UnifyError> {
+        let (left, right) = (self.resolve(left), self.resolve(right));
+
+        if left == right {
+            return Ok(());
+        }
+
+        match (self.get(left), self.get(right)) {
+            (Type::Var(var), _) => self.solve(*var, right),
+            (_, Type::Var(var)) => self.solve(*var, left),
+            (Type::Unit, Type::Unit)
+            | (Type::Never, Type::Never)
+            | (Type::Bool, Type::Bool)
+            | (Type::Int, Type::Int)
+            | (Type::Float, Type::Float)
+            | (Type::Char, Type::Char)
+            | (Type::String, Type::String) => Ok(()),
+            (Type::Param(left), Type::Param(right)) if left == right => Ok(()),
+            (Type::Tuple(left), Type::Tuple(right)) if left.len() == right.len() => {
+                for (left, right) in left.iter().zip(right) {
+                    self.unify(*left, *right)?;
+                }
+                Ok(())
+            }
+            (Type::Function(left_args, left_ret), Type::Function(right_args, right_ret)) => {
+                self.unify(*left_args, *right_args)?;
+                self.unify(*left_ret, *right_ret)
+            }
+            (Type::NewType(left, left_args), Type::NewType(right, right_args))
+                if left == right && left_args.len() == right_args.len() =>
+            {
+                for (left, right) in left_args.iter().zip(right_args) {
+                    self.unify(*left, *right)?;
+                }
+                Ok(())
+            }
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    fn solve(&mut self, var: usize, ty: TypeRef) -> Result<(), UnifyError> {
+        if self.occurs(var, self.levels[var], ty) {
+            return Err(UnifyError::Occurs);
+        }
+
+        self.vars[var] = Some(ty);
+        Ok(())
+    }
+
+    // check that `var` doesn't occur in `ty`, and pull the variables of `ty`
+    // down to the level of `var` so they aren't generalised too early
+    fn occurs(&mut self, var: usize, level: usize, ty: TypeRef) -> bool {
+        let ty = self.resolve(ty);
+
+        match self.get(ty) {
+            Type::Var(other) if *other == var => true,
+            Type::Var(other) => {
+                self.levels[*other] = self.levels[*other].min(level);
+                false
+            }
+            Type::Tuple(types) | Type::NewType(_, types) => {
+                types.iter().any(|ty| self.occurs(var, level, *ty))
+            }
+            Type::Function(args, ret) => {
+                self.occurs(var, level, *args) || self.occurs(var, level, *ret)
+            }
+            _ => false,
+        }
+    }
+
+    fn generalize(&self, ty: TypeRef) -> Scheme<'a> {
+        let mut vars = Vec::new();
+        self.free_vars(ty, &mut vars);
+
+        Scheme {
+            vars,
+            ..Scheme::mono(ty)
+        }
+    }
+
+    fn free_vars(&self, ty: TypeRef, vars: &mut Vec<usize>) {
+        let ty = self.resolve(ty);
+
+        match self.get(ty) {
+            Type::Var(var) if self.levels[*var] > self.level && !vars.contains(var) => {
+                vars.push(*var)
+            }
+            Type::Tuple(types) | Type::NewType(_, types) => {
+                for ty in types {
+                    self.free_vars(*ty, vars);
+                }
+            }
+            Type::Function(args, ret) => {
+                self.free_vars(*args, vars);
+                self.free_vars(*ret, vars);
+            }
+            _ => {}
+        }
+    }
+
+    // a fresh copy of the type of `scheme`, whose bounds the types its
+    // parameters are instantiated with have to satisfy at `expr`
+    fn instantiate(&mut self, scheme: &Scheme<'a>, expr: ExpressionRef) -> TypeRef {
+        if scheme.vars.is_empty() && !scheme.params {
+            return scheme.ty;
+        }
+
+        let mut vars = HashMap::new();
+        let mut params = HashMap::new();
+        let ty = self.copy(scheme, scheme.ty, &mut vars, &mut params);
+        self.require(&scheme.bounds, &params, expr);
+        ty
+    }
+
+    // the types in `params` have to implement the traits that bound them
+    fn require(
+        &mut self,
+        bounds: &[(&'a str, TypeRef)],
+        params: &HashMap<&'a str, TypeRef>,
+        expr: ExpressionRef,
+    ) {
+        for (param, bound) in bounds {
+            if let (Some(&ty), Some(trait_)) = (params.get(param), self.bound_trait(*bound)) {
+                self.constraints.push((ty, trait_, expr));
+            }
+        }
+    }
+
+    // the name of the trait of a bound: `Parser` for `Parser<R>`
+    fn bound_trait(&self, bound: TypeRef) -> Option<&'a str> {
+        match self.get(bound) {
+            Type::NewType(ident, _) => Some(ident),
+            _ => None,
+        }
+    }
+
+    // the bounds of `generics`, as `(param, bound)`
+    fn generic_bounds(&self, generics: &[Generic<'a>]) -> Vec<(&'a str, TypeRef)> {
+        generics
+            .iter()
+            .flat_map(|generic| generic.bounds.iter().map(|bound| (generic.ident, *bound)))
+            .collect()
+    }
+
+    // check that the traits in `bounds` exist, reporting each unknown one once
+    fn check_bounds(&mut self, bounds: &[(&'a str, TypeRef)], expr: ExpressionRef) {
+        let mut unknown = Vec::new();
+
+        for (_, bound) in bounds {
+            match self.bound_trait(*bound) {
+                Some(trait_) if self.traits.contains_key(trait_) => {}
+                Some(trait_) if unknown.contains(&trait_) => {}
+                Some(trait_) => {
+                    unknown.push(trait_);
+                    self.error(format!("unknown trait `{}`", trait_), &[expr]);
+                }
+                None => {
+                    let bound = self.show(*bound, &mut HashMap::new());
+                    self.error(format!("`{}` is not a trait", bound), &[expr]);
+                }
+            }
+        }
+    }
+
+    // run `f` with `bounds` in scope, and check the constraints that arise in
+    // it while they are
+    fn with_bounds<T>(
+        &mut self,
+        bounds: Vec<(&'a str, TypeRef)>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let (scope, pending) = (self.bounds.len(), self.constraints.len());
+        self.bounds.extend(bounds);
+        let result = f(self);
+        self.check_constraints(pending);
+        self.bounds.truncate(scope);
+        result
+    }
+
+    fn check_constraints(&mut self, from: usize) {
+        for (ty, trait_, expr) in self.constraints.split_off(from) {
+            if !self.implements(trait_, ty) {
+                let ty = self.show(ty, &mut HashMap::new());
+                self.error(format!("`{}` doesn't implement `{}`", ty, trait_), &[expr]);
+            }
+        }
+    }
+
+    fn copy(
+        &mut self,
+        scheme: &Scheme<'a>,
+        ty: TypeRef,
+        vars: &mut HashMap<usize, TypeRef>,
+        params: &mut HashMap<&'a str, TypeRef>,
+    ) -> TypeRef {
+        let ty = self.resolve(ty);
+
+        match self.get(ty) {
+            Type::Var(var) if scheme.vars.contains(var) => {
+                *vars.entry(*var).or_insert_with(|| self.fresh())
+            }
+            Type::Param(param) if scheme.params => {
+                *params.entry(*param).or_insert_with(|| self.fresh())
+            }
+            Type::Tuple(types) => {
+                let types = types
+                    .iter()
+                    .map(|ty| self.copy(scheme, *ty, vars, params))
+                    .collect();
+                self.add(Type::Tuple(types))
+            }
+            Type::NewType(ident, types) => {
+                let types = types
+                    .iter()
+                    .map(|ty| self.copy(scheme, *ty, vars, params))
+                    .collect();
+                self.add(Type::NewType(ident, types))
+            }
+            Type::Function(args, ret) => {
+                let args = self.copy(scheme, *args, vars, params);
+                let ret = self.copy(scheme, *ret, vars, params);
+                self.add(Type::Function(args, ret))
+            }
+            _ => ty,
+        }
+    }
+
+    // `ty` with the type parameters in `params` replaced, and the others kept
+    fn replace(&self, ty: TypeRef, params: &HashMap<&'a str, TypeRef>) -> TypeRef {
+        let ty = self.resolve(ty);
+
+        match self.get(ty) {
+            Type::Param(param) => params.get(param).copied().unwrap_or(ty),
+            Type::Tuple(types) => {
+                let types = types.iter().map(|ty| self.replace(*ty, params)).collect();
+                self.add(Type::Tuple(types))
+            }
+            Type::NewType(ident, types) => {
+                let types = types.iter().map(|ty| self.replace(*ty, params)).collect();
+                self.add(Type::NewType(ident, types))
+            }
+            Type::Function(args, ret) => {
+                let (args, ret) = (self.replace(*args, params), self.replace(*ret, params));
+                self.add(Type::Function(args, ret))
+            }
+            _ => ty,
+        }
+    }
+
+    // replace every solved variable with its solution
+    fn zonk(&self, ty: TypeRef) -> TypeRef {
+        let ty = self.resolve(ty);
+
+        match self.get(ty) {
+            Type::Tuple(types) => {
+                let types = types.iter().map(|ty| self.zonk(*ty)).collect();
+                self.add(Type::Tuple(types))
+            }
+            Type::NewType(ident, types) => {
+                let types = types.iter().map(|ty| self.zonk(*ty)).collect();
+                self.add(Type::NewType(ident, types))
+            }
+            Type::Function(args, ret) => {
+                let (args, ret) = (self.zonk(*args), self.zonk(*ret));
+                self.add(Type::Function(args, ret))
+            }
+            _ => ty,
+        }
+    }
+
+    fn expect(&mut self, expected: TypeRef, found: TypeRef, exprs: &[ExpressionRef]) {
+        match self.unify(expected, found) {
+            Ok(()) => {}
+            Err(UnifyError::Mismatch) => {
+                let (expected, found) = self.show_pair(expected, found);
+                self.error(format!("expected `{}`, found `{}`", expected, found), exprs);
+            }
+            Err(UnifyError::Occurs) => {
+                let (expected, found) = self.show_pair(expected, found);
+                self.error(
+                    format!(
+                        "cannot construct the infinite type `{} = {}`",
+                        expected, found
+                    ),
+                    exprs,
+                );
+            }
+        }
+    }
+
+    fn infer(&mut self, expr: ExpressionRef) -> TypeRef {
+        let ty = self.infer_expression(expr);
+        self.expr_types.push((expr, ty));
+        ty
+    }
+
+    fn infer_expression(&mut self, expr: ExpressionRef) -> TypeRef {
+        let ast = self.ast;
+
+        match ast.get_expression(expr.root()) {
+            Expression::Unit => self.unit,
+            Expression::Dash => self.fresh(),
+            Expression::Int(_) => self.int,
+            Expression::Float(_) => self.float,
+            Expression::Bool(_) => self.bool,
+            Expression::Char(_) => self.char,
+            Expression::Str(_) | Expression::RawStr(_) => self.string,
+            Expression::Ident(ident) => match self.lookup(ident) {
+                Some(scheme) => self.instantiate(&scheme, expr),
+                None => {
+                    self.unknown_identifier(ident, expr);
+                    self.fresh()
+                }
+            },
+            Expression::Prefix { operator, operand } => {
+                let ty = self.infer(*operand);
+
+                match operator {
+                    Operator::Not => self.expect(self.bool, ty, &[*operand]),
+                    Operator::BitNot => self.expect(self.int, ty, &[*operand]),
+                    operator => self.operands.push((*operator, ty, *operand)),
+                }
+
+                ty
+            }
+            Expression::Infix {
+                operator,
+                left,
+                right,
+            } => {
+                let left_ty = self.infer(*left);
+                let right_ty = self.infer(*right);
+                self.infer_operator(*operator, (left_ty, *left), (right_ty, *right))
+            }
+            Expression::If {
+                condition,
+                consequent,
+                alternative,
+            } => {
+                let condition_ty = self.infer(*condition);
+                self.expect(self.bool, condition_ty, &[*condition]);
+
+                let consequent_ty = self.infer(*consequent);
+
+                match alternative {
+                    Some(alternative) => {
+                        let alternative_ty = self.infer(*alternative);
+                        self.expect(consequent_ty, alternative_ty, &[*consequent, *alternative]);
+                        consequent_ty
+                    }
+                    None => self.unit,
+                }
+            }
+            Expression::Function { args, body } => self.infer_function(*args, *body, &[]),
+            Expression::Call { callee, args } => {
+                let args = ast.children(*args);
+                let mut arg_types = args.iter().map(|arg| (*arg, self.infer(*arg))).collect();
+                let callee_ty = self.infer_callee(*callee, &mut arg_types);
+                self.infer_call((*callee, callee_ty), arg_types)
+            }
+            Expression::Pipeline { value, call } => {
+                let value_ty = self.infer(*value);
+
+                // `value : f { a }` is `f { value, a }` and `value : f` is `f { value }`
+                let (callee, args) = match ast.get_expression(call.root()) {
+                    Expression::Call { callee, args } => (*callee, ast.children(*args)),
+                    _ => (*call, Vec::new()),
+                };
+
+                let mut arg_types = vec![(*value, value_ty)];
+                arg_types.extend(args.iter().map(|arg| (*arg, self.infer(*arg))));
+                let callee_ty = self.infer_callee(callee, &mut arg_types);
+
+                let ty = self.infer_call((callee, callee_ty), arg_types);
+                if callee != *call {
+                    self.expr_types.push((*call, ty));
+                }
+                ty
+            }
+            Expression::Binding { ident, value } => {
+                self.infer_binding(ident, *value);
+                self.unit
+            }
+            Expression::Assign {
+                ident,
+                operator,
+                value,
+            } => {
+                let value_ty = self.infer(*value);
+
+                match self.lookup(ident) {
+                    Some(scheme) => {
+                        let ty = self.instantiate(&scheme, expr);
+                        let result = self.infer_operator(*operator, (ty, expr), (value_ty, *value));
+                        self.expect(ty, result, &[expr, *value]);
+                    }
+                    None => self.unknown_identifier(ident, expr),
+                }
+
+                self.unit
+            }
+            Expression::Return(value) => {
+                let ty = self.infer(*value);
+
+                if let Some(ret) = self.returns.last().copied() {
+                    self.expect(ret, ty, &[*value]);
+                }
+
+                self.fresh()
+            }
+            Expression::Signature {
+                ident,
+                generics,
+                ty,
+                binding,
+            } => {
+                let bounds = self.generic_bounds(generics);
+                self.check_bounds(&bounds, expr);
+                let scheme = Scheme {
+                    params: true,
+                    bounds: bounds.clone(),
+                    ..Scheme::mono(*ty)
+                };
+                self.env.push((ident, scheme));
+
+                if let Some(binding) = binding {
+                    if let Expression::Binding { value, .. } = ast.get_expression(binding.root()) {
+                        // the value may rely on the bounds of the signature
+                        self.with_bounds(bounds, |infer| {
+                            infer.level += 1;
+                            let value_ty = infer.infer_declared(*value, *ty);
+                            infer.level -= 1;
+                            infer.check_declared(ident, *ty, value_ty, *value, expr);
+                        });
+                    }
+
+                    self.expr_types.push((*binding, self.unit));
+                }
+
+                self.unit
+            }
+            Expression::Block(stmts) => {
+                let scope = self.env.len();
+                let mut ty = self.unit;
+
+                for stmt in ast.children(*stmts) {
+                    ty = self.infer(stmt);
+                }
+
+                self.env.truncate(scope);
+                ty
+            }
+            Expression::Loop { label, body } => {
+                let ty = self.fresh();
+                self.infer_loop_body(*label, ty, *body);
+                ty
+            }
+            Expression::While {
+                label,
+                pattern,
+                condition,
+                body,
+            } => {
+                let scope = self.env.len();
+                let condition_ty = self.infer(*condition);
+
+                match pattern {
+                    Some(pattern) => self.infer_pattern(*pattern, condition_ty),
+                    None => self.expect(self.bool, condition_ty, &[*condition]),
+                }
+
+                self.infer_loop_body(*label, self.unit, *body);
+                self.env.truncate(scope);
+                self.unit
+            }
+            Expression::For {
+                label,
+                pattern,
+                iterable,
+                body,
+            } => {
+                let scope = self.env.len();
+                let iterable_ty = self.infer(*iterable);
+                let element_ty = self.element_type(iterable_ty);
+                self.infer_pattern(*pattern, element_ty);
+
+                self.infer_loop_body(*label, self.unit, *body);
+                self.env.truncate(scope);
+                self.unit
+            }
+            Expression::Break { label, value } => {
+                let ty = match value {
+                    Some(value) => self.infer(*value),
+                    None => self.unit,
+                };
+
+                // the parser has checked that the loop exists
+                let target = self
+                    .loops
+                    .iter()
+                    .rev()
+                    .find(|(target, _)| label.is_none() || target == label);
+
+                if let Some((_, loop_ty)) = target.copied() {
+                    let exprs = match value {
+                        Some(value) => vec![*value],
+                        None => vec![expr],
+                    };
+                    self.expect(loop_ty, ty, &exprs);
+                }
+
+                self.fresh()
+            }
+            Expression::Continue(_) => self.fresh(),
+            Expression::Range { from, to, .. } => {
+                for bound in [from, to] {
+                    let ty = self.infer(*bound);
+                    self.expect(self.int, ty, &[*bound]);
+                }
+
+                self.add(Type::NewType("Range", Vec::new()))
+            }
+            Expression::Tuple(exprs) => {
+                let types = ast
+                    .children(*exprs)
+                    .into_iter()
+                    .map(|expr| self.infer(expr))
+                    .collect();
+                self.add(Type::Tuple(types))
+            }
+            Expression::Match { matched, arms } => {
+                let matched_ty = self.infer(*matched);
+                let ty = self.fresh();
+                let arms = ast.children(*arms);
+
+                for arm in &arms {
+                    self.infer_arm(*arm, matched_ty, ty);
+                }
+
+                let check = check_match(ast, &self.constructors, &arms);
+                if !check.exhaustive {
+                    self.warning("`match` is not exhaustive".to_owned(), &[*matched]);
+                }
+                for arm in check.unreachable {
+                    self.warning("unreachable arm".to_owned(), &[arm]);
+                }
+
+                ty
+            }
+            Expression::TypeDecl(decl) => {
+                self.define_type(expr, decl);
+                self.unit
+            }
+            Expression::Trait(declared) => {
+                self.infer_trait(declared);
+                self.unit
+            }
+            Expression::Impl(implemented) => {
+                self.infer_impl(expr, implemented);
+                self.unit
+            }
+            Expression::Struct { ident, fields } => self.infer_struct(expr, ident, *fields),
+            Expression::Field { target, field } => {
+                let target_ty = self.infer(*target);
+
+                match self.infer_member(*target, target_ty, *field) {
+                    Member::Field(ty) => ty,
+                    Member::Method(ty) => self.bind_receiver(expr, target_ty, ty),
+                }
+            }
+            Expression::Try {
+                expr: value,
+                default,
+            } => self.infer_try(*value, *default),
+            // the syntax error has already been reported
+            Expression::Error(_) => self.fresh(),
+            expr_kind => {
+                self.error(
+                    format!("cannot infer the type of {:?} yet", expr_kind),
+                    &[expr],
+                );
+                self.fresh()
+            }
+        }
+    }
+
+    // `value?` is the type inside the `Option` or the `Result`, and returns
+    // the rest of it, or the default, from the enclosing function
+    fn infer_try(&mut self, value: ExpressionRef, default: Option<ExpressionRef>) -> TypeRef {
+        let ty = self.infer(value);
+        let default = default.map(|default| (self.infer(default), default));
+        let inner = self.fresh();
+
+        match self.get(self.resolve(ty)) {
+            Type::Var(_) => self
+                .tries
+                .push((self.returns.len(), ty, inner, value, default)),
+            _ => self.check_try(ty, inner, value, default),
+        }
+
+        inner
+    }
+
+    // check a `?` whose value has type `ty` and gives `inner`. Once the enclosing
+    // function has been inferred, a value of unknown type is taken to be of the
+    // kind the function returns.
+    fn check_try(
+        &mut self,
+        ty: TypeRef,
+        inner: TypeRef,
+        value: ExpressionRef,
+        default: Option<(TypeRef, ExpressionRef)>,
+    ) {
+        let ret = self.returns.last().copied();
+
+        let rest = match self.get(self.resolve(ty)).clone() {
+            Type::NewType("Option", args) if args.len() == 1 => {
+                self.expect(args[0], inner, &[value]);
+                let rest = self.fresh();
+                self.add(Type::NewType("Option", vec![rest]))
+            }
+            Type::NewType("Result", args) if args.len() == 2 => {
+                self.expect(args[0], inner, &[value]);
+                let rest = self.fresh();
+                self.add(Type::NewType("Result", vec![rest, args[1]]))
+            }
+            Type::Var(_) => {
+                let container = ret.map(|ret| self.get(self.resolve(ret)).clone());
+                let known = match container {
+                    Some(Type::NewType("Option", args)) if args.len() == 1 => {
+                        Some(Type::NewType("Option", vec![inner]))
+                    }
+                    Some(Type::NewType("Result", args)) if args.len() == 2 => {
+                        Some(Type::NewType("Result", vec![inner, args[1]]))
+                    }
+                    _ => None,
+                };
+
+                match (known, default) {
+                    (Some(known), None) => {
+                        let known = self.add(known);
+                        self.expect(known, ty, &[value]);
+                        return self.check_try(ty, inner, value, default);
+                    }
+                    // the default is returned rather than the rest of the
+                    // value, which may be left either an `Option` or a `Result`
+                    (_, Some(_)) => ty,
+                    (None, None) => {
+                        let message = "the type of the value must be known to use `?`".to_owned();
+                        return self.error(message, &[value]);
+                    }
+                }
+            }
+            Type::Never => return self.expect(inner, ty, &[value]),
+            _ => {
+                let ty = self.show(ty, &mut HashMap::new());
+                let message = format!("`?` expects an `Option` or a `Result`, found `{}`", ty);
+                return self.error(message, &[value]);
+            }
+        };
+
+        let (returned, expr) = default.unwrap_or((rest, value));
+
+        if let Some(ret) = ret {
+            self.expect(ret, returned, &[expr]);
+        }
+    }
+
+    // check the `?`s deferred at `depth`, that is in the function being inferred
+    fn check_tries(&mut self, depth: usize) {
+        while let Some(&(at, ty, inner, value, default)) = self.tries.last() {
+            if at < depth {
+                break;
+            }
+
+            self.tries.pop();
+            self.check_try(ty, inner, value, default);
+        }
+    }
+
+    // bind the constructors of a declared type: the variants without fields
+    // are values of the type and the other constructors functions returning one
+    fn define_type(&mut self, expr: ExpressionRef, decl: &'ast TypeDecl<'a>) {
+        let params = decl
+            .generics
+            .iter()
+            .map(|generic| self.add(Type::Param(generic.ident)))
+            .collect();
+        let ty = self.add(Type::NewType(decl.ident, params));
+        self.decls.insert(decl.ident, (decl, ty));
+
+        let bounds = self.generic_bounds(&decl.generics);
+        self.check_bounds(&bounds, expr);
+
+        for (ident, fields) in decl.constructors() {
+            let constructor_ty = match &decl.kind {
+                TypeDeclKind::Enum(_) if fields.is_empty() => ty,
+                _ => self.function(fields.iter().map(|field| field.ty).collect(), ty),
+            };
+            let scheme = Scheme {
+                params: true,
+                bounds: bounds.clone(),
+                ..Scheme::mono(constructor_ty)
+            };
+
+            self.env.push((ident, scheme));
+            self.constructors.insert(ident, decl);
+        }
+
+        self.derive_traits(expr, decl, ty);
+    }
+
+    // implement the traits `decl` derives, which its fields have to implement.
+    // The impls are registered first so that recursive types can derive them,
+    // and the type parameters are taken to implement the trait too.
+    fn derive_traits(&mut self, expr: ExpressionRef, decl: &'ast TypeDecl<'a>, ty: TypeRef) {
+        let mut derives = Vec::new();
+
+        for &derived in &decl.derives {
+            if !DERIVABLE.iter().any(|(ident, ..)| *ident == derived) {
+                self.error(format!("`{}` cannot be derived", derived), &[expr]);
+                continue;
+            }
+            self.derive(derived, decl.ident, ty);
+            derives.push(derived);
+        }
+
+        let scope = self.bounds.len();
+
+        for derived in derives {
+            let implied = self.add(Type::NewType(derived, Vec::new()));
+            self.bounds.truncate(scope);
+            self.bounds.extend(self.generic_bounds(&decl.generics));
+            self.bounds
+                .extend(decl.generics.iter().map(|generic| (generic.ident, implied)));
+
+            for (ident, fields) in decl.constructors() {
+                let field = fields
+                    .iter()
+                    .enumerate()
+                    .find(|(_, field)| !self.implements(derived, field.ty));
+
+                if let Some((index, field)) = field {
+                    let name = field.name.map_or(index.to_string(), str::to_owned);
+                    let field_ty = self.show(field.ty, &mut HashMap::new());
+                    self.error(
+                        format!(
+                            "cannot derive `{}` for `{}`: the field `{}` of `{}` has type `{}`, which doesn't implement `{}`",
+                            derived, decl.ident, name, ident, field_ty, derived
+                        ),
+                        &[expr],
+                    );
+                }
+            }
+        }
+
+        self.bounds.truncate(scope);
+    }
+
+    fn infer_struct(
+        &mut self,
+        expr: ExpressionRef,
+        ident: &'a str,
+        fields: ExpressionRef,
+    ) -> TypeRef {
+        let ast = self.ast;
+        // a struct, or a variant with fields, which builds a value of its enum
+        let declared = self.constructors.get(ident).copied().and_then(|decl| {
+            let (_, declared) = decl
+                .constructors()
+                .into_iter()
+                .find(|(constructor, _)| *constructor == ident)?;
+            match &decl.kind {
+                TypeDeclKind::Enum(_) if declared.is_empty() => None,
+                _ => Some((declared, decl)),
+            }
+        });
+
+        let Some((declared, decl)) = declared else {
+            self.error(format!("`{}` is not a struct", ident), &[expr]);
+            for field in ast.children(fields) {
+                self.infer_field_value(field);
+            }
+            return self.fresh();
+        };
+
+        // the fields share the instantiation of the type's parameters
+        let ty = self.decls[decl.ident].1;
+        let scheme = Scheme {
+            params: true,
+            ..Scheme::mono(ty)
+        };
+        let mut params = HashMap::new();
+        let ty = self.copy(&scheme, ty, &mut HashMap::new(), &mut params);
+        let bounds = self.generic_bounds(&decl.generics);
+        self.require(&bounds, &params, expr);
+        let mut given = Vec::new();
+
+        for field in ast.children(fields) {
+            let value_ty = self.infer_field_value(field);
+            let Expression::NamedField { ident: name, value } = ast.get_expression(field.root())
+            else {
+                continue;
+            };
+
+            match declared.iter().find(|field| field.name == Some(*name)) {
+                Some(declared) => {
+                    let field_ty =
+                        self.copy(&scheme, declared.ty, &mut HashMap::new(), &mut params);
+                    self.expect(field_ty, value_ty, &[*value]);
+                    given.push(*name);
+                }
+                None => self.error(format!("`{}` has no field `{}`", ident, name), &[field]),
+            }
+        }
+
+        for field in declared {
+            match field.name {
+                Some(name) if !given.contains(&name) => {
+                    self.error(format!("missing field `{}` of `{}`", name, ident), &[expr])
+                }
+                Some(_) => {}
+                None => {
+                    self.error(format!("`{}` has positional fields", ident), &[expr]);
+                    break;
+                }
+            }
+        }
+
+        ty
+    }
+
+    // the type of the value of a `NamedField`
+    fn infer_field_value(&mut self, field: ExpressionRef) -> TypeRef {
+        let ty = match self.ast.get_expression(field.root()) {
+            Expression::NamedField { value, .. } => self.infer(*value),
+            _ => self.fresh(),
+        };
+        self.expr_types.push((field, ty));
+        ty
+    }
+
+    // the field of the target called `field`, or else the method of its type.
+    // Both are looked up by the type of the target, so it has to be known by then.
+    fn infer_member(
+        &mut self,
+        target: ExpressionRef,
+        target_ty: TypeRef,
+        field: ExpressionRef,
+    ) -> Member {
+        let (name, index) = match self.ast.get_expression(field.root()) {
+            Expression::Ident(name) => (*name, None),
+            Expression::Int(index) => (*index, index.parse::<usize>().ok()),
+            _ => return Member::Field(self.fresh()),
+        };
+
+        let target_ty = self.resolve(target_ty);
+        let ty = match self.get(target_ty) {
+            Type::Tuple(types) => index.and_then(|index| types.get(index).copied()),
+            Type::NewType(ident, args) => match self.decls.get(ident) {
+                Some(&(decl, _)) => match &decl.kind {
+                    TypeDeclKind::Struct(fields) => {
+                        let field = match index {
+                            Some(index) => fields.get(index),
+                            None => fields.iter().find(|field| field.name == Some(name)),
+                        };
+                        field.map(|field| self.substitute(decl, args, field))
+                    }
+                    TypeDeclKind::Enum(_) => None,
+                },
+                None => None,
+            },
+            Type::Var(_) => {
+                self.error(
+                    format!(
+                        "the type of the value must be known to access field `{}`",
+                        name
+                    ),
+                    &[target],
+                );
+                return Member::Field(self.fresh());
+            }
+            _ => None,
+        };
+
+        if let Some(ty) = ty {
+            return Member::Field(ty);
+        }
+
+        if index.is_none() {
+            if let Some(ty) = self.method(target_ty, name, field) {
+                return Member::Method(ty);
+            }
+        }
+
+        let target_ty = self.show(target_ty, &mut HashMap::new());
+        let message = match index {
+            Some(_) => format!("`{}` has no field `{}`", target_ty, name),
+            None => format!("`{}` has no field or method `{}`", target_ty, name),
+        };
+        self.error(message, &[field]);
+        Member::Field(self.fresh())
+    }
+
+    // the method `name` of the values of type `ty`, if it has one, used at
+    // `expr`. Type parameters have the methods of the traits that bound them.
+    fn method(&mut self, ty: TypeRef, name: &str, expr: ExpressionRef) -> Option<TypeRef> {
+        let key = self.type_key(ty)?;
+
+        if let Some(scheme) = self.methods.get(&(key, name)).cloned() {
+            return Some(self.instantiate(&scheme, expr));
+        }
+
+        let Type::Param(param) = self.get(self.resolve(ty)) else {
+            return None;
+        };
+        let bounds: Vec<_> = self
+            .bounds
+            .iter()
+            .rev()
+            .filter(|(bounded, _)| bounded == param)
+            .map(|(_, bound)| *bound)
+            .collect();
+
+        for bound in bounds {
+            let Type::NewType(trait_, args) = self.get(bound) else {
+                continue;
+            };
+            let Some((generics, methods)) = self.traits.get(trait_) else {
+                continue;
+            };
+            let Some(method) = methods.iter().find(|method| method.ident == name) else {
+                continue;
+            };
+
+            // `Self` is the type parameter, and the parameters of the trait
+            // are the arguments of the bound
+            let scheme = method.scheme.clone();
+            let mut params: HashMap<_, _> =
+                generics.iter().copied().zip(args.iter().copied()).collect();
+            params.insert("Self", ty);
+            return Some(self.copy(&scheme, scheme.ty, &mut HashMap::new(), &mut params));
+        }
+
+        None
+    }
+
+    // the name the methods of `ty` are registered under, which is what
+    // `Value::type_name` gives for its values at runtime
+    fn type_key(&self, ty: TypeRef) -> Option<&'a str> {
+        match self.get(self.resolve(ty)) {
+            Type::Unit => Some("Unit"),
+            Type::Bool => Some("Bool"),
+            Type::Int => Some("Int"),
+            Type::Float => Some("Float"),
+            Type::Char => Some("Char"),
+            Type::String => Some("String"),
+            Type::Tuple(_) => Some("Tuple"),
+            Type::Function(..) => Some("Function"),
+            Type::NewType(ident, _) | Type::Param(ident) => Some(ident),
+            Type::Never | Type::Var(_) => None,
+        }
+    }
+
+    // the type of `target.method` when it isn't called: what it returns if it
+    // only takes the receiver, since it is called right away, or else a
+    // function of its other arguments
+    fn bind_receiver(
+        &mut self,
+        expr: ExpressionRef,
+        receiver: TypeRef,
+        method: TypeRef,
+    ) -> TypeRef {
+        let arity = match self.get(self.resolve(method)) {
+            Type::Function(args, _) => match self.get(self.resolve(*args)) {
+                Type::Tuple(args) => args.len().max(1),
+                _ => 1,
+            },
+            _ => 1,
+        };
+
+        let rest: Vec<_> = (1..arity).map(|_| self.fresh()).collect();
+        let ret = self.fresh();
+        let mut args = vec![receiver];
+        args.extend(rest.iter().copied());
+
+        let expected = self.function(args, ret);
+        self.expect(expected, method, &[expr]);
+
+        match rest.is_empty() {
+            true => ret,
+            false => self.function(rest, ret),
+        }
+    }
+
+    // the type of the function called with `args`. A method called on a
+    // field access gets the target as its first argument, and an unbound name
+    // called with arguments is the method of the type of the first one.
+    fn infer_callee(
+        &mut self,
+        callee: ExpressionRef,
+        args: &mut Vec<(ExpressionRef, TypeRef)>,
+    ) -> TypeRef {
+        let ty = match self.ast.get_expression(callee.root()) {
+            Expression::Field { target, field } => {
+                let target_ty = self.infer(*target);
+
+                match self.infer_member(*target, target_ty, *field) {
+                    Member::Field(ty) => ty,
+                    Member::Method(ty) => {
+                        args.insert(0, (*target, target_ty));
+                        ty
+                    }
+                }
+            }
+            // the method of the type of the first argument, as at runtime
+            Expression::Ident(ident) => {
+                let Some(&(receiver, receiver_ty)) = args.first() else {
+                    return self.infer(callee);
+                };
+
+                match self.method(receiver_ty, ident, callee) {
+                    Some(ty) => ty,
+                    None if self.lookup(ident).is_some() => return self.infer(callee),
+                    None if !self.methods.keys().any(|(_, name)| name == ident) => {
+                        return self.infer(callee)
+                    }
+                    // some type has the method, but not the receiver's
+                    None => {
+                        let message = match self.get(self.resolve(receiver_ty)) {
+                            Type::Var(_) => format!(
+                                "the type of the value must be known to call method `{}`",
+                                ident
+                            ),
+                            _ => {
+                                let ty = self.show(receiver_ty, &mut HashMap::new());
+                                format!("`{}` has no method `{}`", ty, ident)
+                            }
+                        };
+                        self.error(message, &[receiver]);
+                        self.fresh()
+                    }
+                }
+            }
+            _ => return self.infer(callee),
+        };
+
+        self.expr_types.push((callee, ty));
+        ty
+    }
+
+    // `known` are the types of the first arguments, when they are known
+    // before the body is checked
+    fn infer_function(
+        &mut self,
+        args: ExpressionRef,
+        body: ExpressionRef,
+        known: &[TypeRef],
+    ) -> TypeRef {
+        let ast = self.ast;
+        let scope = self.env.len();
+        let mut arg_types = Vec::new();
+
+        for (i, arg) in ast.children(args).into_iter().enumerate() {
+            let ty = match known.get(i) {
+                Some(ty) => *ty,
+                None => self.fresh(),
+            };
+            self.expr_types.push((arg, ty));
+            arg_types.push(ty);
+
+            if let Expression::Arg { ident, .. } = ast.get_expression(arg.root()) {
+                self.env.push((ident, Scheme::mono(ty)));
+            }
+        }
+
+        let ret = self.fresh();
+        self.returns.push(ret);
+        let body_ty = self.infer(body);
+        self.expect(ret, body_ty, &[body]);
+        self.check_tries(self.returns.len());
+        self.returns.pop();
+        self.env.truncate(scope);
+
+        self.function(arg_types, ret)
+    }
+
+    // the type of a value declared to have type `ty`. The arguments of a
+    // function have the declared types inside its body already, so that their
+    // methods can be looked up.
+    fn infer_declared(&mut self, value: ExpressionRef, ty: TypeRef) -> TypeRef {
+        let declared = match self.get(self.resolve(ty)) {
+            Type::Function(args, _) => match self.get(self.resolve(*args)) {
+                Type::Tuple(args) => args.clone(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+
+        match self.ast.get_expression(value.root()) {
+            Expression::Function { args, body } => {
+                let ty = self.infer_function(*args, *body, &declared);
+                self.expr_types.push((value, ty));
+                ty
+            }
+            _ => self.infer(value),
+        }
+    }
+
+    // the type of the value of a method, whose first argument is the receiver
+    fn infer_method(&mut self, value: ExpressionRef, receiver: TypeRef) -> TypeRef {
+        match self.ast.get_expression(value.root()) {
+            Expression::Function { args, body } => {
+                let ty = self.infer_function(*args, *body, &[receiver]);
+                self.expr_types.push((value, ty));
+                ty
+            }
+            _ => self.infer(value),
+        }
+    }
+
+    fn check_declared(
+        &mut self,
+        ident: &str,
+        declared: TypeRef,
+        value_ty: TypeRef,
+        value: ExpressionRef,
+        expr: ExpressionRef,
+    ) {
+        if self.unify(declared, value_ty).is_err() {
+            let (declared, found) = self.show_pair(declared, value_ty);
+            self.error(
+                format!(
+                    "`{}` is declared as `{}` but its value has type `{}`",
+                    ident, declared, found
+                ),
+                &[value, expr],
+            );
+        }
+    }
+
+    // the name, declared type and value of a method of a trait or impl
+    fn method_item(
+        &self,
+        item: ExpressionRef,
+    ) -> Option<(&'a str, Option<TypeRef>, Option<ExpressionRef>)> {
+        let ast = self.ast;
+
+        match ast.get_expression(item.root()) {
+            Expression::Binding { ident, value } => Some((ident, None, Some(*value))),
+            Expression::Signature {
+                ident, ty, binding, ..
+            } => {
+                let value = binding.and_then(|binding| match ast.get_expression(binding.root()) {
+                    Expression::Binding { value, .. } => Some(*value),
+                    _ => None,
+                });
+                Some((ident, Some(*ty), value))
+            }
+            _ => None,
+        }
+    }
+
+    // record the methods of a trait. The defaults are checked with `Self` as
+    // an opaque type that only has the methods of the trait.
+    fn infer_trait(&mut self, declared: &'ast Trait<'a>) {
+        let receiver = self.add(Type::Param("Self"));
+        let mut methods: Vec<TraitMethod<'a>> = Vec::new();
+        let mut defaults = Vec::new();
+
+        self.level += 1;
+
+        for item in self.ast.children(declared.items) {
+            self.expr_types.push((item, self.unit));
+            let Some((ident, ty, value)) = self.method_item(item) else {
+                continue;
+            };
+
+            if methods.iter().any(|method| method.ident == ident) {
+                self.error(
+                    format!("duplicate method `{}` in `{}`", ident, declared.ident),
+                    &[item],
+                );
+                continue;
+            }
+
+            let ty = ty.unwrap_or_else(|| self.fresh());
+            self.methods.insert(("Self", ident), Scheme::mono(ty));
+            methods.push(TraitMethod {
+                ident,
+                scheme: Scheme::mono(ty),
+                default: value.is_some(),
+            });
+
+            if let Some(value) = value {
+                defaults.push((ident, ty, value, item));
+            }
+        }
+
+        // `Self` implements the trait, whatever else the defaults rely on
+        let params = declared.generics.iter();
+        let params = params
+            .map(|generic| self.add(Type::Param(generic.ident)))
+            .collect();
+        let mut bounds = vec![("Self", self.add(Type::NewType(declared.ident, params)))];
+        bounds.extend(self.generic_bounds(&declared.generics));
+
+        self.with_bounds(bounds, |infer| {
+            for (ident, ty, value, item) in defaults {
+                let value_ty = infer.infer_method(value, receiver);
+                infer.check_declared(ident, ty, value_ty, value, item);
+            }
+        });
+
+        self.level -= 1;
+
+        for method in &mut methods {
+            self.methods.remove(&("Self", method.ident));
+            method.scheme = Scheme {
+                params: true,
+                ..self.generalize(method.scheme.ty)
+            };
+        }
+
+        let generics = declared.generics.iter().map(|generic| generic.ident);
+        self.traits
+            .insert(declared.ident, (generics.collect(), methods));
+    }
+
+    // add the methods of an impl to those of its type, checking them against
+    // the trait it implements, if any
+    fn infer_impl(&mut self, expr: ExpressionRef, implemented: &'ast Impl<'a>) {
+        let ast = self.ast;
+        let items = ast.children(implemented.items);
+        let ty = implemented.ty;
+
+        for item in &items {
+            self.expr_types.push((*item, self.unit));
+        }
+
+        let key = match self.get(ty) {
+            Type::Param(_) => None,
+            _ => self.type_key(ty),
+        };
+        let Some(key) = key else {
+            let ty = self.show(ty, &mut HashMap::new());
+            self.error(format!("cannot implement methods for `{}`", ty), &[expr]);
+            return;
+        };
+
+        let mut bounds = self.generic_bounds(&implemented.generics);
+        bounds.extend(self.generic_bounds(&implemented.predicates));
+        self.check_bounds(&bounds, expr);
+
+        // `Self` and the type parameters of the trait stand for the types the
+        // impl gives them
+        let mut params = HashMap::from([("Self", ty)]);
+        let mut trait_ = None;
+
+        if let Some(implemented_trait) = implemented.trait_ {
+            match self.get(implemented_trait) {
+                Type::NewType(ident, args) => match self.traits.get(ident).cloned() {
+                    Some((generics, methods)) => {
+                        if let Some(&previous) = self.impls.get(&(ident, key)) {
+                            let ty = self.show(ty, &mut HashMap::new());
+                            let mut exprs = vec![expr];
+                            exprs.extend(previous);
+                            self.error(
+                                format!("`{}` is already implemented for `{}`", ident, ty),
+                                &exprs,
+                            );
+                            return;
+                        }
+                        self.impls.insert((ident, key), Some(expr));
+
+                        params.extend(generics.into_iter().zip(args.iter().copied()));
+                        trait_ = Some((*ident, methods));
+                    }
+                    // the bounds have reported it already if they use it
+                    None if bounds
+                        .iter()
+                        .any(|(_, bound)| self.bound_trait(*bound) == Some(ident)) => {}
+                    None => self.error(format!("unknown trait `{}`", ident), &[expr]),
+                },
+                _ => {
+                    let implemented_trait = self.show(implemented_trait, &mut HashMap::new());
+                    self.error(format!("`{}` is not a trait", implemented_trait), &[expr]);
+                }
+            }
+        }
+
+        let name = self.show(ty, &mut HashMap::new());
+        let mut defined = Vec::new();
+        let mut bodies = Vec::new();
+
+        self.level += 1;
+
+        for item in items {
+            let Some((ident, declared, Some(value))) = self.method_item(item) else {
+                continue;
+            };
+
+            if self.methods.contains_key(&(key, ident)) {
+                self.error(
+                    format!("duplicate method `{}` for `{}`", ident, name),
+                    &[item],
+                );
+                continue;
+            }
+
+            // the type the trait gives the method, unless the impl declares one
+            let from_trait = match &trait_ {
+                Some((trait_ident, methods)) => {
+                    match methods.iter().find(|method| method.ident == ident) {
+                        Some(method) => Some(method.scheme.clone()),
+                        None => {
+                            self.error(
+                                format!("`{}` is not a method of `{}`", ident, trait_ident),
+                                &[item],
+                            );
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let declared = match (declared, from_trait) {
+                (Some(declared), _) => Some(self.replace(declared, &params)),
+                (None, Some(scheme)) => {
+                    let mut params = params.clone();
+                    Some(self.copy(&scheme, scheme.ty, &mut HashMap::new(), &mut params))
+                }
+                (None, None) => None,
+            };
+            let method_ty = declared.unwrap_or_else(|| self.fresh());
+
+            let scheme = Scheme {
+                params: true,
+                bounds: bounds.clone(),
+                ..Scheme::mono(method_ty)
+            };
+            self.methods.insert((key, ident), scheme);
+            defined.push(ident);
+            bodies.push((ident, method_ty, declared.is_some(), value, item));
+        }
+
+        self.with_bounds(bounds.clone(), |infer| {
+            for (ident, method_ty, declared, value, item) in &bodies {
+                let value_ty = infer.infer_method(*value, ty);
+
+                match declared {
+                    true => infer.check_declared(ident, *method_ty, value_ty, *value, *item),
+                    false => infer.expect(*method_ty, value_ty, &[*value]),
+                }
+            }
+        });
+
+        self.level -= 1;
+
+        for (ident, method_ty, declared, ..) in bodies {
+            if !declared {
+                let scheme = Scheme {
+                    params: true,
+                    bounds: bounds.clone(),
+                    ..self.generalize(method_ty)
+                };
+                self.methods.insert((key, ident), scheme);
+            }
+        }
+
+        let Some((trait_ident, methods)) = trait_ else {
+            return;
+        };
+
+        for method in methods {
+            if defined.contains(&method.ident) {
+                continue;
+            }
+
+            if !method.default {
+                self.error(
+                    format!(
+                        "missing method `{}` in the impl of `{}` for `{}`",
+                        method.ident, trait_ident, name
+                    ),
+                    &[expr],
+                );
+                continue;
+            }
+
+            if self.methods.contains_key(&(key, method.ident)) {
+                self.error(
+                    format!("duplicate method `{}` for `{}`", method.ident, name),
+                    &[expr],
+                );
+                continue;
+            }
+
+            self.level += 1;
+            let mut params = params.clone();
+            let ty = self.copy(
+                &method.scheme,
+                method.scheme.ty,
+                &mut HashMap::new(),
+                &mut params,
+            );
+            self.level -= 1;
+
+            let scheme = Scheme {
+                params: true,
+                bounds: bounds.clone(),
+                ..self.generalize(ty)
+            };
+            self.methods.insert((key, method.ident), scheme);
+        }
+    }
+
+    // the type of a field of a value of `decl` with the type arguments `args`
+    fn substitute(
+        &mut self,
+        decl: &TypeDecl<'a>,
+        args: &[TypeRef],
+        field: &FieldDecl<'a>,
+    ) -> TypeRef {
+        let scheme = Scheme {
+            params: true,
+            ..Scheme::mono(field.ty)
+        };
+        let mut params = decl
+            .generics
+            .iter()
+            .map(|generic| generic.ident)
+            .zip(args.iter().copied())
+            .collect();
+        self.copy(&scheme, field.ty, &mut HashMap::new(), &mut params)
+    }
+
+    fn infer_loop_body(&mut self, label: Option<&'a str>, ty: TypeRef, body: ExpressionRef) {
+        self.loops.push((label, ty));
+        self.infer(body);
+        self.loops.pop();
+    }
+
+    // bind the pattern of `arm` to a value of type `matched`, and unify the
+    // type of its body with `ty`
+    fn infer_arm(&mut self, arm: ExpressionRef, matched: TypeRef, ty: TypeRef) {
+        let ast = self.ast;
+        let Expression::Arm {
+            pattern,
+            guard,
+            body,
+        } = ast.get_expression(arm.root())
+        else {
+            return;
+        };
+
+        let scope = self.env.len();
+        self.infer_pattern(*pattern, matched);
+
+        if let Some(guard) = guard {
+            let guard_ty = self.infer(*guard);
+            self.expect(self.bool, guard_ty, &[*guard]);
+        }
+
+        let body_ty = self.infer(*body);
+        self.expect(ty, body_ty, &[*body]);
+        self.env.truncate(scope);
+        self.expr_types.push((arm, body_ty));
+    }
+
+    // the elements of ranges are `Int`s and those of arrays their type
+    // argument. Other collections are left unconstrained until there are traits.
+    fn element_type(&mut self, iterable: TypeRef) -> TypeRef {
+        match self.get(self.resolve(iterable)) {
+            Type::NewType("Range", args) if args.is_empty() => self.int,
+            Type::NewType("Array", args) if args.len() == 1 => args[0],
+            _ => self.fresh(),
+        }
+    }
+
+    // check that values of type `ty` can match `pattern`, and bind its names
+    fn infer_pattern(&mut self, pattern: ExpressionRef, ty: TypeRef) {
+        let ast = self.ast;
+
+        match ast.get_expression(pattern.root()) {
+            Expression::Dash => self.expr_types.push((pattern, ty)),
+            Expression::Ident(ident) => {
+                self.expr_types.push((pattern, ty));
+                self.env.push((ident, Scheme::mono(ty)));
+            }
+            Expression::Constructor { ident, fields } => {
+                self.infer_constructor_pattern(pattern, ident, *fields, ty);
+                self.expr_types.push((pattern, ty));
+            }
+            // the bounds are values of the matched type, not a `Range`
+            Expression::Range { from, to, .. } => {
+                for bound in [from, to] {
+                    let bound_ty = self.infer(*bound);
+                    self.expect(ty, bound_ty, &[*bound]);
+                }
+                self.expr_types.push((pattern, ty));
+            }
+            Expression::Tuple(patterns) => {
+                let patterns = ast.children(*patterns);
+                let types = patterns.iter().map(|_| self.fresh()).collect::<Vec<_>>();
+                let tuple = self.add(Type::Tuple(types.clone()));
+                self.expect(ty, tuple, &[pattern]);
+
+                for (pattern, ty) in patterns.into_iter().zip(types) {
+                    self.infer_pattern(pattern, ty);
+                }
+                self.expr_types.push((pattern, ty));
+            }
+            // every alternative binds the same names, so only the first one's
+            // bindings are kept
+            Expression::Or(patterns) => {
+                for (i, alternative) in ast.children(*patterns).into_iter().enumerate() {
+                    let scope = self.env.len();
+                    self.infer_pattern(alternative, ty);
+
+                    if i > 0 {
+                        self.env.truncate(scope);
+                    }
+                }
+                self.expr_types.push((pattern, ty));
+            }
+            Expression::At {
+                ident,
+                pattern: inner,
+            } => {
+                self.infer_pattern(*inner, ty);
+                self.env.push((ident, Scheme::mono(ty)));
+                self.expr_types.push((pattern, ty));
+            }
+            _ => {
+                let literal_ty = self.infer(pattern);
+                self.expect(ty, literal_ty, &[pattern]);
+            }
+        }
+    }
+
+    fn infer_constructor_pattern(
+        &mut self,
+        pattern: ExpressionRef,
+        ident: &'a str,
+        fields: ExpressionRef,
+        ty: TypeRef,
+    ) {
+        let ast = self.ast;
+        let fields = ast.children(fields);
+
+        let declared = self.constructors.get(ident).and_then(|decl| {
+            let (_, declared) = decl
+                .constructors()
+                .into_iter()
+                .find(|(constructor, _)| *constructor == ident)?;
+            Some((self.decls[decl.ident].1, declared))
+        });
+
+        let Some((result, declared)) = declared else {
+            self.error(format!("`{}` is not a constructor", ident), &[pattern]);
+            for field in fields {
+                let ty = self.fresh();
+                self.infer_field_pattern(field, ty);
+            }
+            return;
+        };
+
+        let scheme = Scheme {
+            params: true,
+            ..Scheme::mono(result)
+        };
+        let mut params = HashMap::new();
+        let result = self.copy(&scheme, result, &mut HashMap::new(), &mut params);
+        self.expect(ty, result, &[pattern]);
+
+        if fields.len() > declared.len() {
+            self.error(
+                format!(
+                    "`{}` has {} fields, found {}",
+                    ident,
+                    declared.len(),
+                    fields.len()
+                ),
+                &[pattern],
+            );
+        }
+
+        for (i, field) in fields.into_iter().enumerate() {
+            let declared = match ast.get_expression(field.root()) {
+                Expression::NamedField { ident: name, .. } => {
+                    let declared = declared.iter().find(|field| field.name == Some(*name));
+                    if declared.is_none() {
+                        self.error(format!("`{}` has no field `{}`", ident, name), &[field]);
+                    }
+                    declared
+                }
+                _ => declared.get(i),
+            };
+
+            let field_ty = match declared {
+                Some(declared) => self.copy(&scheme, declared.ty, &mut HashMap::new(), &mut params),
+                None => self.fresh(),
+            };
+            self.infer_field_pattern(field, field_ty);
+        }
+    }
+
+    // a field of a constructor pattern is either a pattern or `name: pattern`
+    fn infer_field_pattern(&mut self, field: ExpressionRef, ty: TypeRef) {
+        match self.ast.get_expression(field.root()) {
+            Expression::NamedField { value, .. } => {
+                self.infer_pattern(*value, ty);
+                self.expr_types.push((field, ty));
+            }
+            _ => self.infer_pattern(field, ty),
+        }
+    }
+
+    fn infer_call(
+        &mut self,
+        (callee, callee_ty): (ExpressionRef, TypeRef),
+        args: Vec<(ExpressionRef, TypeRef)>,
+    ) -> TypeRef {
+        if let Some(ret) = self.variadic_return(callee) {
+            return ret;
+        }
+
+        let ret = self.fresh();
+        let arg_types = args.iter().map(|(_, ty)| *ty).collect();
+        let arg_types = self.add(Type::Tuple(arg_types));
+        let expected = self.add(Type::Function(arg_types, ret));
+
+        if self.unify(callee_ty, expected).is_err() {
+            let (callee_ty, arg_types) = self.show_pair(callee_ty, arg_types);
+            let mut exprs = vec![callee];
+            exprs.extend(args.iter().map(|(arg, _)| *arg));
+            self.error(
+                format!("cannot call `{}` with arguments `{}`", callee_ty, arg_types),
+                &exprs,
+            );
+        }
+
+        ret
+    }
+
+    // give each top-level function a type before any of them is inferred, so
+    // that they can call each other. The calls made before a function is bound
+    // use it at a single type.
+    fn declare_functions(&mut self) {
+        let ast = self.ast;
+
+        for stmt in ast.statements() {
+            if let Expression::Binding { ident, value } = ast.get_expression(stmt.expr().root()) {
+                let is_function = matches!(
+                    ast.get_expression(value.root()),
+                    Expression::Function { .. }
+                );
+
+                if is_function && !self.forward.iter().any(|(name, _)| name == ident) {
+                    let ty = self.fresh();
+                    self.forward.push((ident, ty));
+                }
+            }
+        }
+    }
+
+    // check the calls made to the top-level function bound by `stmt` before
+    // it was bound against its type
+    fn define_function(&mut self, stmt: ExpressionRef) {
+        let Expression::Binding { ident, value } = self.ast.get_expression(stmt.root()) else {
+            return;
+        };
+        let Some(i) = self.forward.iter().position(|(name, _)| name == ident) else {
+            return;
+        };
+
+        let (_, forward) = self.forward.remove(i);
+        if let Some(scheme) = self.lookup(ident) {
+            let ty = self.instantiate(&scheme, stmt);
+            self.expect(forward, ty, &[*value]);
+        }
+    }
+
+    fn infer_binding(&mut self, ident: &'a str, value: ExpressionRef) {
+        let ast = self.ast;
+        let is_function = matches!(
+            ast.get_expression(value.root()),
+            Expression::Function { .. }
+        );
+
+        self.level += 1;
+
+        // functions may refer to themselves
+        let scope = self.env.len();
+        let ty = self.fresh();
+        if is_function {
+            self.env.push((ident, Scheme::mono(ty)));
+        }
+
+        let value_ty = self.infer(value);
+        self.expect(ty, value_ty, &[value]);
+        self.env.truncate(scope);
+
+        self.level -= 1;
+
+        // only functions are generalised, since other values may be mutated
+        let scheme = if is_function {
+            self.generalize(ty)
+        } else {
+            Scheme::mono(ty)
+        };
+
+        self.env.push((ident, scheme));
+    }
+
+    fn infer_operator(
+        &mut self,
+        operator: Operator,
+        (left, left_expr): (TypeRef, ExpressionRef),
+        (right, right_expr): (TypeRef, ExpressionRef),
+    ) -> TypeRef {
+        use Operator::*;
+
+        match operator {
+            And | Or => {
+                self.expect(self.bool, left, &[left_expr]);
+                self.expect(self.bool, right, &[right_expr]);
+                self.bool
+            }
+            Equal | NotEqual => {
+                self.expect(left, right, &[left_expr, right_expr]);
+                self.constraints.push((left, "Eq", left_expr));
+                self.bool
+            }
+            LessThan | GreaterThan | LessEqual | GreaterEqual => {
+                self.expect(left, right, &[left_expr, right_expr]);
+                self.constraints.push((left, "Ord", left_expr));
+                self.bool
+            }
+            BitAnd | BitOr | BitXor | LeftShift | RightShift => {
+                self.expect(self.int, left, &[left_expr]);
+                self.expect(self.int, right, &[right_expr]);
+                self.int
+            }
+            _ => {
+                self.expect(left, right, &[left_expr, right_expr]);
+                self.operands.push((operator, left, left_expr));
+                left
+            }
+        }
+    }
+
+    fn check_operands(&mut self) {
+        for (operator, ty, expr) in std::mem::take(&mut self.operands) {
+            let ty = self.resolve(ty);
+
+            let valid = match self.get(ty) {
+                Type::Int | Type::Float | Type::Var(_) => true,
+                Type::String => operator == Operator::Plus,
+                _ => false,
+            };
+
+            if !valid {
+                let ty = self.show(ty, &mut HashMap::new());
+                self.error(format!("cannot apply `{}` to `{}`", operator, ty), &[expr]);
+            }
+        }
+    }
+
+    // variadic builtins take any arguments, so only their return type is checked
+    fn variadic_return(&self, callee: ExpressionRef) -> Option<TypeRef> {
+        let Expression::Ident(ident) = self.ast.get_expression(callee.root()) else {
+            return None;
+        };
+
+        let scheme = self.lookup(ident).filter(|scheme| scheme.variadic)?;
+        match self.get(scheme.ty) {
+            Type::Function(_, ret) => Some(*ret),
+            _ => None,
+        }
+    }
+
+    fn show_pair(&self, left: TypeRef, right: TypeRef) -> (String, String) {
+        let mut names = HashMap::new();
+        (self.show(left, &mut names), self.show(right, &mut names))
+    }
+
+    // print a type, naming its unsolved variables `a`, `b`, ... in order
+    fn show(&self, ty: TypeRef, names: &mut HashMap<usize, String>) -> String {
+        let ty = self.resolve(ty);
+
+        match self.get(ty) {
+            Type::Unit => "()".to_owned(),
+            Type::Never => "Never".to_owned(),
+            Type::Bool => "Bool".to_owned(),
+            Type::Int => "Int".to_owned(),
+            Type::Float => "Float".to_owned(),
+            Type::Char => "Char".to_owned(),
+            Type::String => "String".to_owned(),
+            Type::Param(param) => param.to_string(),
+            Type::Var(var) => {
+                let next = names.len();
+                names.entry(*var).or_insert_with(|| var_name(next)).clone()
+            }
+            Type::Tuple(types) => {
+                let types: Vec<_> = types.iter().map(|ty| self.show(*ty, names)).collect();
+                format!("({})", types.join(", "))
+            }
+            Type::NewType(ident, types) if types.is_empty() => ident.to_string(),
+            Type::NewType(ident, types) => {
+                let types: Vec<_> = types.iter().map(|ty| self.show(*ty, names)).collect();
+                format!("{}<{}>", ident, types.join(", "))
+            }
+            Type::Function(args, ret) => {
+                let args = match self.get(self.resolve(*args)) {
+                    Type::Tuple(types) if types.is_empty() => "()".to_owned(),
+                    Type::Tuple(types) => {
+                        let types: Vec<_> = types
+                            .iter()
+                            .map(|ty| match self.get(self.resolve(*ty)) {
+                                Type::Function(..) => format!("({})", self.show(*ty, names)),
+                                _ => self.show(*ty, names),
+                            })
+                            .collect();
+                        types.join(", ")
+                    }
+                    _ => self.show(*args, names),
+                };
+
+                format!("{} -> {}", args, self.show(*ret, names))
+            }
+        }
+    }
+}
+
+fn var_name(index: usize) -> String {
+    let letter = (b'a' + (index % 26) as u8) as char;
+
+    match index / 26 {
+        0 => letter.to_string(),
+        n => format!("{}{}", letter, n),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::flat_ast::StatementKind;
+
+    // builds the flat AST by hand, one statement per line
+    struct Builder<'a> {
+        ast: Ast<'a>,
+        line: u32,
+        column: u32,
+    }
+
+    impl<'a> Builder<'a> {
+        fn new() -> Self {
+            Builder {
+                ast: Ast::new(),
+                line: 1,
+                column: 0,
+            }
+        }
+
+        fn expr(&mut self, expr: Expression<'a>) -> ExpressionRef {
+            self.column += 1;
+            let location = Location {
+                line: self.line,
+                column: self.column,
+            };
+            self.ast.add_expression(expr, Span::default(), location)
+        }
+
+        fn stmt(&mut self, expr: ExpressionRef) {
+            self.ast.add_statement(StatementKind::Expr, expr);
+            self.line += 1;
+            self.column = 0;
+        }
+
+        fn ident(&mut self, ident: &'a str) -> ExpressionRef {
+            self.expr(Expression::Ident(ident))
+        }
+
+        fn infix(
+            &mut self,
+            operator: Operator,
+            left: ExpressionRef,
+            right: ExpressionRef,
+        ) -> ExpressionRef {
+            self.expr(Expression::Infix {
+                operator,
+                left,
+                right,
+            })
+        }
+
+        fn call(&mut self, callee: ExpressionRef, args: &[ExpressionRef]) -> ExpressionRef {
+            let args = run(args, callee.index + callee.len);
+            self.expr(Expression::Call { callee, args })
+        }
+
+        fn function(
+            &mut self,
+            args: &[&'a str],
+            body: impl FnOnce(&mut Self) -> ExpressionRef,
+        ) -> ExpressionRef {
+            let args: Vec<_> = args
+                .iter()
+                .map(|ident| {
+                    self.expr(Expression::Arg {
+                        ident,
+                        mutable: false,
+                    })
+                })
+                .collect();
+            let body = body(self);
+            let args = run(&args, body.index);
+            self.expr(Expression::Function { args, body })
+        }
+
+        fn binding(&mut self, ident: &'a str, value: ExpressionRef) -> ExpressionRef {
+            self.expr(Expression::Binding { ident, value })
+        }
+
+        fn ty(&mut self, ty: Type<'a>) -> TypeRef {
+            self.ast.add_type(ty)
+        }
+
+        fn function_type(&mut self, args: Vec<TypeRef>, ret: TypeRef) -> TypeRef {
+            let args = self.ty(Type::Tuple(args));
+            self.ty(Type::Function(args, ret))
+        }
+
+        fn infer(mut self) -> (Ast<'a>, Vec<TypeError>) {
+            let errors = self.ast.infer_types().err().unwrap_or_default();
+            (self.ast, errors)
+        }
+    }
+
+    // the run covering `exprs`, or an empty run at `empty`
+    fn run(exprs: &[ExpressionRef], empty: usize) -> ExpressionRef {
+        match (exprs.first(), exprs.last()) {
+            (Some(first), Some(last)) => ExpressionRef {
+                index: first.index,
+                len: last.index + last.len - first.index,
+            },
+            _ => ExpressionRef {
+                index: empty,
+                len: 0,
+            },
+        }
+    }
+
+    fn type_of<'a>(ast: &'a Ast, expr: ExpressionRef) -> &'a Type<'a> {
+        ast.get_type_of(ast.get_type(expr).unwrap())
+    }
+
+    fn messages(errors: &[TypeError]) -> Vec<String> {
+        errors.iter().map(|error| error.to_string()).collect()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut b = Builder::new();
+        let (one, two) = (b.expr(Expression::Int("1")), b.expr(Expression::Int("2")));
+        let sum = b.infix(Operator::Plus, one, two);
+        b.stmt(sum);
+        let (x, y) = (
+            b.expr(Expression::Float("1.0")),
+            b.expr(Expression::Float("2.0")),
+        );
+        let less = b.infix(Operator::LessThan, x, y);
+        b.stmt(less);
+        let (s, t) = (b.expr(Expression::Str("a")), b.expr(Expression::Str("b")));
+        let concat = b.infix(Operator::Plus, s, t);
+        b.stmt(concat);
+
+        let (ast, errors) = b.infer();
+        assert_eq!(errors, vec![]);
+        assert_eq!(type_of(&ast, sum), &Type::Int);
+        assert_eq!(type_of(&ast, less), &Type::Bool);
+        assert_eq!(type_of(&ast, concat), &Type::String);
+    }
+
+    #[test]
+    fn operand_mismatches() {
+        let mut b = Builder::new();
+        let (one, yes) = (
+            b.expr(Expression::Int("1")),
+            b.expr(Expression::Bool("true")),
+        );
+        let sum = b.infix(Operator::Plus, one, yes);
+        b.stmt(sum);
+        let (s, t) = (b.expr(Expression::Str("a")), b.expr(Expression::Str("b")));
+        let product = b.infix(Operator::Multiply, s, t);
+        b.stmt(product);
+
+        let (_, errors) = b.infer();
+        assert_eq!(
+            messages(&errors),
+            vec![
+                "Type Error: expected `Int`, found `Bool` at 1:1, 1:2",
+                "Type Error: cannot apply `*` to `String` at 2:1",
+            ]
+        );
+    }
+
+    #[test]
+    fn let_polymorphism() {
+        // id = a -> a; id { 1 }; id { true }
+        let mut b = Builder::new();
+        let id = b.function(&["a"], |b| b.ident("a"));
+        let binding = b.binding("id", id);
+        b.stmt(binding);
+
+        let (callee, arg) = (b.ident("id"), b.expr(Expression::Int("1")));
+        let int_call = b.call(callee, &[arg]);
+        b.stmt(int_call);
+        let (callee, arg) = (b.ident("id"), b.expr(Expression::Bool("true")));
+        let bool_call = b.call(callee, &[arg]);
+        b.stmt(bool_call);
+
+        let (ast, errors) = b.infer();
+        assert_eq!(errors, vec![]);
+        assert_eq!(type_of(&ast, int_call), &Type::Int);
+        assert_eq!(type_of(&ast, bool_call), &Type::Bool);
+    }
+
+    #[test]
+    fn lambda_arguments_are_monomorphic() {
+        // f -> f { 1 } + f { true }
+        let mut b = Builder::new();
+        let function = b.function(&["f"], |b| {
+            let (callee, arg) = (b.ident("f"), b.expr(Expression::Int("1")));
+            let left = b.call(callee, &[arg]);
+            let (callee, arg) = (b.ident("f"), b.expr(Expression::Bool("true")));
+            let right = b.call(callee, &[arg]);
+            b.infix(Operator::Plus, left, right)
+        });
+        b.stmt(function);
+
+        let (_, errors) = b.infer();
+        assert_eq!(
+            messages(&errors),
+            vec!["Type Error: cannot call `Int -> a` with arguments `(Bool)` at 1:5, 1:6"]
+        );
+    }
+
+    #[test]
+    fn occurs_check() {
+        // f = x -> x { x }
+        let mut b = Builder::new();
+        let f = b.function(&["x"], |b| {
+            let (callee, arg) = (b.ident("x"), b.ident("x"));
+            b.call(callee, &[arg])
+        });
+        let binding = b.binding("f", f);
+        b.stmt(binding);
+
+        let (_, errors) = b.infer();
+        assert_eq!(
+            messages(&errors),
+            vec!["Type Error: cannot call `a` with arguments `(a)` at 1:2, 1:3"]
+        );
+    }
+
+    #[test]
+    fn recursive_functions() {
+        // fact = n -> n * fact { n - 1 }; fact { 5 }
+        let mut b = Builder::new();
+        let fact = b.function(&["n"], |b| {
+            let n = b.ident("n");
+            let callee = b.ident("fact");
+            let (m, one) = (b.ident("n"), b.expr(Expression::Int("1")));
+            let arg = b.infix(Operator::Minus, m, one);
+            let call = b.call(callee, &[arg]);
+            b.infix(Operator::Multiply, n, call)
+        });
+        let binding = b.binding("fact", fact);
+        b.stmt(binding);
+        let (callee, arg) = (b.ident("fact"), b.expr(Expression::Int("5")));
+        let call = b.call(callee, &[arg]);
+        b.stmt(call);
+
+        let (ast, errors) = b.infer();
+        assert_eq!(errors, vec![]);
+        assert_eq!(type_of(&ast, call), &Type::Int);
+    }
+
+    #[test]
+    fn collection_methods() {
+        let infer = |input: &str| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+
+        assert_eq!(
+            infer("x = 0..10 : filter { n -> n % 2 == 0 } : sum; y = \"ab\".len; x + y"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("x = 0..3 : map { n -> 1.5 } : sum; x + 1"),
+            vec!["Type Error: expected `Float`, found `Int` at 1:36, 1:40"]
+        );
+        assert_eq!(
+            infer("Point :: { x: Int }; p = Point { x: 1 }; p : sum"),
+            vec!["Type Error: `Point` has no method `sum` at 1:42"]
+        );
+        assert_eq!(
+            infer("\"ab\" : sum"),
+            vec!["Type Error: `String` has no method `sum` at 1:1"]
+        );
+        assert_eq!(
+            infer("f = xs -> len(xs);"),
+            vec!["Type Error: the type of the value must be known to call method `len` at 1:15"]
+        );
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let infer = |input: &str| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+        let parity = "even = n -> if n == 0 { true } else { odd(n - 1) };
+            odd = n -> if n == 0 { false } else { even(n - 1) };";
+        assert_eq!(
+            infer(&format!("{} even(10) && odd(3)", parity)),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} odd(1) + 1", parity)),
+            vec![
+                "Type Error: expected `Bool`, found `Int` at 2:69, 2:75",
+                "Type Error: cannot apply `+` to `Bool` at 2:69",
+            ]
+        );
+        assert_eq!(
+            infer("f = x -> g(x) + 1; g = x -> 'a';"),
+            vec!["Type Error: expected `a -> Int`, found `a -> Char` at 1:24"]
+        );
+        assert_eq!(
+            infer("f = x -> h(x);"),
+            vec!["Type Error: unknown identifier `h` at 1:10"]
+        );
+    }
+
+    #[test]
+    fn signatures() {
+        // add | Int, Int -> Int; add = a, b -> a == b
+        let mut b = Builder::new();
+        let int = b.ty(Type::Int);
+        let ty = b.function_type(vec![int, int], int);
+        let value = b.function(&["a", "b"], |b| {
+            let (left, right) = (b.ident("a"), b.ident("b"));
+            b.infix(Operator::Equal, left, right)
+        });
+        let binding = b.binding("add", value);
+        let signature = b.expr(Expression::Signature {
+            ident: "add",
+            generics: Vec::new(),
+            ty,
+            binding: Some(binding),
+        });
+        b.stmt(signature);
+
+        let (_, errors) = b.infer();
+        assert_eq!(
+            messages(&errors),
+            vec!["Type Error: `add` is declared as `Int, Int -> Int` but its value has type `Int, Int -> Bool` at 1:6, 1:8"]
+        );
+    }
+
+    #[test]
+    fn type_parameters_are_rigid() {
+        // id | T -> T; id = x -> 1; id { true }
+        let mut b = Builder::new();
+        let param = b.ty(Type::Param("T"));
+        let ty = b.function_type(vec![param], param);
+        let value = b.function(&["x"], |b| b.expr(Expression::Int("1")));
+        let binding = b.binding("id", value);
+        let signature = b.expr(Expression::Signature {
+            ident: "id",
+            generics: Vec::new(),
+            ty,
+            binding: Some(binding),
+        });
+        b.stmt(signature);
+        let (callee, arg) = (b.ident("id"), b.expr(Expression::Bool("true")));
+        let call = b.call(callee, &[arg]);
+        b.stmt(call);
+
+        let (ast, errors) = b.infer();
+        assert_eq!(
+            messages(&errors),
+            vec!["Type Error: `id` is declared as `T -> T` but its value has type `T -> Int` at 1:3, 1:5"]
+        );
+        assert_eq!(type_of(&ast, call), &Type::Bool);
+    }
+
+    #[test]
+    fn parsed_programs() {
+        let infer = |input| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+
+        assert_eq!(
+            infer("add = a, b -> a + b; add { 1, 2 } : add { 3 }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("0..10 : map { n -> n * 2 } : sum; println(\"done\", 1)"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("add | Int, Int -> Int; add = a, b -> a + b; add { 1, true }"),
+            vec!["Type Error: cannot call `Int, Int -> Int` with arguments `(Int, Bool)` at 1:45, 1:51, 1:54"]
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let infer = |input| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+
+        assert_eq!(
+            infer("s = 0; for n in 0..10 { s += n; }; x = loop { break s; } + 1"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("'a: loop { loop { break 'a 1; }; break true; }"),
+            vec!["Type Error: expected `Int`, found `Bool` at 1:40"]
+        );
+        assert_eq!(
+            infer("for n in 0..3 { n + 1.5 }; while 1 = 2.0 { }"),
+            vec![
+                "Type Error: expected `Int`, found `Float` at 1:17, 1:21",
+                "Type Error: expected `Float`, found `Int` at 1:34",
+            ]
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let infer = |input| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+
+        assert_eq!(
+            infer("x = 1; y = if x > 0 { 1 } else if x < 0 { -1 } else { 0 } + 1"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("if 1 { 2 } else { 3 }"),
+            vec!["Type Error: expected `Bool`, found `Int` at 1:4"]
+        );
+        assert_eq!(
+            infer("if true { 1 } else { \"one\" }"),
+            vec!["Type Error: expected `Int`, found `String` at 1:9, 1:20"]
+        );
+    }
+
+    #[test]
+    fn matches() {
+        let infer = |input| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            let mut errors = ast.infer_types().err().unwrap_or_default();
+            errors.extend_from_slice(ast.warnings());
+            messages(&errors)
+        };
+
+        assert_eq!(
+            infer(
+                "x = 3; y = match (x, true) { (0, _) => 0, (n @ 1..=9, b) if b => n, _ => -1 } + 1"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("match true { true => 1, false => 0 }; match () { () => 1 }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("match 1 { 0 => \"zero\", n if n > 0 => 1, 'a' => 2 }"),
+            vec![
+                "Type Error: expected `String`, found `Int` at 1:38",
+                "Type Error: expected `Int`, found `Char` at 1:41",
+                "Type Error: expected `String`, found `Int` at 1:48",
+                "Warning: `match` is not exhaustive at 1:7",
+            ]
+        );
+        assert_eq!(
+            infer("match (1, false) { (_, true) => 1, (0 | 1, false) => 2, (1, _) => 3 }"),
+            vec![
+                "Warning: `match` is not exhaustive at 1:7",
+                "Warning: unreachable arm at 1:57",
+            ]
+        );
+        assert_eq!(
+            infer("match 'c' { 'a'..='z' => 1, _ => 2, 'q' => 3 }"),
+            vec!["Warning: unreachable arm at 1:37"]
+        );
+    }
+
+    #[test]
+    fn type_declarations() {
+        let infer = |input: &str| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            let mut errors = ast.infer_types().err().unwrap_or_default();
+            errors.extend_from_slice(ast.warnings());
+            messages(&errors)
+        };
+        let option = "Option :: T -> | Some { T } | None;";
+        let point = "Point<T> :: { x: T, y: T };";
+
+        assert_eq!(
+            infer(&format!(
+                "{} unwrap_or = o, d -> match o {{ Some {{ x }} => x, None => d }}; unwrap_or(Some(1), 2) + 1",
+                option
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} p = Point {{ x: 1, y: 2 }}; p.x + p.y", point)),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("t = (1, \"two\"); t.1 + \"three\""),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} Point {{ x: 1, y: 'a' }}", point)),
+            vec!["Type Error: expected `Int`, found `Char` at 1:46"]
+        );
+        assert_eq!(
+            infer(&format!("{} p = Point {{ x: 1 }}; p.z", point)),
+            vec![
+                "Type Error: missing field `y` of `Point` at 1:33",
+                "Type Error: `Point<Int>` has no field or method `z` at 1:51",
+            ]
+        );
+        let shape = "Shape :: | Circle { radius: Float } | Rect { w: Float, h: Float };";
+        assert_eq!(
+            infer(&format!(
+                "{} s = Rect {{ w: 1.0, h: 2.0 }}; match s {{ Rect {{ w, h }} => w * h, _ => 0.0 }}",
+                shape
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} Rect {{ w: 1.0, h: 'a' }}", shape)),
+            vec!["Type Error: expected `Float`, found `Char` at 1:86"]
+        );
+        assert_eq!(
+            infer(&format!("{} Some {{ value: 1 }}", option)),
+            vec![
+                "Type Error: `Some` has no field `value` at 1:44",
+                "Type Error: `Some` has positional fields at 1:37",
+            ]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} match Some(1) {{ Some {{ 'a' }} => 1 }}",
+                option
+            )),
+            vec![
+                "Type Error: expected `Int`, found `Char` at 1:60",
+                "Warning: `match` is not exhaustive at 1:47",
+            ]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} match None {{ Some {{ _ }} => 1, None => 2, _ => 3 }}",
+                option
+            )),
+            vec!["Warning: unreachable arm at 1:78"]
+        );
+        assert_eq!(
+            infer("f = p -> p.x;"),
+            vec!["Type Error: the type of the value must be known to access field `x` at 1:10"]
+        );
+    }
+
+    #[test]
+    fn traits_and_impls() {
+        let infer = |input: &str| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+        let point = "Point<T> :: { x: T, y: T }; \
+            trait Describe = { describe | Self -> String; greet = self -> \"hi \" + self.describe; }; \
+            impl<T> Describe for Point<T> { describe = self -> \"point\"; }; \
+            impl<T> Point<T> { first = self -> self.x; pick = self, b -> if b { self.x } else { self.y }; }; \
+            p = Point { x: 1, y: 2 };";
+
+        assert_eq!(
+            infer(&format!(
+                "{} p.first + p.pick {{ true }} + (p : pick {{ false }})",
+                point
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} f = p.pick; p.greet + describe(p); f {{ true }} + 1",
+                point
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} impl<T> Point<T> {{ sum = self -> 0; len = self -> \"\"; }}; (p : sum) + 1; (p : len) + \"\"",
+                point
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} (p : describe) + 1", point)),
+            vec!["Type Error: expected `String`, found `Int` at 1:306, 1:320"]
+        );
+        assert_eq!(
+            infer(&format!("{} impl Describe for Int {{ }}", point)),
+            vec!["Type Error: missing method `describe` in the impl of `Describe` for `Int` at 1:303"]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} impl<T> Describe for Point<T> {{ describe = self -> \"\"; }}",
+                point
+            )),
+            vec!["Type Error: `Describe` is already implemented for `Point<T>` at 1:303, 1:117"]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} impl Point<Int> {{ first = self -> 1; }}",
+                point
+            )),
+            vec!["Type Error: duplicate method `first` for `Point<Int>` at 1:321"]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} impl Describe for Int {{ describe = self -> 1; size = self -> 1; }}",
+                point
+            )),
+            vec![
+                "Type Error: `size` is not a method of `Describe` at 1:349",
+                "Type Error: `describe` is declared as `Int -> String` but its value has type `Int -> Int` at 1:338, 1:327",
+            ]
+        );
+        assert_eq!(
+            infer("impl Show for Int { }"),
+            vec!["Type Error: unknown trait `Show` at 1:1"]
+        );
+        assert_eq!(
+            infer("impl<T: Show + Show, U: Show> Show for Point<T> { }"),
+            vec!["Type Error: unknown trait `Show` at 1:1"]
+        );
+        assert_eq!(
+            infer("trait Show = { show | Self -> String; show = self -> self.size; };"),
+            vec!["Type Error: `Self` has no field or method `size` at 1:59"]
+        );
+    }
+
+    #[test]
+    fn derives() {
+        let infer = |input: &str| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+        let point = "Point :: { x: Int, y: Int } derive Format, Eq, Ord, Hash, Clone; \
+            p = Point { x: 1, y: 2 };";
+        let shape = "Shape :: | Circle { radius: Float } | Rect(Float, Float)";
+
+        assert_eq!(
+            infer(&format!(
+                "{} p.format + \"!\"; p.hash + p.cmp {{ p.clone }}; p == p && p < p && p.eq {{ p }}",
+                point
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("List<T> :: | Cons(T, List<T>) | Nil derive Eq; Cons(1, Nil) == Nil"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} p.cmp {{ 1 }}", point)),
+            vec!["Type Error: cannot call `Point, Point -> Int` with arguments `(Point, Int)` at 1:93, 1:92, 1:100"]
+        );
+        assert_eq!(
+            infer(&format!("{} derive Eq, Clone;", shape)),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} derive Hash;", shape)),
+            vec![
+                "Type Error: cannot derive `Hash` for `Shape`: the field `radius` of `Circle` has type `Float`, which doesn't implement `Hash` at 1:1",
+                "Type Error: cannot derive `Hash` for `Shape`: the field `0` of `Rect` has type `Float`, which doesn't implement `Hash` at 1:1",
+            ]
+        );
+        assert_eq!(
+            infer("Wrapper :: { f: (Int -> Int) } derive Show, Eq;"),
+            vec![
+                "Type Error: `Show` cannot be derived at 1:1",
+                "Type Error: cannot derive `Eq` for `Wrapper`: the field `f` of `Wrapper` has type `Int -> Int`, which doesn't implement `Eq` at 1:1",
+            ]
+        );
+        assert_eq!(
+            infer(&format!("{}; Circle(1.0) == Rect(1.0, 2.0)", shape)),
+            vec!["Type Error: `Shape` doesn't implement `Eq` at 1:65"]
+        );
+        assert_eq!(
+            infer("f = x -> x + 1; f < f"),
+            vec!["Type Error: `Int -> Int` doesn't implement `Ord` at 1:17"]
+        );
+    }
+
+    #[test]
+    fn bounds() {
+        let infer = |input: &str| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            messages(&ast.infer_types().err().unwrap_or_default())
+        };
+        let describe = "trait Describe = { describe | Self -> String; }; \
+            impl Describe for Int { describe = self -> \"int\"; }; \
+            Point<T> :: { x: T };";
+
+        assert_eq!(
+            infer("id<T> | T -> T; id = x -> x; id { 1 } + 1; id { \"a\" } + \"b\""),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("max<T: Ord> | T, T -> T; max = a, b -> if a > b { a } else { b }; max { 1, 2 } + 1"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("max<T> | T, T -> T; max = a, b -> if a > b { a } else { b };"),
+            vec!["Type Error: `T` doesn't implement `Ord` at 1:38"]
+        );
+        assert_eq!(
+            infer("key<T: Hash> | T -> Int; key = x -> x.hash; key { 1 } + key { 1.5 }"),
+            vec!["Type Error: `Float` doesn't implement `Hash` at 1:57"]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} show | T: Describe -> T -> String; show = x -> x.describe + describe(x); show {{ 1 }}; show {{ true }}",
+                describe
+            )),
+            vec!["Type Error: `Bool` doesn't implement `Describe` at 1:210"]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} impl Point<T: Describe> {{ d = self -> self.x.describe; }}; Point {{ x: 1 }}.d; Point {{ x: 'a' }}.d",
+                describe
+            )),
+            vec!["Type Error: `Char` doesn't implement `Describe` at 1:218"]
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} impl<T> Point<T> {{ d = self -> self.x.describe; }};",
+                describe
+            )),
+            vec!["Type Error: `T` has no field or method `describe` at 1:163"]
+        );
+        assert_eq!(
+            infer(
+                "Pair<T: Eq> :: { a: T, b: T }; Pair { a: 1, b: 2 }; Pair { a: x -> x, b: x -> x }"
+            ),
+            vec!["Type Error: `a -> a` doesn't implement `Eq` at 1:53"]
+        );
+        assert_eq!(
+            infer("show<T: Shout> | T -> T;"),
+            vec!["Type Error: unknown trait `Shout` at 1:1"]
+        );
+        assert_eq!(
+            infer("trait Same = { same | Self, Self -> Bool; same = a, b -> a == b; };"),
+            vec!["Type Error: `Self` doesn't implement `Eq` at 1:58"]
+        );
+    }
+
+    #[test]
+    fn prelude() {
+        let infer = |input: &str| {
+            let (mut ast, diagnostics) = crate::parser::FlatParser::new(input).parse();
+            assert!(diagnostics.is_empty());
+            let mut errors = ast.infer_types().err().unwrap_or_default();
+            errors.extend_from_slice(ast.warnings());
+            messages(&errors)
+        };
+        let half = "half = n -> if n % 2 == 0 { Ok(n / 2) } else { Err(\"odd\") };";
+
+        assert_eq!(
+            infer("x = Some(1) : map { x -> x + 1 } : and_then { x -> Some(x * 2) } : unwrap_or { 0 }; x + 1"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} quarter = n -> {{ h = half(n)?; Ok(half(h).try + 1) }}; quarter(8).unwrap_or {{ 0 }} + 1",
+                half
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("first | Option<Int> -> Int; first = o -> { x = o ? 0; x + 1 }; first(Some(1)) + first(None)"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("Some(1) == None; Ok(1).map { x -> x } == Err('a'); 1..3 : map { x -> x * 2 }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!("{} f = n -> {{ h = half(n)?; h + 1 }};", half)),
+            vec!["Type Error: expected `Result<a, String>`, found `Int` at 1:71"]
+        );
+        assert_eq!(
+            infer("f = n -> { x = Some(n)?; Ok(x) };"),
+            vec!["Type Error: expected `Option<a>`, found `Result<b, c>` at 1:10"]
+        );
+        assert_eq!(
+            infer("h = o -> { v = o?; Some(v + 1) }; x = h(Some(1)) : unwrap_or { 0 }; x + 1"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer(&format!(
+                "{} k = r -> {{ v = r?; Ok(v * 2) }}; k(half(4)) : unwrap_or {{ 0 }}",
+                half
+            )),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            infer("h = o -> { v = o?; Some(v + 1) }; h(1)"),
+            vec!["Type Error: cannot call `Option<Int> -> Option<Int>` with arguments `(Int)` at 1:35, 1:37"]
+        );
+        assert_eq!(
+            infer("f = n -> (n + 1) ? 0; g = o -> o?;"),
+            vec![
+                "Type Error: `?` expects an `Option` or a `Result`, found `Int` at 1:13",
+                "Type Error: the type of the value must be known to use `?` at 1:32",
+            ]
+        );
+    }
+}
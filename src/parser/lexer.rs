@@ -1,357 +1,672 @@
-use super::{Token, TokenKind, TokenKind::*};
+use super::literal::{escape_len, scan_string, Part};
+use super::{Span, Token, TokenKind, TokenKind::*};
 
+// Scans the source one char at a time. `cursor` is the byte offset of the
+// current char, so looking at it is O(1) and every literal is a slice of the
+// source.
 #[derive(Debug)]
-pub struct Lexer {
-    source: String,
+pub struct Lexer<'a> {
+    source: &'a str,
     cursor: usize,
     line: u32,
     column: u32,
 }
 
-impl Iterator for Lexer {
-    type Item = Token;
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.skip_whitespace();
 
         let line = self.line;
         let column = self.column;
+        let start = self.cursor;
 
-        let next = self.curr().map(|curr| match curr {
+        let (literal, kind) = match self.curr()? {
+            'r' if matches!(self.peek(), Some('"' | '#')) => self.read_raw_string(),
             ch if ch.is_alphabetic() || ch == '_' => self.read_word(),
-            ch if ch.is_numeric() => self.read_number(),
+            ch if ch.is_ascii_digit() => self.read_number(),
             '\'' => self.read_char(),
             '"' => self.read_string(),
+            '-' if self.peek() == Some('-') => self.read_line_comment(),
+            '/' if self.peek() == Some('*') => self.read_block_comment(),
             _ => self.read_symbol(),
-        });
-
-        self.bump();
+        };
 
-        next.map(|(literal, kind)| Token {
-            literal,
+        Some(Token {
             kind,
+            literal,
+            span: Span::new(start, self.cursor),
             line,
             column,
         })
     }
 }
 
-impl Lexer {
-    pub fn new(source: &'_ str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
         Lexer {
-            source: source.to_owned(),
+            source,
             cursor: 0,
             line: 1,
             column: 1,
         }
     }
 
+    // lex `source[start..end]`, where `start` is at `line` and `column`
+    pub fn new_at(source: &'a str, start: usize, end: usize, line: u32, column: u32) -> Self {
+        Lexer {
+            source: &source[..end],
+            cursor: start,
+            line,
+            column,
+        }
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
     fn curr(&self) -> Option<char> {
-        self.source.chars().nth(self.cursor)
+        self.source[self.cursor..].chars().next()
     }
 
     fn peek(&self) -> Option<char> {
-        self.source.chars().nth(self.cursor + 1)
+        self.source[self.cursor..].chars().nth(1)
     }
 
-    fn bump(&mut self) {
-        if self.cursor < self.source.len() {
-            self.cursor += 1;
+    // consume the current char and return it
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.curr()?;
+        self.cursor += ch.len_utf8();
 
-            if let Some('\n') = self.peek() {
-                self.line += 1;
-                self.column = 0;
-            } else {
-                self.column += 1;
-            }
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(ch)
+    }
+
+    // consume the current char if it is `expected`
+    fn eat(&mut self, expected: char) -> bool {
+        if self.curr() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
         }
     }
 
+    // the source consumed since `start`
+    fn slice(&self, start: usize) -> &'a str {
+        &self.source[start..self.cursor]
+    }
+
     fn skip_whitespace(&mut self) {
-        while let Some(c) = self.curr() {
-            if c.is_whitespace() {
-                self.bump();
-            } else {
-                break;
-            }
+        while self.curr().is_some_and(char::is_whitespace) {
+            self.bump();
         }
     }
 
-    fn read_word(&mut self) -> (String, TokenKind) {
+    fn read_word(&mut self) -> (&'a str, TokenKind) {
         let start = self.cursor;
 
-        if let Some('_') = self.curr() {
-            if self.peek().is_none() || self.peek().is_some_and(|peek| peek.is_whitespace()) {
-                return (self.source[start..start + 1].to_owned(), Underscore);
-            }
+        if self.eat('_') && self.curr().map_or(true, char::is_whitespace) {
+            return (self.slice(start), Underscore);
+        }
+
+        while self.curr().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.bump();
+        }
+
+        let word = self.slice(start);
+
+        let kind = TokenKind::keyword(word).unwrap_or(Identifier);
+
+        (word, kind)
+    }
+
+    // `42`, `1_000`, `0xff`, `0o17`, `0b1010`, `2.5`, `1e-9` and `6.02E23`,
+    // each with an optional suffix such as `42i32` or `1.0f32`. The literal is
+    // the whole number, suffix included.
+    fn read_number(&mut self) -> (&'a str, TokenKind) {
+        let start = self.cursor;
+
+        let radix = match (self.curr(), self.peek()) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10,
+        };
+
+        if radix != 10 {
+            self.bump();
+            self.bump();
+        }
+
+        let mut digits = self.read_digits(radix);
+        let mut float = false;
+
+        // `0..10` is a range and `1.max` a method call, not floats
+        let fraction = self.curr() == Some('.') && self.peek().is_some_and(|c| c.is_ascii_digit());
+
+        if radix == 10 && fraction {
+            self.bump();
+            digits = digits.and(self.read_digits(10));
+            float = true;
         }
 
-        while let Some(c) = self.peek() {
-            if c.is_alphanumeric() || c == '_' {
+        if radix == 10 && matches!(self.curr(), Some('e' | 'E')) {
+            self.bump();
+            if matches!(self.curr(), Some('+' | '-')) {
                 self.bump();
-            } else {
-                break;
             }
+            digits = digits.and(self.read_digits(10));
+            float = true;
+        }
+
+        let suffix = self.cursor;
+        while self.curr().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.bump();
         }
 
-        let word = &self.source[start..self.cursor + 1];
-
-        let kind = match word {
-            "true" | "false" => Bool,
-            "use" => Use,
-            "mut" => Mut,
-            "if" => If,
-            "else" => Else,
-            "return" => Return,
-            "for" => For,
-            "break" => Break,
-            "continue" => Continue,
-            "match" => Match,
-            "pub" => Pub,
-            "fn" => Function,
-            "type" => TypeDef,
-            "impl" => Impl,
-            "trait" => Trait,
-            "where" => Where,
-            "as" => As,
-            "derive" => Derive,
-            "spawn" => Spawn,
-            _ => Ident,
+        let kind = match (digits, float, self.slice(suffix)) {
+            (Err(kind), ..) => kind,
+            (Ok(()), false, "" | "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64") => {
+                Int
+            }
+            (Ok(()), true, "") => Float,
+            (Ok(()), _, "f32" | "f64") if radix == 10 => Float,
+            _ => InvalidSuffix,
         };
 
-        (word.to_owned(), kind)
+        (self.slice(start), kind)
     }
 
-    fn read_number(&mut self) -> (String, TokenKind) {
-        let start = self.cursor;
+    // a run of digits and `_` separators, which needs at least one digit.
+    // Decimal digits that are too big for `radix` are consumed as invalid.
+    fn read_digits(&mut self, radix: u32) -> Result<(), TokenKind> {
+        let mut result = Err(MissingDigits);
 
-        while let Some(c) = self.peek() {
-            if c.is_numeric() || c == '.' {
+        while let Some(c) = self.curr() {
+            if c == '_' {
                 self.bump();
-            } else {
-                break;
+                continue;
+            }
+
+            match c.to_digit(radix.max(10)) {
+                Some(digit) if digit < radix => {
+                    if result == Err(MissingDigits) {
+                        result = Ok(());
+                    }
+                }
+                Some(_) => result = Err(InvalidDigit),
+                None => break,
             }
+
+            self.bump();
         }
 
-        let number_str = &self.source[start..self.cursor + 1];
-        let kind = if number_str.contains(".") { Float } else { Int };
-        (number_str.to_owned(), kind)
+        result
+    }
+
+    // consume chars up to the byte offset `end`
+    fn bump_to(&mut self, end: usize) {
+        while self.cursor < end && self.bump().is_some() {}
     }
 
-    fn read_char(&mut self) -> (String, TokenKind) {
+    // the literal of a char is the text between the quotes: `a` or `\n`. A
+    // quote before a word that isn't closed right after its first char is a
+    // loop label instead, whose literal is the word: `'outer`.
+    fn read_char(&mut self) -> (&'a str, TokenKind) {
         let start = self.cursor;
         self.bump();
-        let ch = self.source[self.cursor..self.cursor + 1].to_owned();
 
-        if self.curr().is_some() {
-            self.bump();
+        let inner = self.cursor;
+        let mut kind = Char;
+
+        if self.curr().is_some_and(|c| c.is_alphabetic() || c == '_') && self.peek() != Some('\'') {
+            while self.curr().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                self.bump();
+            }
+            return (self.slice(inner), Label);
+        }
 
-            if let Some('\'') = self.curr() {
+        match self.curr() {
+            Some('\\') => match escape_len(&self.source[inner..]) {
+                Some(len) => self.bump_to(inner + len),
+                None => {
+                    kind = InvalidEscape;
+                    self.bump();
+
+                    // `\u{D800}` runs to its brace, so the char ends after it
+                    if self.eat('u') {
+                        if self.curr() == Some('{') {
+                            while let Some(c) = self.curr().filter(|c| !matches!(c, '\'' | '\n')) {
+                                self.bump();
+                                if c == '}' {
+                                    break;
+                                }
+                            }
+                        }
+                    } else {
+                        self.bump();
+                    }
+                }
+            },
+            Some(_) => {
                 self.bump();
-                (ch, Char)
-            } else {
-                (self.source[start..self.cursor + 1].to_owned(), Unknown)
             }
+            None => return (self.slice(start), Unknown),
+        }
+
+        let ch = self.slice(inner);
+
+        if self.eat('\'') {
+            (ch, kind)
         } else {
-            (self.source[start..self.cursor + 1].to_owned(), Unknown)
+            (self.slice(start), Unknown)
         }
     }
 
-    fn read_string(&mut self) -> (String, TokenKind) {
+    // the literal of a string is the text between the quotes, which may span
+    // lines. Strings that interpolate expressions are templates.
+    fn read_string(&mut self) -> (&'a str, TokenKind) {
         self.bump();
         let start = self.cursor;
+        let scanned = scan_string(&self.source[start..]);
 
-        while let Some(ch) = self.peek() {
-            self.bump();
+        let Some(end) = scanned.end else {
+            self.bump_to(self.source.len());
+            return (self.slice(start), UntermDoubleQuote);
+        };
 
-            if ch == '\n' {
-                return (
-                    self.source[start..self.cursor + 1].to_owned(),
-                    UntermDoubleQuote,
-                );
-            }
+        self.bump_to(start + end);
+        let text = self.slice(start);
+        self.bump();
+
+        let kind = if scanned.invalid_escape {
+            InvalidEscape
+        } else if scanned
+            .parts
+            .iter()
+            .any(|part| matches!(part, Part::Interpolation(_)))
+        {
+            Template
+        } else {
+            Str
+        };
+
+        (text, kind)
+    }
+
+    // `r"C:\path"` or `r#"say "hi""#`, with neither escapes nor interpolation.
+    // The literal is the text between the quotes.
+    fn read_raw_string(&mut self) -> (&'a str, TokenKind) {
+        let start = self.cursor;
+        self.bump();
+
+        let hashes = self.cursor;
+        while self.eat('#') {}
+        let hashes = self.cursor - hashes;
+
+        if !self.eat('"') {
+            return (self.slice(start), Unknown);
+        }
+
+        let text = self.cursor;
+        let close = format!("\"{}", "#".repeat(hashes));
 
-            if ch == '"' {
-                break;
+        match self.source[text..].find(&close) {
+            Some(end) => {
+                self.bump_to(text + end);
+                let literal = self.slice(text);
+                self.bump_to(text + end + close.len());
+                (literal, RawStr)
+            }
+            None => {
+                self.bump_to(self.source.len());
+                (self.slice(text), UntermDoubleQuote)
             }
         }
+    }
 
-        if self.peek().is_none() {
-            return (
-                self.source[start..self.cursor + 1].to_owned(),
-                UntermDoubleQuote,
-            );
+    // `-- comment` or `--- doc comment`, whose literal is the text after the
+    // dashes. Four or more dashes make an ordinary comment, such as a divider.
+    fn read_line_comment(&mut self) -> (&'a str, TokenKind) {
+        self.bump();
+        self.bump();
+
+        let kind = if self.curr() == Some('-') && self.peek() != Some('-') {
+            self.bump();
+            DocComment
+        } else {
+            Comment
+        };
+
+        let start = self.cursor;
+        while self.curr().is_some_and(|c| c != '\n') {
+            self.bump();
         }
 
-        (self.source[start..self.cursor].to_owned(), Str)
+        (self.slice(start), kind)
     }
 
-    fn read_symbol(&mut self) -> (String, TokenKind) {
+    // `/* comment */`, which may contain other block comments. Its literal is
+    // the text between the outermost delimiters.
+    fn read_block_comment(&mut self) -> (&'a str, TokenKind) {
+        self.bump();
+        self.bump();
+
         let start = self.cursor;
-        let kind = if let Some(curr) = self.curr() {
-            match curr {
-                '+' => {
-                    if let Some('=') = self.peek() {
-                        self.bump();
-                        PlusAssign
-                    } else {
-                        Plus
-                    }
-                }
-                '=' => {
-                    if let Some('>') = self.peek() {
-                        self.bump();
-                        DoubleArrow
-                    } else if let Some('=') = self.peek() {
-                        self.bump();
-                        Equal
-                    } else {
-                        Assign
-                    }
+        let mut depth = 1;
+
+        loop {
+            match (self.curr(), self.peek()) {
+                (Some('/'), Some('*')) => {
+                    self.bump();
+                    self.bump();
+                    depth += 1;
                 }
-                '-' => {
-                    if let Some('>') = self.peek() {
-                        self.bump();
-                        Arrow
-                    } else if let Some('=') = self.peek() {
-                        self.bump();
-                        MinusAssign
-                    } else {
-                        Minus
-                    }
-                }
-                '/' => {
-                    if let Some('=') = self.peek() {
-                        self.bump();
-                        DivideAssign
-                    } else {
-                        Divide
+                (Some('*'), Some('/')) => {
+                    let text = self.slice(start);
+                    self.bump();
+                    self.bump();
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return (text, Comment);
                     }
                 }
-                '%' => {
-                    if let Some('=') = self.peek() {
-                        self.bump();
-                        ModuloAssign
-                    } else {
-                        Modulo
-                    }
+                (Some(_), _) => {
+                    self.bump();
                 }
-                '<' => {
-                    if let Some('<') = self.peek() {
-                        self.bump();
+                (None, _) => return (self.slice(start), UntermBlockComment),
+            }
+        }
+    }
 
-                        if let Some('=') = self.peek() {
-                            self.bump();
-                            LeftShiftAssign
-                        } else {
-                            LeftShift
-                        }
-                    } else if let Some('=') = self.peek() {
-                        self.bump();
-                        LessEqual
-                    } else {
-                        LessThan
-                    }
-                }
-                '>' => {
-                    if let Some('>') = self.peek() {
-                        self.bump();
+    fn read_symbol(&mut self) -> (&'a str, TokenKind) {
+        let start = self.cursor;
 
-                        if let Some('=') = self.peek() {
-                            self.bump();
-                            RightShiftAssign
-                        } else {
-                            RightShift
-                        }
-                    } else if let Some('=') = self.peek() {
-                        self.bump();
-                        GreaterEqual
-                    } else {
-                        GreaterThan
-                    }
-                }
-                '!' => {
-                    if let Some('=') = self.peek() {
-                        self.bump();
-                        NotEqual
-                    } else {
-                        Not
-                    }
-                }
-                '&' => {
-                    if let Some('&') = self.peek() {
-                        self.bump();
-                        And
-                    } else if let Some('=') = self.peek() {
-                        self.bump();
-                        BitAndAssign
-                    } else {
-                        Ampersand
-                    }
-                }
-                '|' => {
-                    if let Some('|') = self.peek() {
-                        self.bump();
-                        Or
-                    } else if let Some('=') = self.peek() {
-                        self.bump();
-                        BitOrAssign
-                    } else {
-                        Pipe
-                    }
+        let kind = match self.bump() {
+            Some('+') if self.eat('=') => PlusAssign,
+            Some('+') => Plus,
+            Some('=') if self.eat('>') => ArrowRight,
+            Some('=') if self.eat('=') => Equal,
+            Some('=') => Assign,
+            Some('-') if self.eat('>') => Arrow,
+            Some('-') if self.eat('=') => MinusAssign,
+            Some('-') => Minus,
+            Some('/') if self.eat('=') => DivideAssign,
+            Some('/') => Divide,
+            Some('%') if self.eat('=') => ModuloAssign,
+            Some('%') => Modulo,
+            Some('<') if self.eat('<') => {
+                if self.eat('=') {
+                    LeftShiftAssign
+                } else {
+                    LeftShift
                 }
-                '*' => {
-                    if let Some('*') = self.peek() {
-                        self.bump();
-
-                        if let Some('=') = self.peek() {
-                            self.bump();
-                            PowerAssign
-                        } else {
-                            Power
-                        }
-                    } else if let Some('=') = self.peek() {
-                        self.bump();
-                        MultiplyAssign
-                    } else {
-                        Multiply
-                    }
+            }
+            Some('<') if self.eat('=') => LessEqual,
+            Some('<') => LessThan,
+            Some('>') if self.eat('>') => {
+                if self.eat('=') {
+                    RightShiftAssign
+                } else {
+                    RightShift
                 }
-                '^' => {
-                    if let Some('=') = self.peek() {
-                        self.bump();
-                        BitXorAssign
-                    } else {
-                        Caret
-                    }
+            }
+            Some('>') if self.eat('=') => GreaterEqual,
+            Some('>') => GreaterThan,
+            Some('!') if self.eat('=') => NotEqual,
+            Some('!') => Not,
+            Some('&') if self.eat('&') => And,
+            Some('&') if self.eat('=') => BitAndAssign,
+            Some('&') => Ampersand,
+            Some('|') if self.eat('|') => Or,
+            Some('|') if self.eat('=') => BitOrAssign,
+            Some('|') => Pipe,
+            Some('*') if self.eat('*') => {
+                if self.eat('=') {
+                    PowerAssign
+                } else {
+                    Power
                 }
-                ':' => {
-                    if let Some(':') = self.peek() {
-                        self.bump();
-                        DoubleColon
-                    } else {
-                        Colon
-                    }
+            }
+            Some('*') if self.eat('=') => MultiplyAssign,
+            Some('*') => Multiply,
+            Some('^') if self.eat('=') => BitXorAssign,
+            Some('^') => Caret,
+            Some(':') if self.eat(':') => DoubleColon,
+            Some(':') => Colon,
+            Some('~') => Tilde,
+            Some(';') => Semicolon,
+            Some(',') => Comma,
+            Some('.') if self.eat('.') => {
+                if self.eat('=') {
+                    DoubleDotEqual
+                } else {
+                    DoubleDot
                 }
-                '~' => Tilde,
-                ';' => Semicolon,
-                ',' => Comma,
-                '.' => Dot,
-                '(' => OpenParen,
-                ')' => CloseParen,
-                '{' => OpenBrace,
-                '}' => CloseBrace,
-                '[' => OpenBracket,
-                ']' => CloseBracket,
-                '@' => At,
-                '?' => Question,
-                _ => Unknown,
             }
-        } else {
-            Unknown
+            Some('.') => Dot,
+            Some('(') => OpenParen,
+            Some(')') => CloseParen,
+            Some('{') => OpenBrace,
+            Some('}') => CloseBrace,
+            Some('[') => OpenBracket,
+            Some(']') => CloseBracket,
+            Some('@') => At,
+            Some('?') => Question,
+            _ => Unknown,
+        };
+
+        (self.slice(start), kind)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<(TokenKind, &str, Span)> {
+        Lexer::new(source)
+            .map(|token| (token.kind, token.literal, token.span))
+            .collect()
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        assert_eq!(
+            tokens("größe = \"naïve\" + 'é';"),
+            vec![
+                (Identifier, "größe", Span::new(0, 7)),
+                (Assign, "=", Span::new(8, 9)),
+                (Str, "naïve", Span::new(10, 18)),
+                (Plus, "+", Span::new(19, 20)),
+                (Char, "é", Span::new(21, 25)),
+                (Semicolon, ";", Span::new(25, 26)),
+            ]
+        );
+    }
+
+    #[test]
+    fn literals_borrow_from_the_source() {
+        let source = "x = 1..10";
+        let literal = Lexer::new(source).next().unwrap().literal;
+        assert_eq!(literal.as_ptr(), source.as_ptr());
+
+        let kinds: Vec<_> = tokens(source).into_iter().map(|(kind, ..)| kind).collect();
+        assert_eq!(kinds, vec![Identifier, Assign, Int, DoubleDot, Int]);
+    }
+
+    #[test]
+    fn lines_and_columns_count_chars() {
+        let positions: Vec<_> = Lexer::new("ñ = 1\n  \"ü\" é")
+            .map(|token| (token.line, token.column))
+            .collect();
+        assert_eq!(positions, vec![(1, 1), (1, 3), (1, 5), (2, 3), (2, 7)]);
+    }
+
+    #[test]
+    fn malformed_literals() {
+        // strings may span lines, so an unclosed one runs to the end
+        assert_eq!(
+            tokens("\"ab\ncd"),
+            vec![(UntermDoubleQuote, "ab\ncd", Span::new(0, 6))]
+        );
+        assert_eq!(tokens("'€"), vec![(Unknown, "'€", Span::new(0, 4))]);
+        assert_eq!(tokens("€"), vec![(Unknown, "€", Span::new(0, 3))]);
+    }
+
+    #[test]
+    fn numbers() {
+        let kinds = |source| -> Vec<_> {
+            tokens(source)
+                .into_iter()
+                .map(|(kind, literal, _)| (kind, literal))
+                .collect()
         };
 
-        let literal = self.source[start..self.cursor + 1].to_owned();
-        (literal, kind)
+        assert_eq!(
+            kinds("1_000 0xFF_u8 0o17 0b1010 2.5 1e-9 6.02E+23 42i32 1.0f32 7f64"),
+            vec![
+                (Int, "1_000"),
+                (Int, "0xFF_u8"),
+                (Int, "0o17"),
+                (Int, "0b1010"),
+                (Float, "2.5"),
+                (Float, "1e-9"),
+                (Float, "6.02E+23"),
+                (Int, "42i32"),
+                (Float, "1.0f32"),
+                (Float, "7f64"),
+            ]
+        );
+        assert_eq!(kinds("1.2.3"), vec![(Float, "1.2"), (Dot, "."), (Int, "3")]);
+        assert_eq!(
+            kinds("0..10 1.max"),
+            vec![
+                (Int, "0"),
+                (DoubleDot, ".."),
+                (Int, "10"),
+                (Int, "1"),
+                (Dot, "."),
+                (Identifier, "max")
+            ]
+        );
+        assert_eq!(
+            kinds("0x 1e 1e+ 0b102 0o9 42px 1.5i32 0x1f64 0b1f32"),
+            vec![
+                (MissingDigits, "0x"),
+                (MissingDigits, "1e"),
+                (MissingDigits, "1e+"),
+                (InvalidDigit, "0b102"),
+                (InvalidDigit, "0o9"),
+                (InvalidSuffix, "42px"),
+                (InvalidSuffix, "1.5i32"),
+                (Int, "0x1f64"),
+                (InvalidSuffix, "0b1f32"),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            tokens(r#""a\tb\"" '\n' "\q""#),
+            vec![
+                (Str, r#"a\tb\""#, Span::new(0, 8)),
+                (Char, r"\n", Span::new(9, 13)),
+                (InvalidEscape, r"\q", Span::new(14, 18)),
+            ]
+        );
+        assert_eq!(
+            tokens(r"'\u{D800}' '\u{110000}' '\u'"),
+            vec![
+                (InvalidEscape, r"\u{D800}", Span::new(0, 10)),
+                (InvalidEscape, r"\u{110000}", Span::new(11, 23)),
+                (InvalidEscape, r"\u", Span::new(24, 28)),
+            ]
+        );
+        assert_eq!(
+            tokens(r###"r"C:\dir" r#"say "hi""#"###),
+            vec![
+                (RawStr, r"C:\dir", Span::new(0, 9)),
+                (RawStr, r#"say "hi""#, Span::new(10, 23)),
+            ]
+        );
+        assert_eq!(
+            tokens(r#""{x} + {"}"} {}""#),
+            vec![(Template, r#"{x} + {"}"} {}"#, Span::new(0, 16))]
+        );
+        assert_eq!(
+            tokens("\"line\n  two\""),
+            vec![(Str, "line\n  two", Span::new(0, 12))]
+        );
+        assert_eq!(
+            tokens("r#\"never closed\""),
+            vec![(UntermDoubleQuote, "never closed\"", Span::new(0, 16))]
+        );
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            tokens("'outer: 'a' 'b 'λx"),
+            vec![
+                (Label, "outer", Span::new(0, 6)),
+                (Colon, ":", Span::new(6, 7)),
+                (Char, "a", Span::new(8, 11)),
+                (Label, "b", Span::new(12, 14)),
+                (Label, "λx", Span::new(15, 19)),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            tokens("x -- the answer\n--- doc\n---\n---- divider"),
+            vec![
+                (Identifier, "x", Span::new(0, 1)),
+                (Comment, " the answer", Span::new(2, 15)),
+                (DocComment, " doc", Span::new(16, 23)),
+                (DocComment, "", Span::new(24, 27)),
+                (Comment, "-- divider", Span::new(28, 40)),
+            ]
+        );
+        assert_eq!(
+            tokens("a /* outer /* inner */ still outer */ - b"),
+            vec![
+                (Identifier, "a", Span::new(0, 1)),
+                (Comment, " outer /* inner */ still outer ", Span::new(2, 37)),
+                (Minus, "-", Span::new(38, 39)),
+                (Identifier, "b", Span::new(40, 41)),
+            ]
+        );
+        assert_eq!(
+            tokens("1 /* /* */"),
+            vec![
+                (Int, "1", Span::new(0, 1)),
+                (UntermBlockComment, " /* */", Span::new(2, 10)),
+            ]
+        );
+        assert_eq!(
+            tokens("a - -b / c"),
+            vec![
+                (Identifier, "a", Span::new(0, 1)),
+                (Minus, "-", Span::new(2, 3)),
+                (Minus, "-", Span::new(4, 5)),
+                (Identifier, "b", Span::new(5, 6)),
+                (Divide, "/", Span::new(7, 8)),
+                (Identifier, "c", Span::new(9, 10)),
+            ]
+        );
     }
 }
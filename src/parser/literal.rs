@@ -0,0 +1,329 @@
+// Values of the literals accepted by the lexer
+use std::ops::Range;
+
+// `0xff`, `1_000` or `42u8`, checked against the range of the suffix
+pub fn parse_int(literal: &str) -> Result<i64, String> {
+    parse_signed_int(literal, false)
+}
+
+// the literal of `-9223372036854775808`, which is only in range once negated,
+// so the signed types reach one further below zero
+pub fn parse_negated_int(literal: &str) -> Result<i64, String> {
+    parse_signed_int(literal, true)
+}
+
+fn parse_signed_int(literal: &str, negated: bool) -> Result<i64, String> {
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x") => (16, &literal[2..]),
+        Some("0o") => (8, &literal[2..]),
+        Some("0b") => (2, &literal[2..]),
+        _ => (10, literal),
+    };
+
+    let end = digits
+        .find(|c: char| c != '_' && !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let (digits, suffix) = digits.split_at(end);
+
+    let (max, signed) = match suffix {
+        "i8" => (i8::MAX as u64, true),
+        "i16" => (i16::MAX as u64, true),
+        "i32" => (i32::MAX as u64, true),
+        "u8" => (u8::MAX as u64, false),
+        "u16" => (u16::MAX as u64, false),
+        "u32" => (u32::MAX as u64, false),
+        _ => (i64::MAX as u64, true),
+    };
+    let max = if negated && signed { max + 1 } else { max };
+
+    let digits = digits.replace('_', "");
+    match u64::from_str_radix(&digits, radix) {
+        Ok(int) if int <= max && negated => Ok((int as i64).wrapping_neg()),
+        Ok(int) if int <= max => Ok(int as i64),
+        _ => Err(format!(
+            "integer literal `{}{}` is out of range for `{}`",
+            if negated { "-" } else { "" },
+            literal,
+            if suffix.is_empty() { "Int" } else { suffix }
+        )),
+    }
+}
+
+// `2.5`, `1e-9` or `1.5f32`, which must be finite
+pub fn parse_float(literal: &str) -> Result<f64, String> {
+    let (digits, suffix) = match literal.strip_suffix("f32") {
+        Some(digits) => (digits, "f32"),
+        None => (literal.strip_suffix("f64").unwrap_or(literal), "Float"),
+    };
+
+    let digits = digits.replace('_', "");
+    let float = match suffix {
+        "f32" => digits.parse::<f32>().map(f64::from),
+        _ => digits.parse::<f64>(),
+    };
+
+    match float {
+        Ok(float) if float.is_finite() => Ok(float),
+        Ok(_) => Err(format!(
+            "float literal `{}` is out of range for `{}`",
+            literal, suffix
+        )),
+        Err(_) => Err(format!("invalid float literal `{}`", literal)),
+    }
+}
+
+// A piece of the text between the quotes of a string, by its byte range in
+// that text
+#[derive(Clone, Debug, PartialEq)]
+pub enum Part {
+    Text(Range<usize>),
+    // the source of the expression in `{x + 1}`, without the braces
+    Interpolation(Range<usize>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScannedString {
+    // where the closing quote is, unless the string is unterminated
+    pub end: Option<usize>,
+    pub parts: Vec<Part>,
+    pub invalid_escape: bool,
+}
+
+// Scan the text after the opening quote of a string up to its closing quote.
+// Braces around an expression interpolate it, while `{}` is left alone for
+// format strings and `\{` is a literal brace.
+pub fn scan_string(text: &str) -> ScannedString {
+    let bytes = text.as_bytes();
+    let mut scanned = ScannedString {
+        end: None,
+        parts: Vec::new(),
+        invalid_escape: false,
+    };
+
+    let (mut i, mut text_start) = (0, 0);
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                if text_start < i {
+                    scanned.parts.push(Part::Text(text_start..i));
+                }
+                scanned.end = Some(i);
+                return scanned;
+            }
+            b'\\' => match escape_len(&text[i..]) {
+                Some(len) => i += len,
+                None => {
+                    scanned.invalid_escape = true;
+                    i += 1;
+                }
+            },
+            b'{' if bytes.get(i + 1) == Some(&b'}') => i += 2,
+            b'{' => {
+                if text_start < i {
+                    scanned.parts.push(Part::Text(text_start..i));
+                }
+
+                let start = i + 1;
+                let Some(end) = interpolation_end(text, start) else {
+                    return scanned;
+                };
+
+                scanned.parts.push(Part::Interpolation(start..end));
+                i = end + 1;
+                text_start = i;
+            }
+            _ => i += 1,
+        }
+    }
+
+    // a token's literal has no closing quote, so the last text ends here
+    if text_start < i {
+        scanned.parts.push(Part::Text(text_start..i));
+    }
+
+    scanned
+}
+
+// the offset of the brace closing the interpolation that starts at `start`
+fn interpolation_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let (mut i, mut depth) = (start, 1);
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            // strings inside the expression
+            b'"' => i += scan_string(&text[i + 1..]).end? + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+// the length of the escape sequence at the start of `text`: `\n`, `\t`, `\r`,
+// `\0`, `\\`, `\"`, `\'`, `\{`, `\}`, `\u{1F600}`, or a backslash at the end of a
+// line, which skips the line break and the indentation after it
+pub fn escape_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('\\')?;
+
+    match rest.chars().next()? {
+        'n' | 't' | 'r' | '0' | '\\' | '"' | '\'' | '{' | '}' | '\n' => Some(2),
+        'u' => {
+            // only look as far as the longest valid escape, so lexing one
+            // never depends on the rest of the source
+            let hex = rest[1..].strip_prefix('{')?;
+            let end = hex.bytes().take(7).position(|b| b == b'}')?;
+            let valid = (1..=6).contains(&end)
+                && u32::from_str_radix(&hex[..end], 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .is_some();
+            valid.then_some(end + 4)
+        }
+        _ => None,
+    }
+}
+
+// the text of a string or char literal with its escapes replaced
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find('\\') {
+        unescaped.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let Some(len) = escape_len(rest) else {
+            unescaped.push('\\');
+            rest = &rest[1..];
+            continue;
+        };
+
+        match rest.as_bytes()[1] {
+            b'n' => unescaped.push('\n'),
+            b't' => unescaped.push('\t'),
+            b'r' => unescaped.push('\r'),
+            b'0' => unescaped.push('\0'),
+            b'u' => {
+                let code = u32::from_str_radix(&rest[3..len - 1], 16).unwrap();
+                unescaped.push(char::from_u32(code).unwrap());
+            }
+            b'\n' => {
+                rest = rest[len..].trim_start();
+                continue;
+            }
+            escaped => unescaped.push(escaped as char),
+        }
+
+        rest = &rest[len..];
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ints() {
+        assert_eq!(parse_int("42"), Ok(42));
+        assert_eq!(parse_int("1_000_000"), Ok(1_000_000));
+        assert_eq!(parse_int("0xff"), Ok(255));
+        assert_eq!(parse_int("0xDEAD_beef"), Ok(0xdead_beef));
+        assert_eq!(parse_int("0o17"), Ok(15));
+        assert_eq!(parse_int("0b1010_1010"), Ok(170));
+        assert_eq!(parse_int("255u8"), Ok(255));
+        assert_eq!(parse_int("0x7fi8"), Ok(127));
+        assert_eq!(parse_int("9223372036854775807"), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn int_overflow() {
+        assert_eq!(
+            parse_int("256u8"),
+            Err("integer literal `256u8` is out of range for `u8`".to_owned())
+        );
+        assert_eq!(
+            parse_int("0x80i8"),
+            Err("integer literal `0x80i8` is out of range for `i8`".to_owned())
+        );
+        assert_eq!(
+            parse_int("9223372036854775808"),
+            Err("integer literal `9223372036854775808` is out of range for `Int`".to_owned())
+        );
+        assert!(parse_int("18446744073709551615u64").is_err());
+        assert_eq!(parse_negated_int("9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(parse_negated_int("0x80i8"), Ok(-128));
+        assert_eq!(
+            parse_negated_int("9223372036854775809"),
+            Err("integer literal `-9223372036854775809` is out of range for `Int`".to_owned())
+        );
+        assert_eq!(
+            parse_negated_int("256u8"),
+            Err("integer literal `-256u8` is out of range for `u8`".to_owned())
+        );
+        assert!(parse_int("99999999999999999999999").is_err());
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(parse_float("2.5"), Ok(2.5));
+        assert_eq!(parse_float("1e-9"), Ok(1e-9));
+        assert_eq!(parse_float("6.02E23"), Ok(6.02e23));
+        assert_eq!(parse_float("1_000.000_1"), Ok(1000.0001));
+        assert_eq!(parse_float("1f64"), Ok(1.0));
+        assert_eq!(parse_float("0.1f32"), Ok(0.1f32 as f64));
+        assert_eq!(
+            parse_float("1e39f32"),
+            Err("float literal `1e39f32` is out of range for `f32`".to_owned())
+        );
+        assert!(parse_float("1e400").is_err());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape(r"a\tb\nc"), "a\tb\nc");
+        assert_eq!(unescape(r#"\"quoted\" \\ \' \{x\}"#), r#""quoted" \ ' {x}"#);
+        assert_eq!(unescape(r"\u{48}\u{1F600}\0"), "H\u{1F600}\0");
+        assert_eq!(unescape("one \\\n     two"), "one two");
+        assert_eq!(escape_len(r"\u{110000}"), None);
+        assert_eq!(escape_len(r"\u{}"), None);
+        assert_eq!(escape_len(r"\q"), None);
+    }
+
+    #[test]
+    fn strings() {
+        let scanned = scan_string(r#"a \" {} b" rest"#);
+        assert_eq!(scanned.end, Some(9));
+        assert_eq!(scanned.parts, vec![Part::Text(0..9)]);
+        assert!(!scanned.invalid_escape);
+
+        let scanned = scan_string(r#"x = {x}, {f { "}", y }}!" rest"#);
+        assert_eq!(scanned.end, Some(24));
+        assert_eq!(
+            scanned.parts,
+            vec![
+                Part::Text(0..4),
+                Part::Interpolation(5..6),
+                Part::Text(7..9),
+                Part::Interpolation(10..22),
+                Part::Text(23..24),
+            ]
+        );
+
+        assert!(scan_string(r#"\q""#).invalid_escape);
+        assert_eq!(scan_string("open {x").end, None);
+        assert_eq!(scan_string("no end").end, None);
+    }
+}
@@ -1,140 +1,2015 @@
 mod ast;
+mod errors;
+mod exhaustive;
+pub mod flat_ast;
+mod flat_parser;
+mod infer;
 mod lexer;
 mod list;
+mod literal;
+mod stream;
 mod token;
 
-pub use ast::*;
-use lexer::*;
+// `Function`, `Type`, `Match`, the loops, `Trait` and `Impl` are named
+// explicitly so they win over the `TokenKind` glob
+pub use ast::{For, Function, Impl, Loop, Match, Trait, Type, While, *};
+pub use errors::{Diagnostic, Label, Severity};
+pub use flat_parser::FlatParser;
+pub use infer::TypeError;
+pub use lexer::Lexer;
 use list::*;
-use token::*;
+pub(crate) use literal::{parse_float, parse_int, parse_negated_int, unescape};
+use literal::{scan_string, Part};
+pub use stream::TokenStream;
+pub use token::{Span, Token, TokenKind, KEYWORDS};
 
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
 use TokenKind::*;
 
-pub struct Parser {
-    lexer: Lexer,
-    curr: Option<Token>,
-    peek: Option<Token>,
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    curr: Option<Token<'a>>,
+    peek: Option<Token<'a>>,
+    // tokens after `peek`, buffered by `nth_is`
+    lookahead: VecDeque<Token<'a>>,
+    // where the last consumed token ends
+    prev_end: usize,
+    // how many delimiters are open at the current token
+    depth: usize,
+    // the syntax errors found so far
+    diagnostics: Vec<Diagnostic>,
+    // the comments lexed so far
+    trivia: Vec<Trivia>,
+    // the lines of the doc comments right above a token, by where it starts
+    docs: HashMap<usize, Vec<&'a str>>,
+    // the doc comments waiting for the token they document
+    pending_docs: Vec<&'a str>,
+    // whether `f { a }` is a call, which it isn't in the head of a loop or
+    // conditional, where the brace opens the body
+    brace_calls: bool,
+    // the loops around the current token, innermost last
+    loops: Vec<LoopScope<'a>>,
 }
 
-pub type ParseError = (&'static str, Option<Token>);
+// a loop that `break` and `continue` can refer to
+struct LoopScope<'a> {
+    label: Option<&'a str>,
+    // only `loop` can `break` with a value
+    value: bool,
+}
+
+pub type ParseError = Diagnostic;
 pub type Result<T> = std::result::Result<T, ParseError>;
 
-impl Parser {
-    pub fn new(source: &'_ str) -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self::with_lexer(Lexer::new(source))
+    }
+
+    fn with_lexer(lexer: Lexer<'a>) -> Self {
         let mut parser = Self {
-            lexer: Lexer::new(source),
+            lexer,
             curr: None,
             peek: None,
+            lookahead: VecDeque::new(),
+            prev_end: 0,
+            depth: 0,
+            diagnostics: Vec::new(),
+            trivia: Vec::new(),
+            docs: HashMap::new(),
+            pending_docs: Vec::new(),
+            brace_calls: true,
+            loops: Vec::new(),
         };
         parser.bump();
         parser.bump();
         parser
     }
 
-    pub fn parse(&mut self) -> Result<Ast> {
+    // parse the whole input, skipping over the statements with syntax errors,
+    // and return the statements with every error found
+    pub fn parse(&mut self) -> (Ast, Vec<Diagnostic>) {
         let mut ast = Ast::new();
 
-        while let Some(start) = self.curr.take() {
-            let stmt = self.parse_stmt(start)?;
-            ast.push(stmt);
+        while let Some(token) = &self.curr {
+            match token.kind {
+                Semicolon => {
+                    self.bump();
+                }
+                CloseBrace => self.skip_unmatched_brace(),
+                _ => ast.push(self.parse_stmt_or_recover()),
+            }
+        }
+
+        ast.trivia = std::mem::take(&mut self.trivia);
+        (ast, std::mem::take(&mut self.diagnostics))
+    }
+
+    fn parse_stmt_or_recover(&mut self) -> Stmt {
+        let (start, depth) = (self.start(), self.depth);
+
+        match self.parse_stmt() {
+            Ok(stmt) => stmt,
+            Err(error) => Stmt::new(StmtKind::Error, self.recover(error, start, depth)),
+        }
+    }
+
+    // record `error` and skip the rest of the statement that started at
+    // `start` with `depth` open delimiters, up to the next `;` or the `}`
+    // closing its block. Returns the span of the skipped statement.
+    fn recover(&mut self, error: Diagnostic, start: usize, depth: usize) -> Span {
+        self.diagnostics.push(error);
+
+        while let Some(token) = &self.curr {
+            if self.depth <= depth && matches!(token.kind, Semicolon | CloseBrace) {
+                break;
+            }
+            self.bump();
+        }
+
+        Span::new(start, self.prev_end.max(start))
+    }
+
+    // a `}` at the top level, which has no block to close
+    fn skip_unmatched_brace(&mut self) {
+        let error = self.error("unexpected CLOSE_BRACE".to_owned());
+        self.diagnostics.push(error);
+        self.bump();
+    }
+
+    // consume the current token and return it
+    fn bump(&mut self) -> Option<Token<'a>> {
+        let curr = std::mem::replace(&mut self.curr, self.peek.take());
+        self.peek = match self.lookahead.pop_front() {
+            Some(token) => Some(token),
+            None => self.next_token(),
+        };
+
+        if let Some(token) = &curr {
+            self.prev_end = token.span.end;
+
+            match token.kind {
+                OpenParen | OpenBracket | OpenBrace => self.depth += 1,
+                CloseParen | CloseBracket | CloseBrace => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        curr
+    }
+
+    // the next token from the lexer that isn't a comment
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        loop {
+            let token = self.lexer.next()?;
+
+            match token.kind {
+                Comment => self.trivia.push(Trivia {
+                    span: token.span,
+                    doc: false,
+                }),
+                DocComment => {
+                    self.trivia.push(Trivia {
+                        span: token.span,
+                        doc: true,
+                    });
+
+                    let line = token.literal;
+                    self.pending_docs
+                        .push(line.strip_prefix(' ').unwrap_or(line));
+                }
+                _ => {
+                    if !self.pending_docs.is_empty() {
+                        let docs = std::mem::take(&mut self.pending_docs);
+                        self.docs.insert(token.span.start, docs);
+                    }
+
+                    return Some(token);
+                }
+            }
+        }
+    }
+
+    // the doc comments right above the token at `start`
+    fn take_docs(&mut self, start: usize) -> Vec<&'a str> {
+        self.docs.remove(&start).unwrap_or_default()
+    }
+
+    // where the next node starts
+    fn start(&self) -> usize {
+        match &self.curr {
+            Some(token) => token.span.start,
+            None => self.prev_end,
+        }
+    }
+
+    // the span from `start` to the end of the last consumed token
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.prev_end)
+    }
+
+    // a syntax error pointing at the current token
+    fn error(&self, message: String) -> Diagnostic {
+        match &self.curr {
+            Some(token) if token.kind == UntermDoubleQuote => {
+                Diagnostic::error("syntax-error", message, token.span)
+                    .with_label("found an unterminated string")
+            }
+            Some(token) if token.kind == UntermBlockComment => {
+                Diagnostic::error("syntax-error", message, token.span)
+                    .with_label("found an unterminated comment")
+                    .with_note("block comments end with `*/`")
+            }
+            Some(token) => Diagnostic::error("syntax-error", message, token.span)
+                .with_label(format!("found `{}`", token.literal)),
+            None => {
+                let span = Span::new(self.prev_end, self.prev_end);
+                Diagnostic::error("syntax-error", message, span).with_label("found end of input")
+            }
+        }
+    }
+
+    fn expected(&self, what: &str) -> Diagnostic {
+        self.error(format!("expected {}", what))
+    }
+
+    // a number literal that the lexer could not read
+    fn malformed_number(&self) -> Diagnostic {
+        let kind = self.curr.as_ref().map(|token| token.kind.clone());
+
+        match kind {
+            Some(MissingDigits) => self.error("number literal has no digits".to_owned()),
+            Some(InvalidDigit) => {
+                self.error("invalid digit for the base of the number literal".to_owned())
+            }
+            _ => self
+                .error("invalid suffix on number literal".to_owned())
+                .with_note("the suffixes are i8, i16, i32, i64, u8, u16, u32, u64, f32 and f64"),
+        }
+    }
+
+    // the `n`th token from `curr`, without consuming anything
+    fn nth(&mut self, n: usize) -> Option<&Token<'a>> {
+        match n {
+            0 => self.curr.as_ref(),
+            1 => self.peek.as_ref(),
+            n => {
+                while self.lookahead.len() < n - 1 {
+                    match self.next_token() {
+                        Some(token) => self.lookahead.push_back(token),
+                        None => break,
+                    }
+                }
+                self.lookahead.get(n - 2)
+            }
+        }
+    }
+
+    fn nth_is(&mut self, n: usize, kind: TokenKind) -> bool {
+        matches!(self.nth(n), Some(token) if token.kind == kind)
+    }
+
+    fn curr_is(&self, kind: TokenKind) -> bool {
+        matches!(&self.curr, Some(token) if token.kind == kind)
+    }
+
+    fn peek_is(&self, kind: TokenKind) -> bool {
+        matches!(&self.peek, Some(token) if token.kind == kind)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token<'a>> {
+        if self.curr_is(kind.clone()) {
+            Ok(self.bump().unwrap())
+        } else {
+            Err(self.expected(&kind.name()))
+        }
+    }
+
+    // statements end with a semicolon, which may be left out before the end of
+    // the input or of the enclosing block
+    fn expect_terminator(&mut self) -> Result<()> {
+        match &self.curr {
+            Some(token) if token.kind == Semicolon => {
+                self.bump();
+                Ok(())
+            }
+            Some(token) if token.kind == CloseBrace => Ok(()),
+            None => Ok(()),
+            _ => Err(self.expected(&Semicolon.name())),
+        }
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt> {
+        let kind = self.curr.as_ref().map(|token| token.kind.clone());
+
+        match kind {
+            Some(Identifier) => self.parse_ident(),
+            Some(Return) => self.parse_return(),
+            Some(Break | Continue) => self.parse_break(),
+            Some(TokenKind::Trait) => self.parse_trait(),
+            Some(TokenKind::Impl) => self.parse_impl(),
+            _ => self.parse_expr_stmt(),
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<Stmt> {
+        let kind = self.peek.as_ref().map(|token| token.kind.clone());
+        let start = self.start();
+
+        match kind {
+            Some(Assign) => {
+                let docs = self.take_docs(start);
+                let ident = self.bump().unwrap().literal.to_owned();
+                self.bump();
+                let expr = self.parse_expr()?;
+                let span = self.span_from(start);
+                self.expect_terminator()?;
+
+                let stmt = Stmt::new(StmtKind::Binding(ident, expr), span);
+                Ok(stmt.with_docs(owned(docs)))
+            }
+            Some(
+                kind @ (PlusAssign | MinusAssign | MultiplyAssign | DivideAssign | ModuloAssign
+                | PowerAssign | BitAndAssign | BitOrAssign | BitXorAssign | LeftShiftAssign
+                | RightShiftAssign),
+            ) => {
+                let ident = self.bump().unwrap().literal.to_owned();
+                self.bump();
+                let expr = self.parse_expr()?;
+                let span = self.span_from(start);
+                self.expect_terminator()?;
+
+                let kind = StmtKind::Assign(ident, assign_operator(&kind), expr);
+                Ok(Stmt::new(kind, span))
+            }
+            Some(Pipe) => self.parse_type_signature(),
+            Some(DoubleColon) => self.parse_type_decl(),
+            Some(LessThan) if self.generics_follow(DoubleColon) => self.parse_type_decl(),
+            Some(LessThan) if self.generics_follow(Pipe) => self.parse_type_signature(),
+            _ => self.parse_expr_stmt(),
+        }
+    }
+
+    // `Name<...> ::` starts a type declaration and `name<...> |` a signature,
+    // where the `<` would otherwise be a comparison
+    fn generics_follow(&mut self, kind: TokenKind) -> bool {
+        let (mut n, mut depth) = (1, 0);
+
+        loop {
+            match self.nth(n).map(|token| &token.kind) {
+                Some(LessThan) => depth += 1,
+                Some(GreaterThan) => depth -= 1,
+                Some(RightShift) => depth -= 2,
+                Some(Identifier | Colon | Comma | Plus | Dot) => {}
+                _ => return false,
+            }
+
+            if depth <= 0 {
+                return depth == 0 && self.nth_is(n + 1, kind);
+            }
+            n += 1;
+        }
+    }
+
+    // `Point<T: Add>`, where the arguments of the type of an impl declare its
+    // type parameters
+    fn bounded_args_follow(&mut self) -> bool {
+        if !self.curr_is(Identifier) || !self.peek_is(LessThan) {
+            return false;
+        }
+
+        for n in 2.. {
+            match self.nth(n).map(|token| &token.kind) {
+                Some(Colon) => return true,
+                Some(Identifier | Comma) => {}
+                _ => break,
+            }
+        }
+
+        false
+    }
+
+    // `Point<T> :: { x: T, y: T }` or `Option :: T -> | Some { T } | None`,
+    // then `derive Format, Eq`
+    fn parse_type_decl(&mut self) -> Result<Stmt> {
+        let start = self.start();
+        let docs = self.take_docs(start);
+        let ident = self.bump().unwrap().literal.to_owned();
+
+        let mut generics = Vec::new();
+
+        if self.curr_is(LessThan) {
+            self.bump();
+            generics = self.parse_generics()?;
+            self.expect_closing_angle()?;
+        }
+
+        self.expect(DoubleColon)?;
+
+        if generics.is_empty() && self.curr_is(Identifier) {
+            generics = self.parse_generics()?;
+            self.expect(Arrow)?;
+        }
+
+        let kind = match &self.curr {
+            Some(token) if matches!(token.kind, OpenBrace | OpenParen) => {
+                TypeDeclKind::Struct(self.parse_field_decls()?)
+            }
+            Some(token) if token.kind == Pipe => TypeDeclKind::Enum(self.parse_variants()?),
+            _ => return Err(self.expected("`{`, `(` or `|`")),
+        };
+
+        let mut derives = Vec::new();
+
+        if self.curr_is(Derive) {
+            self.bump();
+
+            loop {
+                derives.push(self.expect(Identifier)?.literal.to_owned());
+
+                if !self.curr_is(Comma) {
+                    break;
+                }
+                self.bump();
+            }
+        }
+
+        let span = self.span_from(start);
+        self.expect_terminator()?;
+
+        let decl = TypeDecl {
+            ident,
+            generics,
+            kind,
+            derives,
+        };
+        let stmt = Stmt::new(StmtKind::TypeDecl(Box::new(decl)), span);
+        Ok(stmt.with_docs(owned(docs)))
+    }
+
+    // `trait ToString = { to_string | Self -> String; }`
+    fn parse_trait(&mut self) -> Result<Stmt> {
+        let start = self.start();
+        let docs = self.take_docs(start);
+        self.bump();
+        let ident = self.expect(Identifier)?.literal.to_owned();
+
+        let mut generics = Vec::new();
+
+        if self.curr_is(LessThan) {
             self.bump();
+            generics = self.parse_generics()?;
+            self.expect_closing_angle()?;
         }
 
-        Ok(ast)
+        self.expect(Assign)?;
+        let items = self.parse_items(true)?;
+        let span = self.span_from(start);
+        self.expect_terminator()?;
+
+        let declared = Trait {
+            ident,
+            generics,
+            items,
+        };
+        let stmt = Stmt::new(StmtKind::Trait(Box::new(declared)), span);
+        Ok(stmt.with_docs(owned(docs)))
     }
 
-    fn bump(&mut self) {
-        self.curr = self.peek.take();
-        self.peek = self.lexer.next();
+    // `impl<T> Format for Node<T> where T: Format { ... }` or `impl Point { ... }`
+    fn parse_impl(&mut self) -> Result<Stmt> {
+        let start = self.start();
+        let docs = self.take_docs(start);
+        self.bump();
+
+        let mut generics = Vec::new();
+
+        if self.curr_is(LessThan) {
+            self.bump();
+            generics = self.parse_generics()?;
+            self.expect_closing_angle()?;
+        }
+
+        let mut ty = self.parse_impl_type(&mut generics)?;
+        let mut trait_ = None;
+
+        if self.curr_is(TokenKind::For) {
+            self.bump();
+            trait_ = Some(ty);
+            ty = self.parse_impl_type(&mut generics)?;
+        }
+
+        let mut predicates = Vec::new();
+
+        if self.curr_is(Where) {
+            self.bump();
+            predicates = self.parse_generics()?;
+        }
+
+        let items = self.parse_items(false)?;
+        let span = self.span_from(start);
+        self.expect_terminator()?;
+
+        let implemented = Impl {
+            generics,
+            trait_,
+            ty,
+            predicates,
+            items,
+        };
+        let stmt = Stmt::new(StmtKind::Impl(Box::new(implemented)), span);
+        Ok(stmt.with_docs(owned(docs)))
     }
 
-    fn parse_stmt(&mut self, start: Token) -> Result<Stmt> {
-        match start.kind {
-            Ident => self.parse_ident(start.literal),
-            Return => self.parse_return(),
-            For => self.parse_for(),
-            _ => todo!(),
+    // the type of an impl, which declares the type parameters of the impl
+    // when they have no other declaration: `impl Point<T: Add>`
+    fn parse_impl_type(&mut self, generics: &mut Vec<Generic>) -> Result<Type> {
+        if !generics.is_empty() || !self.bounded_args_follow() {
+            return self.parse_type_primary();
         }
+
+        let ident = self.bump().unwrap().literal.to_owned();
+        self.bump();
+        *generics = self.parse_generics()?;
+        self.expect_closing_angle()?;
+
+        let args = generics
+            .iter()
+            .map(|generic| Type::NewType(generic.ident.clone(), Vec::new()))
+            .collect();
+        Ok(Type::NewType(ident, args))
     }
 
-    fn parse_ident(&mut self, ident: String) -> Result<Stmt> {
-        match &self.peek {
-            None => Ok(Stmt::Return(Expr::Ident(ident))),
-            Some(Token { kind, .. }) => match kind {
-                Assign => {
+    // the methods of a trait or impl, between braces: bindings, and signatures
+    // that only the methods a trait requires can leave without one
+    fn parse_items(&mut self, required: bool) -> Result<Vec<Stmt>> {
+        self.expect(OpenBrace)?;
+        let mut items = Vec::new();
+
+        loop {
+            match (&self.curr, &self.peek) {
+                (Some(token), _) if token.kind == CloseBrace => break,
+                (Some(token), _) if token.kind == Semicolon => {
                     self.bump();
-                    let expr = self.parse_expr()?;
-                    match &self.peek {
-                        Some(token) if token.kind == Semicolon => {
-                            self.bump();
-                            Ok(Stmt::Binding(ident, expr))
+                }
+                (Some(token), Some(peek))
+                    if token.kind == Identifier && matches!(peek.kind, Assign | Pipe) =>
+                {
+                    let item = self.parse_ident()?;
+
+                    if let StmtKind::Signature(signature) = &item.kind {
+                        if signature.binding.is_none() && !required {
+                            let message = format!("method `{}` has no body", signature.ident);
+                            return Err(Diagnostic::error("syntax-error", message, item.span));
                         }
-                        _ => Err(("Expected Semicolon", self.curr.take())),
                     }
+
+                    items.push(item);
                 }
-                Pipe => self.parse_type_signature(),
-                Semicolon => {
+                _ => return Err(self.expected("method")),
+            }
+        }
+
+        self.bump();
+        Ok(items)
+    }
+
+    // type parameters and their bounds: `T: Format + Clone, U`
+    fn parse_generics(&mut self) -> Result<Vec<Generic>> {
+        let mut generics = Vec::new();
+
+        loop {
+            let ident = self.expect(Identifier)?.literal.to_owned();
+            let mut bounds = Vec::new();
+
+            if self.curr_is(Colon) {
+                self.bump();
+
+                loop {
+                    bounds.push(self.parse_type_primary()?);
+
+                    if !self.curr_is(Plus) {
+                        break;
+                    }
                     self.bump();
-                    Ok(Stmt::Expr(Expr::Ident(ident)))
                 }
-                _ => {
-                    Err(("Expected binding, expression or type signature", self.peek.take()))
+            }
+
+            generics.push(Generic { ident, bounds });
+
+            if !self.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        Ok(generics)
+    }
+
+    // `| Some { T } | None`
+    fn parse_variants(&mut self) -> Result<Vec<Variant>> {
+        let mut variants = Vec::new();
+
+        while self.curr_is(Pipe) {
+            self.bump();
+
+            let ident = match &self.curr {
+                Some(token) if is_constructor(token) => self.bump().unwrap().literal.to_owned(),
+                _ => return Err(self.expected("variant")),
+            };
+
+            let fields = match self.curr_is(OpenBrace) || self.curr_is(OpenParen) {
+                true => self.parse_field_decls()?,
+                false => Vec::new(),
+            };
+
+            variants.push(Variant { ident, fields });
+        }
+
+        Ok(variants)
+    }
+
+    // `{ x: T, y: T }` or `(T, T)`, where fields can be named in either
+    fn parse_field_decls(&mut self) -> Result<Vec<FieldDecl>> {
+        let close = match self.curr_is(OpenParen) {
+            true => CloseParen,
+            false => CloseBrace,
+        };
+
+        self.bump();
+        let mut fields = Vec::new();
+
+        while !self.curr_is(close.clone()) {
+            let name = match self.curr_is(Identifier) && self.peek_is(Colon) {
+                true => {
+                    let name = self.bump().unwrap().literal.to_owned();
+                    self.bump();
+                    Some(name)
                 }
-            },
+                false => None,
+            };
+
+            let ty = self.parse_type_primary()?;
+            fields.push(FieldDecl { name, ty });
+
+            if !self.curr_is(Comma) {
+                break;
+            }
+            self.bump();
         }
+
+        self.expect(close)?;
+        Ok(fields)
     }
 
     fn parse_type_signature(&mut self) -> Result<Stmt> {
-        todo!()
+        let start = self.start();
+        let docs = self.take_docs(start);
+        let ident = self.bump().unwrap().literal.to_owned();
+        let mut generics = Vec::new();
+
+        // `id<T> | T -> T`
+        if self.curr_is(LessThan) {
+            self.bump();
+            generics = self.parse_generics()?;
+            self.expect_closing_angle()?;
+        }
+
+        self.expect(Pipe)?;
+
+        let (bounded, ty) = self.parse_type_scheme()?;
+        generics.extend(bounded);
+        let span = self.span_from(start);
+        self.expect_terminator()?;
+
+        // attach the signature to the binding that follows it
+        let binding = match (&self.curr, &self.peek) {
+            (Some(curr), Some(peek))
+                if curr.kind == Identifier && curr.literal == ident && peek.kind == Assign =>
+            {
+                Some(self.parse_ident()?)
+            }
+            _ => None,
+        };
+
+        let signature = Signature {
+            ident,
+            generics,
+            ty,
+            binding,
+        };
+        let stmt = Stmt::new(StmtKind::Signature(Box::new(signature)), span);
+        Ok(stmt.with_docs(owned(docs)))
     }
 
-    fn parse_return(&mut self) -> Result<Stmt> {
-        let expr = self.parse_expr()?;
+    // a type, optionally preceded by its bounded type parameters:
+    // `P1: Parser<R1>, R1 -> P1 -> Parser<R1>`
+    fn parse_type_scheme(&mut self) -> Result<(Vec<Generic>, Type)> {
+        let mut params = Vec::new();
+        let mut bounded = false;
 
-        match &self.peek {
-            Some(token) if token.kind == Semicolon => {
+        loop {
+            let ty = self.parse_type_primary()?;
+            let mut bounds = Vec::new();
+
+            if self.curr_is(Colon) {
                 self.bump();
-                Ok(Stmt::Return(expr))
+                bounded = true;
+
+                loop {
+                    bounds.push(self.parse_type_primary()?);
+
+                    if !self.curr_is(Plus) {
+                        break;
+                    }
+                    self.bump();
+                }
+            }
+
+            params.push((ty, bounds));
+
+            if !self.curr_is(Comma) {
+                break;
             }
-            _ => Err(("Expected Semicolon", self.curr.take())),
+            self.bump();
         }
-    }
 
-    fn parse_for(&mut self) -> Result<Stmt> {
-        todo!()
-    }
+        if !bounded {
+            let params = params.into_iter().map(|(ty, _)| ty).collect();
+            return Ok((Vec::new(), self.parse_type_arrow(params)?));
+        }
 
-    fn parse_expr(&mut self) -> Result<Expr> {
-        // let expr = match &self.peek {
-        //     Some(token) => match token.kind {
-        //         Ident => {
-        //             self.bump();
-        //             Ok(Expr::Ident(self.curr.take().unwrap().literal))
-        //         }
-        //         _ => todo!(),
-        //     }
-        //     None => todo!(),
-        // };
+        let mut generics = Vec::new();
+
+        for (ty, bounds) in params {
+            match ty {
+                Type::NewType(ident, args) if args.is_empty() => {
+                    generics.push(Generic { ident, bounds })
+                }
+                _ => return Err(self.expected("type parameter")),
+            }
+        }
+
+        self.expect(Arrow)?;
+        Ok((generics, self.parse_type()?))
+    }
 
-        // return expr;
+    // `A, B -> C` is a function type, `A, B` on its own is a tuple
+    fn parse_type(&mut self) -> Result<Type> {
+        let mut types = vec![self.parse_type_primary()?];
 
-        match self.parse_unary() {
-            Ok(mut expr) => Ok(expr),
-            Err(_) => Ok(Expr::Unit),
+        while self.curr_is(Comma) {
+            self.bump();
+            types.push(self.parse_type_primary()?);
         }
+
+        self.parse_type_arrow(types)
     }
 
-    fn parse_unary(&mut self) -> Result<Expr> {
-        if let Some(token) = &self.peek {
+    fn parse_type_arrow(&mut self, mut types: Vec<Type>) -> Result<Type> {
+        if self.curr_is(Arrow) {
             self.bump();
-            let literal = self.curr.take().unwrap().literal;
-            match token.kind {
-                Str => Ok(Expr::Str(literal)),
-                Ident => Ok(Expr::Ident(literal)),
-                _ => todo!()
+            let ret = self.parse_type()?;
+
+            // `() -> T` takes no arguments
+            if types == [Type::Unit] {
+                types.clear();
             }
-        } else {
-            todo!()
+
+            return Ok(Type::Function(types, Box::new(ret)));
+        }
+
+        match types.len() {
+            1 => Ok(types.pop().unwrap()),
+            _ => Ok(Type::Tuple(types)),
         }
     }
+
+    fn parse_type_primary(&mut self) -> Result<Type> {
+        // `Self` is a keyword, but names a type like any other
+        if !self.curr_is(OpenParen) && !self.curr_is(Identifier) && !self.curr_is(SelfType) {
+            return Err(self.expected("type"));
+        }
+
+        let token = self.bump().unwrap();
+
+        if token.kind == OpenParen {
+            if self.curr_is(CloseParen) {
+                self.bump();
+                return Ok(Type::Unit);
+            }
+
+            let ty = self.parse_type()?;
+            self.expect(CloseParen)?;
+            return Ok(ty);
+        }
+
+        let mut ident = token.literal.to_owned();
+
+        // paths into modules: `format.Formatter`
+        while self.curr_is(Dot) && self.peek_is(Identifier) {
+            self.bump();
+            ident.push('.');
+            ident.push_str(self.bump().unwrap().literal);
+        }
+
+        let mut args = Vec::new();
+
+        if self.curr_is(LessThan) {
+            self.bump();
+
+            loop {
+                args.push(self.parse_type_primary()?);
+
+                if !self.curr_is(Comma) {
+                    break;
+                }
+                self.bump();
+            }
+
+            self.expect_closing_angle()?;
+        }
+
+        let ty = match (ident.as_str(), args.is_empty()) {
+            ("Never", true) => Type::Never,
+            ("Bool", true) => Type::Bool,
+            ("Int", true) => Type::Int,
+            ("Float", true) => Type::Float,
+            ("Char", true) => Type::Char,
+            ("String", true) => Type::String,
+            _ => Type::NewType(ident, args),
+        };
+
+        Ok(ty)
+    }
+
+    // `Option<Node<T>>` ends with a single `>>` token which closes two lists
+    fn expect_closing_angle(&mut self) -> Result<()> {
+        match &mut self.curr {
+            Some(token) if token.kind == GreaterThan => {
+                self.bump();
+                Ok(())
+            }
+            Some(token) if token.kind == RightShift => {
+                token.kind = GreaterThan;
+                token.literal = &token.literal[1..];
+                token.span.start += 1;
+                token.column += 1;
+                self.prev_end = token.span.start;
+                Ok(())
+            }
+            _ => Err(self.expected(&GreaterThan.name())),
+        }
+    }
+
+    fn parse_return(&mut self) -> Result<Stmt> {
+        let start = self.start();
+        self.bump();
+        let expr = self.parse_expr()?;
+        let span = self.span_from(start);
+        self.expect_terminator()?;
+        Ok(Stmt::new(StmtKind::Return(expr), span))
+    }
+
+    // `break`, `break 'outer`, `break value` and `continue 'outer`
+    fn parse_break(&mut self) -> Result<Stmt> {
+        let start = self.start();
+        let keyword = self.bump().unwrap();
+        let label = self.parse_label_use();
+
+        let value = match &self.curr {
+            Some(token)
+                if keyword.kind == Break && !matches!(token.kind, Semicolon | CloseBrace) =>
+            {
+                Some(self.parse_expr()?)
+            }
+            _ => None,
+        };
+
+        self.check_loop_exit(
+            &keyword,
+            label.as_ref(),
+            value.as_ref().map(|value| value.span),
+        )?;
+        let span = self.span_from(start);
+        self.expect_terminator()?;
+
+        let label = label.map(|label| label.literal.to_owned());
+        let kind = match keyword.kind {
+            Break => StmtKind::Break(label, value),
+            _ => StmtKind::Continue(label),
+        };
+        Ok(Stmt::new(kind, span))
+    }
+
+    // the label after `break` or `continue`
+    fn parse_label_use(&mut self) -> Option<Token<'a>> {
+        match self.curr_is(TokenKind::Label) {
+            true => self.bump(),
+            false => None,
+        }
+    }
+
+    // `break` and `continue` need a loop around them with their label, if they
+    // have one, and only `loop` can `break` with a value
+    fn check_loop_exit(
+        &self,
+        keyword: &Token<'a>,
+        label: Option<&Token<'a>>,
+        value: Option<Span>,
+    ) -> Result<()> {
+        let scope = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|scope| scope.label == Some(label.literal)),
+            None => self.loops.last(),
+        };
+
+        match (scope, label) {
+            (None, Some(label)) => {
+                let message = format!("use of undeclared label `'{}`", label.literal);
+                Err(Diagnostic::error("syntax-error", message, label.span)
+                    .with_label("no loop around this has the label"))
+            }
+            (None, None) => {
+                let message = format!("`{}` outside of a loop", keyword.literal);
+                Err(Diagnostic::error("syntax-error", message, keyword.span)
+                    .with_label(format!("cannot `{}` outside of a loop", keyword.literal)))
+            }
+            (Some(scope), _) => match value {
+                Some(value) if !scope.value => Err(Diagnostic::error(
+                    "syntax-error",
+                    "`break` with a value outside of `loop`",
+                    keyword.span.to(value),
+                )
+                .with_label("cannot break with a value")
+                .with_note("only `loop` can break with a value, `while` and `for` are `()`")),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    // the label of a loop, followed by the keyword that starts it
+    fn parse_label(&mut self) -> Result<(Option<&'a str>, TokenKind)> {
+        let label = match self.curr_is(TokenKind::Label) {
+            true => {
+                let label = self.bump().unwrap().literal;
+                self.expect(Colon)?;
+                Some(label)
+            }
+            false => None,
+        };
+
+        match &self.curr {
+            Some(token)
+                if matches!(
+                    token.kind,
+                    TokenKind::Loop | TokenKind::While | TokenKind::For
+                ) =>
+            {
+                Ok((label, self.bump().unwrap().kind))
+            }
+            _ => Err(self.expected("`loop`, `while` or `for`")),
+        }
+    }
+
+    // `'outer: loop { .. }`, `while condition { .. }`, `while pattern = value { .. }`
+    // and `for pattern in iterable { .. }`
+    fn parse_loop(&mut self) -> Result<Expr> {
+        let start = self.start();
+        let (label, kind) = self.parse_label()?;
+
+        let kind = match kind {
+            TokenKind::Loop => ExprKind::Loop(Box::new(Loop {
+                label: label.map(str::to_owned),
+                body: self.parse_loop_body(label, true)?,
+            })),
+            TokenKind::While => {
+                let pattern = match self.pattern_follows() {
+                    true => {
+                        let pattern = self.parse_pattern()?;
+                        self.expect(Assign)?;
+                        Some(pattern)
+                    }
+                    false => None,
+                };
+
+                ExprKind::While(Box::new(While {
+                    label: label.map(str::to_owned),
+                    pattern,
+                    condition: self.parse_head()?,
+                    body: self.parse_loop_body(label, false)?,
+                }))
+            }
+            _ => {
+                let pattern = self.parse_pattern()?;
+                self.expect(In)?;
+
+                ExprKind::For(Box::new(For {
+                    label: label.map(str::to_owned),
+                    pattern,
+                    iterable: self.parse_head()?,
+                    body: self.parse_loop_body(label, false)?,
+                }))
+            }
+        };
+
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // `while` is followed by a pattern if there is an `=` before its body.
+    // Conditions have no `=` outside of delimiters, and a pattern's braces are
+    // followed by the `=`.
+    fn pattern_follows(&mut self) -> bool {
+        let (mut n, mut depth) = (0, 0);
+
+        loop {
+            match self.nth(n).map(|token| &token.kind) {
+                None => return false,
+                Some(Semicolon) if depth == 0 => return false,
+                Some(Assign) if depth == 0 => return true,
+                Some(OpenParen | OpenBracket | OpenBrace) => depth += 1,
+                Some(CloseParen | CloseBracket) => depth -= 1,
+                Some(CloseBrace) if depth == 1 => return self.nth_is(n + 1, Assign),
+                Some(CloseBrace) if depth == 0 => return false,
+                Some(CloseBrace) => depth -= 1,
+                _ => {}
+            }
+
+            n += 1;
+        }
+    }
+
+    // the expression after `if`, `while` or `in`, where a brace opens the body
+    fn parse_head(&mut self) -> Result<Expr> {
+        self.with_brace_calls(false, Self::parse_expr)
+    }
+
+    fn parse_loop_body(&mut self, label: Option<&'a str>, value: bool) -> Result<Vec<Stmt>> {
+        self.loops.push(LoopScope { label, value });
+        let body = self.parse_body();
+        self.loops.pop();
+        body
+    }
+
+    // `if condition { .. } else if condition { .. } else { .. }`, where an
+    // `else if` is an alternative holding just the next conditional
+    fn parse_if(&mut self) -> Result<Expr> {
+        let start = self.start();
+        self.bump();
+
+        let condition = self.parse_head()?;
+        let consequent = self.parse_body()?;
+
+        let alternative = match self.curr_is(Else) {
+            true => {
+                self.bump();
+
+                match self.curr_is(If) {
+                    true => {
+                        let conditional = self.parse_if()?;
+                        let span = conditional.span;
+                        Some(vec![Stmt::new(StmtKind::Expr(conditional), span)])
+                    }
+                    false => Some(self.parse_body()?),
+                }
+            }
+            false => None,
+        };
+
+        let conditional = Conditional {
+            condition,
+            consequent,
+            alternative,
+        };
+        let kind = ExprKind::Conditional(Box::new(conditional));
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // `match value { pattern => body, pattern if guard => body }`
+    fn parse_match(&mut self) -> Result<Expr> {
+        let start = self.start();
+        self.bump();
+
+        let matched = self.parse_head()?;
+        self.expect(OpenBrace)?;
+        let arms = self.with_brace_calls(true, Self::parse_arms)?;
+        self.expect(CloseBrace)?;
+
+        let kind = ExprKind::Match(Box::new(Match { matched, arms }));
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // arms are separated by commas, which may be left out after a block
+    fn parse_arms(&mut self) -> Result<Vec<Arm>> {
+        let mut arms = Vec::new();
+
+        while !self.curr_is(CloseBrace) {
+            let start = self.start();
+            let pattern = self.parse_pattern()?;
+
+            let guard = match self.curr_is(If) {
+                true => {
+                    self.bump();
+                    Some(self.parse_expr()?)
+                }
+                false => None,
+            };
+
+            self.expect(ArrowRight)?;
+            let body = self.parse_expr()?;
+            let block = is_block_like(&body.kind);
+
+            arms.push(Arm {
+                pattern,
+                guard,
+                body,
+                span: self.span_from(start),
+            });
+
+            match self.curr_is(Comma) {
+                true => {
+                    self.bump();
+                }
+                false if block => {}
+                false => break,
+            }
+        }
+
+        Ok(arms)
+    }
+
+    // `_`, a literal or a range of them, a name to bind, a tuple or a
+    // constructor of patterns, any of those bound with `@`, or joined by `|`
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let start = self.start();
+        let first = self.parse_pattern_at()?;
+
+        if !self.curr_is(Pipe) {
+            return Ok(first);
+        }
+
+        let mut patterns = vec![first];
+
+        while self.curr_is(Pipe) {
+            self.bump();
+            patterns.push(self.parse_pattern_at()?);
+        }
+
+        Ok(Pattern {
+            kind: PatternKind::Or(patterns),
+            span: self.span_from(start),
+        })
+    }
+
+    // `n @ pattern`, or a pattern on its own
+    fn parse_pattern_at(&mut self) -> Result<Pattern> {
+        if !self.curr_is(Identifier) || !self.peek_is(At) {
+            return self.parse_pattern_primary();
+        }
+
+        let start = self.start();
+        let ident = self.bump().unwrap().literal.to_owned();
+        self.bump();
+        let pattern = self.parse_pattern_primary()?;
+
+        Ok(Pattern {
+            kind: PatternKind::At(ident, Box::new(pattern)),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_pattern_primary(&mut self) -> Result<Pattern> {
+        let start = self.start();
+
+        let kind = match &self.curr {
+            Some(token) if is_wildcard(token) => {
+                self.bump();
+                PatternKind::Wildcard
+            }
+            Some(token) if is_constructor(token) => {
+                let ident = self.bump().unwrap().literal.to_owned();
+                PatternKind::Constructor(ident, self.parse_field_patterns()?)
+            }
+            Some(token) if token.kind == Identifier => {
+                PatternKind::Binding(self.bump().unwrap().literal.to_owned())
+            }
+            Some(token) if token.kind == OpenParen => {
+                self.bump();
+                let mut patterns = Vec::new();
+                let mut tuple = false;
+
+                while !self.curr_is(CloseParen) {
+                    patterns.push(self.parse_pattern()?);
+
+                    if !self.curr_is(Comma) {
+                        break;
+                    }
+                    self.bump();
+                    tuple = true;
+                }
+
+                self.expect(CloseParen)?;
+
+                match patterns.len() {
+                    0 => PatternKind::Literal(Expr::new(ExprKind::Unit, self.span_from(start))),
+                    1 if !tuple => return Ok(patterns.pop().unwrap()),
+                    _ => PatternKind::Tuple(patterns),
+                }
+            }
+            _ => {
+                let from = self.parse_pattern_literal()?;
+
+                let inclusive = match &self.curr {
+                    Some(token) if token.kind == DoubleDot => false,
+                    Some(token) if token.kind == DoubleDotEqual => true,
+                    _ => {
+                        return Ok(Pattern {
+                            kind: PatternKind::Literal(from),
+                            span: self.span_from(start),
+                        })
+                    }
+                };
+
+                self.bump();
+                let to = self.parse_pattern_literal()?;
+
+                let range = Range {
+                    from,
+                    to,
+                    inclusive,
+                };
+                PatternKind::Range(Box::new(range))
+            }
+        };
+
+        Ok(Pattern {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    // the fields of a constructor pattern, in braces or parentheses, if any
+    fn parse_field_patterns(&mut self) -> Result<Vec<FieldPattern>> {
+        let close = match &self.curr {
+            Some(token) if token.kind == OpenBrace => CloseBrace,
+            Some(token) if token.kind == OpenParen => CloseParen,
+            _ => return Ok(Vec::new()),
+        };
+
+        self.bump();
+        let mut fields = Vec::new();
+
+        while !self.curr_is(close.clone()) {
+            // `name = pattern` matches a field by name
+            let name = match self.curr_is(Identifier) && self.peek_is(Assign) {
+                true => {
+                    let name = self.bump().unwrap().literal.to_owned();
+                    self.bump();
+                    Some(name)
+                }
+                false => None,
+            };
+
+            let pattern = self.parse_pattern()?;
+            fields.push(FieldPattern { name, pattern });
+
+            if !self.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        self.expect(close)?;
+        Ok(fields)
+    }
+
+    // a literal in a pattern, where numbers may be negated
+    fn parse_pattern_literal(&mut self) -> Result<Expr> {
+        let negated = self.curr_is(Minus) && (self.peek_is(Int) || self.peek_is(Float));
+
+        match &self.curr {
+            _ if negated => self.parse_unary(),
+            Some(token) if is_literal(&token.kind) => self.parse_primary(),
+            _ => Err(self.expected("pattern")),
+        }
+    }
+
+    fn parse_expr_stmt(&mut self) -> Result<Stmt> {
+        let expr = self.parse_expr()?;
+        let span = expr.span;
+        self.expect_terminator()?;
+        Ok(Stmt::new(StmtKind::Expr(expr), span))
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr> {
+        if self.at_function() {
+            return self.parse_function();
+        }
+
+        self.parse_pipeline()
+    }
+
+    // `a : f { b } : g` is sugar for `g { f { a, b } }`
+    fn parse_pipeline(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_range()?;
+
+        while self.curr_is(Colon) {
+            self.bump();
+
+            // `a : f?` tries the call rather than `f`
+            let callee = self.parse_postfix_chain(false)?;
+            let span = expr.span.to(callee.span);
+
+            expr = match callee.kind {
+                ExprKind::Call(mut call) => {
+                    call.args.insert(0, expr);
+                    Expr::new(ExprKind::Call(call), span)
+                }
+                kind => {
+                    let callee = Expr::new(kind, callee.span);
+                    let call = Call {
+                        callee,
+                        args: vec![expr],
+                    };
+                    Expr::new(ExprKind::Call(Box::new(call)), span)
+                }
+            };
+
+            while self.curr_is(Question) {
+                expr = self.parse_try(expr)?;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    // `value?`, or `value ? default` when an operand follows that can't be
+    // the right side of a binary operator: `o? - 1` is `(o?) - 1`
+    fn parse_try(&mut self, expr: Expr) -> Result<Expr> {
+        let question = self.bump().unwrap();
+        let mut span = expr.span.to(question.span);
+
+        let default = match self.default_follows(expr.span, question.span) {
+            true => {
+                let default = self.parse_unary()?;
+                span = span.to(default.span);
+                Some(default)
+            }
+            false => None,
+        };
+
+        let try_ = Try { expr, default };
+        Ok(Expr::new(ExprKind::Try(Box::new(try_)), span))
+    }
+
+    // whether a `?` has a default, which it only does when it is set apart from
+    // the value, so `o ? -1` falls back to `-1` while `o? - 1` subtracts from `o?`
+    fn default_follows(&self, value: Span, question: Span) -> bool {
+        match &self.curr {
+            Some(token) if value.end < question.start => starts_unary(&token.kind),
+            _ => false,
+        }
+    }
+
+    fn parse_range(&mut self) -> Result<Expr> {
+        let from = self.parse_binary(0)?;
+
+        let inclusive = match &self.curr {
+            Some(token) if token.kind == DoubleDot => false,
+            Some(token) if token.kind == DoubleDotEqual => true,
+            _ => return Ok(from),
+        };
+
+        self.bump();
+        let to = self.parse_binary(0)?;
+        let span = from.span.to(to.span);
+
+        let range = Range {
+            from,
+            to,
+            inclusive,
+        };
+        Ok(Expr::new(ExprKind::Range(Box::new(range)), span))
+    }
+
+    // look ahead for an argument list followed by an arrow: `->`, `() ->`,
+    // `a, b mut ->` or `(a, b mut) ->`
+    fn at_function(&mut self) -> bool {
+        let parenthesised = self.nth_is(0, OpenParen);
+        let mut n = if parenthesised { 1 } else { 0 };
+
+        if parenthesised && self.nth_is(n, CloseParen) {
+            return self.nth_is(n + 1, Arrow);
+        }
+
+        if !parenthesised && self.nth_is(n, Arrow) {
+            return true;
+        }
+
+        loop {
+            if !self.nth_is(n, Identifier) && !self.nth_is(n, SelfValue) {
+                return false;
+            }
+            n += 1;
+
+            if self.nth_is(n, Mut) {
+                n += 1;
+            }
+
+            if !self.nth_is(n, Comma) {
+                break;
+            }
+            n += 1;
+        }
+
+        if parenthesised {
+            if !self.nth_is(n, CloseParen) {
+                return false;
+            }
+            n += 1;
+        }
+
+        self.nth_is(n, Arrow)
+    }
+
+    fn parse_function(&mut self) -> Result<Expr> {
+        let start = self.start();
+        let parenthesised = self.curr_is(OpenParen);
+        if parenthesised {
+            self.bump();
+        }
+
+        let mut args = Vec::new();
+
+        while self.curr_is(Identifier) || self.curr_is(SelfValue) {
+            let arg_start = self.start();
+            let ident = self.bump().unwrap().literal.to_owned();
+            let mutable = self.curr_is(Mut);
+            if mutable {
+                self.bump();
+            }
+
+            let span = self.span_from(arg_start);
+            args.push(Arg {
+                ident,
+                mutable,
+                span,
+            });
+
+            if !self.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        if parenthesised {
+            self.expect(CloseParen)?;
+        }
+
+        self.expect(Arrow)?;
+
+        // `break` and `continue` cannot leave the function for a loop around it
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.parse_expr();
+        self.loops = loops;
+
+        let body = body?;
+        let span = self.span_from(start);
+
+        let function = Function {
+            args,
+            body: Rc::new(body),
+        };
+        Ok(Expr::new(ExprKind::Function(Box::new(function)), span))
+    }
+
+    fn parse_block(&mut self) -> Result<Expr> {
+        let start = self.start();
+        let stmts = self.parse_body()?;
+        Ok(Expr::new(ExprKind::Scope(stmts), self.span_from(start)))
+    }
+
+    // the statements between braces, which allow brace calls again, even in
+    // the head of a loop
+    fn parse_body(&mut self) -> Result<Vec<Stmt>> {
+        self.expect(OpenBrace)?;
+        let stmts = self.with_brace_calls(true, Self::parse_stmts)?;
+        self.bump();
+        Ok(stmts)
+    }
+
+    // the statements of a block, up to its closing brace
+    fn parse_stmts(&mut self) -> Result<Vec<Stmt>> {
+        let mut stmts = Vec::new();
+
+        loop {
+            match &self.curr {
+                Some(token) if token.kind == CloseBrace => return Ok(stmts),
+                Some(token) if token.kind == Semicolon => {
+                    self.bump();
+                }
+                Some(_) => stmts.push(self.parse_stmt_or_recover()),
+                None => return Err(self.expected(&CloseBrace.name())),
+            }
+        }
+    }
+
+    // precedence climbing: parse operands that bind tighter than `min_precedence`
+    // and fold them into left-associative binary nodes, except for `**`
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr> {
+        let mut left = self.parse_unary()?;
+
+        while let Some((operator, precedence)) = self.curr.as_ref().and_then(binary_operator) {
+            if precedence < min_precedence {
+                break;
+            }
+
+            self.bump();
+
+            let right = if operator == Operator::Power {
+                self.parse_binary(precedence)?
+            } else {
+                self.parse_binary(precedence + 1)?
+            };
+
+            let span = left.span.to(right.span);
+            let binary = Binary {
+                operator,
+                left_operand: left,
+                right_operand: right,
+            };
+            left = Expr::new(ExprKind::Binary(Box::new(binary)), span);
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        let operator = match self.curr.as_ref().map(|token| &token.kind) {
+            Some(Minus) => Operator::Minus,
+            Some(Not) => Operator::Not,
+            Some(Tilde) => Operator::BitNot,
+            _ => return self.parse_postfix(),
+        };
+
+        let start = self.start();
+        self.bump();
+
+        // bind tighter than every binary operator but `**`, so `-2 ** 2` is `-(2 ** 2)`
+        let operand = self.parse_binary(POWER_PRECEDENCE)?;
+        let span = self.span_from(start);
+
+        let unary = Unary { operator, operand };
+        Ok(Expr::new(ExprKind::Unary(Box::new(unary)), span))
+    }
+
+    // calls with either braces or parentheses: `f { a, b }` and `f(a, b)`
+    fn parse_postfix(&mut self) -> Result<Expr> {
+        self.parse_postfix_chain(true)
+    }
+
+    // calls, fields and, if `tries`, `?`s after a primary expression
+    fn parse_postfix_chain(&mut self, tries: bool) -> Result<Expr> {
+        let mut expr = self.parse_primary()?;
+
+        // a brace after a block, loop or conditional opens another block
+        let brace_calls = self.brace_calls && !is_block_like(&expr.kind);
+        // `1.2.3` is a malformed number rather than a field of `1.2`
+        let fields = !matches!(expr.kind, ExprKind::Int(_) | ExprKind::Float(_));
+
+        loop {
+            let close = match &self.curr {
+                Some(token) if token.kind == OpenBrace && brace_calls => CloseBrace,
+                Some(token) if token.kind == OpenParen => CloseParen,
+                Some(token) if token.kind == Dot && fields => {
+                    expr = self.parse_field(expr)?;
+                    continue;
+                }
+                Some(token) if token.kind == Question && tries => {
+                    expr = self.parse_try(expr)?;
+                    continue;
+                }
+                _ => break,
+            };
+
+            self.bump();
+            let args = self.parse_args(close)?;
+            let span = self.span_from(expr.span.start);
+
+            let call = Call { callee: expr, args };
+            expr = Expr::new(ExprKind::Call(Box::new(call)), span);
+        }
+
+        Ok(expr)
+    }
+
+    // `.x` or `.0` after `target`
+    fn parse_field(&mut self, mut target: Expr) -> Result<Expr> {
+        self.bump();
+
+        let token = match &self.curr {
+            Some(token) if matches!(token.kind, Identifier | Int | Float) => self.bump().unwrap(),
+            _ => return Err(self.expected("field")),
+        };
+
+        // `value.try` is `value?`
+        if token.kind == Identifier && token.literal == "try" {
+            let span = target.span.to(token.span);
+            let try_ = Try {
+                expr: target,
+                default: None,
+            };
+            return Ok(Expr::new(ExprKind::Try(Box::new(try_)), span));
+        }
+
+        let fields = match token.kind {
+            Identifier => vec![(ExprKind::Ident(token.literal.to_owned()), token.span)],
+            _ => tuple_fields(&token)
+                .ok_or_else(|| invalid_tuple_field(&token))?
+                .into_iter()
+                .map(|(field, span)| (ExprKind::Int(field.to_owned()), span))
+                .collect(),
+        };
+
+        for (field, span) in fields {
+            let field = Field {
+                field: Expr::new(field, span),
+                target,
+            };
+            let span = Span::new(field.target.span.start, span.end);
+            target = Expr::new(ExprKind::Field(Box::new(field)), span);
+        }
+
+        Ok(target)
+    }
+
+    fn parse_args(&mut self, close: TokenKind) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+
+        while !self.curr_is(close.clone()) {
+            args.push(self.with_brace_calls(true, Self::parse_expr)?);
+
+            if !self.curr_is(Comma) {
+                break;
+            }
+            self.bump();
+        }
+
+        self.expect(close)?;
+        Ok(args)
+    }
+
+    // run `parse` with brace calls allowed or not, as they were before after
+    fn with_brace_calls<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let brace_calls = std::mem::replace(&mut self.brace_calls, allowed);
+        let result = parse(self);
+        self.brace_calls = brace_calls;
+        result
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        if self.curr_is(OpenBrace) {
+            return self.parse_block();
+        }
+
+        let kind = match &self.curr {
+            Some(token) => token.kind.clone(),
+            None => return Err(self.expected("expression")),
+        };
+
+        let kind = match kind {
+            Int => ExprKind::Int,
+            Float => ExprKind::Float,
+            Bool => ExprKind::Bool,
+            Char => ExprKind::Char,
+            Str => ExprKind::Str,
+            RawStr => ExprKind::RawStr,
+            Identifier if self.struct_follows() => return self.parse_struct(),
+            Identifier | SelfValue => ExprKind::Ident,
+            Template => return self.parse_template(),
+            OpenParen => {
+                let start = self.start();
+                self.bump();
+
+                if self.curr_is(CloseParen) {
+                    self.bump();
+                    return Ok(Expr::new(ExprKind::Unit, self.span_from(start)));
+                }
+
+                let expr = self.with_brace_calls(true, Self::parse_expr)?;
+
+                if !self.curr_is(Comma) {
+                    self.expect(CloseParen)?;
+                    return Ok(expr);
+                }
+
+                return self.parse_tuple(start, expr);
+            }
+            TokenKind::Label | TokenKind::Loop | TokenKind::While | TokenKind::For => {
+                return self.parse_loop()
+            }
+            If => return self.parse_if(),
+            TokenKind::Match => return self.parse_match(),
+            MissingDigits | InvalidDigit | InvalidSuffix => return Err(self.malformed_number()),
+            InvalidEscape => return Err(self.invalid_escape()),
+            _ => return Err(self.expected("expression")),
+        };
+
+        let token = self.bump().unwrap();
+        Ok(Expr::new(kind(token.literal.to_owned()), token.span))
+    }
+
+    // `Point { x: ...` is a struct, where `Some { x : f }` would be a call.
+    // Struct fields are written with the colon right after the name.
+    fn struct_follows(&mut self) -> bool {
+        let constructor = matches!(&self.curr, Some(token) if is_constructor(token));
+
+        if !constructor || !self.brace_calls || !self.peek_is(OpenBrace) {
+            return false;
+        }
+
+        match (self.nth(2).cloned(), self.nth(3)) {
+            (Some(name), Some(colon)) => {
+                name.kind == Identifier && colon.kind == Colon && colon.span.start == name.span.end
+            }
+            _ => false,
+        }
+    }
+
+    // `Point { x: 0, y: 0 }`
+    fn parse_struct(&mut self) -> Result<Expr> {
+        let start = self.start();
+        let ident = self.bump().unwrap().literal.to_owned();
+        self.bump();
+
+        let fields = self.with_brace_calls(true, |parser| {
+            let mut fields = Vec::new();
+
+            while !parser.curr_is(CloseBrace) {
+                let name = parser.expect(Identifier)?.literal.to_owned();
+                parser.expect(Colon)?;
+                fields.push((name, parser.parse_expr()?));
+
+                if !parser.curr_is(Comma) {
+                    break;
+                }
+                parser.bump();
+            }
+
+            Ok(fields)
+        })?;
+
+        self.expect(CloseBrace)?;
+        let kind = ExprKind::Struct(Box::new(Struct { ident, fields }));
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // the rest of `(first, b, c)` after `first`, or of `(first,)`
+    fn parse_tuple(&mut self, start: usize, first: Expr) -> Result<Expr> {
+        let mut exprs = vec![first];
+
+        while self.curr_is(Comma) {
+            self.bump();
+
+            if self.curr_is(CloseParen) {
+                break;
+            }
+            exprs.push(self.with_brace_calls(true, Self::parse_expr)?);
+        }
+
+        self.expect(CloseParen)?;
+        Ok(Expr::new(ExprKind::Tuple(exprs), self.span_from(start)))
+    }
+
+    // `"{name} is {age}"` is sugar for `format { name } + " is " + format { age }`
+    fn parse_template(&mut self) -> Result<Expr> {
+        let token = self.bump().unwrap();
+        let mut template: Option<Expr> = None;
+
+        for part in scan_string(token.literal).parts {
+            let part = match part {
+                Part::Text(range) => {
+                    let span = text_span(&token, &range);
+                    Expr::new(ExprKind::Str(token.literal[range].to_owned()), span)
+                }
+                Part::Interpolation(range) => {
+                    let span = interpolation_span(&token, &range);
+                    let mut parser = self.interpolation_parser(&token, range);
+                    std::mem::swap(self, &mut parser);
+                    let value = self.parse_interpolation();
+                    std::mem::swap(self, &mut parser);
+
+                    let call = Call {
+                        callee: Expr::new(ExprKind::Ident("format".to_owned()), span),
+                        args: vec![value?],
+                    };
+                    Expr::new(ExprKind::Call(Box::new(call)), span)
+                }
+            };
+
+            template = Some(match template {
+                Some(left) => {
+                    let span = left.span.to(part.span);
+                    let binary = Binary {
+                        operator: Operator::Plus,
+                        left_operand: left,
+                        right_operand: part,
+                    };
+                    Expr::new(ExprKind::Binary(Box::new(binary)), span)
+                }
+                None => part,
+            });
+        }
+
+        // templates have at least one interpolation
+        let mut template = template.unwrap();
+        template.span = token.span;
+        Ok(template)
+    }
+
+    // a parser for the expression between the braces at `range` of a template
+    fn interpolation_parser(
+        &self,
+        template: &Token<'a>,
+        range: std::ops::Range<usize>,
+    ) -> Parser<'a> {
+        let (mut line, mut column) = (template.line, template.column + 1);
+
+        for ch in template.literal[..range.start].chars() {
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+
+        let start = template.span.start + 1;
+        let source = self.lexer.source();
+        let lexer = Lexer::new_at(source, start + range.start, start + range.end, line, column);
+
+        let mut parser = Parser::with_lexer(lexer);
+        parser.prev_end = start + range.start;
+        parser
+    }
+
+    // the whole input of an interpolation parser is one expression
+    fn parse_interpolation(&mut self) -> Result<Expr> {
+        let expr = self.parse_expr()?;
+
+        match self.curr {
+            Some(_) => Err(self.expected(&CloseBrace.name())),
+            None => Ok(expr),
+        }
+    }
+
+    fn invalid_escape(&self) -> Diagnostic {
+        self.error("invalid escape sequence".to_owned()).with_note(
+            "the escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\', \\{, \\} and \\u{7FFF}",
+        )
+    }
+}
+
+// the indices in a tuple field token with their spans: `.0`, or `.0.1`, which
+// is lexed as the float `0.1`
+fn tuple_fields<'a>(token: &Token<'a>) -> Option<Vec<(&'a str, Span)>> {
+    let mut fields = Vec::new();
+    let mut start = token.span.start;
+
+    for field in token.literal.split('.') {
+        if field.is_empty() || !field.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        fields.push((field, Span::new(start, start + field.len())));
+        start += field.len() + 1;
+    }
+
+    Some(fields)
+}
+
+fn invalid_tuple_field(token: &Token) -> Diagnostic {
+    Diagnostic::error(
+        "syntax-error",
+        format!("invalid tuple field `{}`", token.literal),
+        token.span,
+    )
+}
+
+// the span in the source of the text at `range` of a template
+fn text_span(template: &Token, range: &std::ops::Range<usize>) -> Span {
+    let start = template.span.start + 1;
+    Span::new(start + range.start, start + range.end)
+}
+
+// the span of an interpolation, braces included
+fn interpolation_span(template: &Token, range: &std::ops::Range<usize>) -> Span {
+    let span = text_span(template, range);
+    Span::new(span.start - 1, span.end + 1)
+}
+
+const POWER_PRECEDENCE: u8 = 10;
+
+fn owned(lines: Vec<&str>) -> Vec<String> {
+    lines.into_iter().map(str::to_owned).collect()
+}
+
+fn is_block_like(kind: &ExprKind) -> bool {
+    matches!(
+        kind,
+        ExprKind::Scope(_)
+            | ExprKind::Conditional(_)
+            | ExprKind::Match(_)
+            | ExprKind::Loop(_)
+            | ExprKind::While(_)
+            | ExprKind::For(_)
+    )
+}
+
+// `_`, which is only lexed as `Underscore` when followed by whitespace
+fn is_wildcard(token: &Token) -> bool {
+    token.kind == Underscore || (token.kind == Identifier && token.literal == "_")
+}
+
+// constructors are capitalised, like the types they belong to: `Some`, `None`
+fn is_constructor(token: &Token) -> bool {
+    token.kind == Identifier && token.literal.starts_with(|c: char| c.is_uppercase())
+}
+
+fn is_literal(kind: &TokenKind) -> bool {
+    matches!(kind, Int | Float | Bool | Char | Str | RawStr)
+}
+
+// whether a token can start the operand of a prefix operator
+fn starts_unary(kind: &TokenKind) -> bool {
+    is_literal(kind)
+        || matches!(
+            kind,
+            Identifier
+                | SelfValue
+                | Template
+                | OpenParen
+                | OpenBrace
+                | Label
+                | Loop
+                | While
+                | For
+                | If
+                | Match
+                | Minus
+                | Not
+                | Tilde
+        )
+}
+
+fn assign_operator(kind: &TokenKind) -> Operator {
+    match kind {
+        PlusAssign => Operator::Plus,
+        MinusAssign => Operator::Minus,
+        MultiplyAssign => Operator::Multiply,
+        DivideAssign => Operator::Divide,
+        ModuloAssign => Operator::Modulo,
+        PowerAssign => Operator::Power,
+        BitAndAssign => Operator::BitAnd,
+        BitOrAssign => Operator::BitOr,
+        BitXorAssign => Operator::BitXor,
+        LeftShiftAssign => Operator::LeftShift,
+        RightShiftAssign => Operator::RightShift,
+        _ => unreachable!("{:?} is not an assignment operator", kind),
+    }
+}
+
+fn binary_operator(token: &Token) -> Option<(Operator, u8)> {
+    let operator = match token.kind {
+        Or => (Operator::Or, 1),
+        And => (Operator::And, 2),
+        Equal => (Operator::Equal, 3),
+        NotEqual => (Operator::NotEqual, 3),
+        LessThan => (Operator::LessThan, 3),
+        GreaterThan => (Operator::GreaterThan, 3),
+        LessEqual => (Operator::LessEqual, 3),
+        GreaterEqual => (Operator::GreaterEqual, 3),
+        Pipe => (Operator::BitOr, 4),
+        Caret => (Operator::BitXor, 5),
+        Ampersand => (Operator::BitAnd, 6),
+        LeftShift => (Operator::LeftShift, 7),
+        RightShift => (Operator::RightShift, 7),
+        Plus => (Operator::Plus, 8),
+        Minus => (Operator::Minus, 8),
+        Multiply => (Operator::Multiply, 9),
+        Divide => (Operator::Divide, 9),
+        Modulo => (Operator::Modulo, 9),
+        Power => (Operator::Power, POWER_PRECEDENCE),
+        _ => return None,
+    };
+
+    Some(operator)
 }
@@ -0,0 +1,216 @@
+use super::{Lexer, Span, Token, TokenKind};
+
+// The tokens of a buffer that is edited in place, as in an editor. An edit
+// relexes from just before it until the new tokens line up with the old ones
+// again, and the tokens after that are reused with their positions shifted.
+//
+// The lexer has no state besides its position, so once a new token starts
+// where an old one did, past the edit, the rest of the old tokens follow.
+#[derive(Debug, Clone)]
+pub struct TokenStream {
+    source: String,
+    tokens: Vec<Entry>,
+}
+
+// How many bytes past its end the lexer can look before deciding a token,
+// as in `1.5`, `---` or `'\u{10FFFF}'`. Tokens that end further before an
+// edit than this are unaffected by it.
+const LOOKAHEAD: usize = 12;
+
+// a token whose literal is stored as offsets from the start of its span, so
+// that moving it is only a matter of moving the span
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    kind: TokenKind,
+    span: Span,
+    literal: (usize, usize),
+    line: u32,
+    column: u32,
+}
+
+impl TokenStream {
+    pub fn new(source: impl Into<String>) -> Self {
+        let source = source.into();
+        let tokens = lex(&source, 0, 1, 1);
+        TokenStream { source, tokens }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = Token<'_>> {
+        self.tokens.iter().map(|entry| self.token(entry))
+    }
+
+    // Replace the bytes at `range` with `text`, and return the indices of the
+    // tokens that were relexed. The tokens before them are untouched and the
+    // ones after them have only moved.
+    pub fn edit(&mut self, range: Span, text: &str) -> std::ops::Range<usize> {
+        let delta = text.len() as isize - (range.end - range.start) as isize;
+        self.source.replace_range(range.start..range.end, text);
+
+        let affected = self
+            .tokens
+            .partition_point(|entry| entry.span.end + LOOKAHEAD < range.start);
+
+        // with no token before that, relex from the start of the source
+        let (first, start, line, column) = match affected.checked_sub(1) {
+            Some(first) => {
+                let entry = &self.tokens[first];
+                (first, entry.span.start, entry.line, entry.column)
+            }
+            None => (0, 0, 1, 1),
+        };
+
+        let edit_end = range.start + text.len();
+        let mut old = self
+            .tokens
+            .partition_point(|entry| entry.span.start < range.end)
+            .max(first);
+        let mut relexed = Vec::new();
+        let mut reused = None;
+
+        for token in Lexer::new_at(&self.source, start, self.source.len(), line, column) {
+            if token.span.start >= edit_end {
+                let start = token.span.start as isize - delta;
+                while self
+                    .tokens
+                    .get(old)
+                    .is_some_and(|entry| (entry.span.start as isize) < start)
+                {
+                    old += 1;
+                }
+
+                // the new token starts where an old one did, so the rest
+                // of the old tokens only need moving
+                if let Some(entry) = self.tokens.get(old) {
+                    if entry.span.start as isize == start {
+                        shift(&mut self.tokens[old..], &token, delta);
+                        reused = Some(old);
+                        break;
+                    }
+                }
+            }
+
+            relexed.push(entry(&self.source, &token));
+        }
+
+        let relexed_len = relexed.len();
+        let reused = reused.unwrap_or(self.tokens.len());
+        self.tokens.splice(first..reused, relexed);
+        first..first + relexed_len
+    }
+
+    fn token<'a>(&'a self, entry: &Entry) -> Token<'a> {
+        let start = entry.span.start;
+        Token {
+            kind: entry.kind.clone(),
+            literal: &self.source[start + entry.literal.0..start + entry.literal.1],
+            span: entry.span,
+            line: entry.line,
+            column: entry.column,
+        }
+    }
+}
+
+fn lex(source: &str, start: usize, line: u32, column: u32) -> Vec<Entry> {
+    Lexer::new_at(source, start, source.len(), line, column)
+        .map(|token| entry(source, &token))
+        .collect()
+}
+
+fn entry(source: &str, token: &Token) -> Entry {
+    let literal = token.literal.as_ptr() as usize - source.as_ptr() as usize;
+    let literal = literal - token.span.start;
+
+    Entry {
+        kind: token.kind.clone(),
+        span: token.span,
+        literal: (literal, literal + token.literal.len()),
+        line: token.line,
+        column: token.column,
+    }
+}
+
+// move `entries` by `delta` bytes so that the first one is at `to`. Only the
+// tokens on the same line as the first one change column.
+fn shift(entries: &mut [Entry], to: &Token, delta: isize) {
+    let line = entries[0].line;
+    let lines = to.line as i64 - line as i64;
+    let columns = to.column as i64 - entries[0].column as i64;
+
+    for entry in entries {
+        if entry.line == line {
+            entry.column = (entry.column as i64 + columns) as u32;
+        }
+
+        entry.line = (entry.line as i64 + lines) as u32;
+        entry.span.start = (entry.span.start as isize + delta) as usize;
+        entry.span.end = (entry.span.end as isize + delta) as usize;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn relexed(source: &str, range: Span, text: &str) -> Vec<String> {
+        let mut stream = TokenStream::new(source);
+        let changed = stream.edit(range, text);
+
+        let tokens: Vec<_> = stream.tokens().collect();
+        assert_eq!(tokens, Lexer::new(stream.source()).collect::<Vec<_>>());
+
+        tokens[changed]
+            .iter()
+            .map(|token| token.literal.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn relexes_only_around_the_edit() {
+        let source = "first = 1;\nsecond = 2;\nthird = 3;\nfourth = 4;";
+        assert_eq!(
+            relexed(source, Span::new(31, 32), "30"),
+            vec!["second", "=", "2", ";", "third", "=", "30"]
+        );
+        assert_eq!(
+            relexed("x = 1. y = 2", Span::new(6, 6), "5"),
+            vec!["x", "=", "1.5"]
+        );
+    }
+
+    #[test]
+    fn edits_inside_strings_and_comments() {
+        assert_eq!(
+            relexed("x = \"ab\"; y = 1;", Span::new(6, 6), "\" + \""),
+            vec!["x", "=", "a", "+", "b"]
+        );
+        assert_eq!(
+            relexed("a /* b */ c", Span::new(7, 9), ""),
+            vec!["a", " b  c"]
+        );
+    }
+
+    #[test]
+    fn moves_the_tokens_after_the_edit() {
+        let mut stream = TokenStream::new("a = 1;\n\nb = 2; c = 3;");
+        stream.edit(Span::new(0, 1), "abc\n");
+
+        let positions: Vec<_> = stream
+            .tokens()
+            .map(|token| (token.literal, token.line, token.column))
+            .collect();
+        assert_eq!(positions[4], ("b", 4, 1));
+        assert_eq!(positions[8], ("c", 4, 8));
+    }
+}
@@ -1,22 +1,49 @@
+// `literal` is borrowed from the source: the word or symbol itself, or the
+// text between the quotes of a char or string
 #[derive(Debug, Clone, PartialEq)]
-pub struct Token {
+pub struct Token<'a> {
     pub kind: TokenKind,
-    pub literal: String,
+    pub literal: &'a str,
+    pub span: Span,
     pub line: u32,
     pub column: u32,
 }
 
+// A range of bytes in the source, `end` excluded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    // the smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     // Identifiers
-    Ident,
+    Identifier,
+    Label, // 'outer
 
     // Literals
-    Bool,  //
-    Int,   // 69
-    Float, // 1.0, 4.20
-    Char,  // 'a'
-    Str,   // "hello world"
+    Bool,     //
+    Int,      // 69
+    Float,    // 1.0, 4.20
+    Char,     // 'a'
+    Str,      // "hello world"
+    RawStr,   // r"C:\dir", r#"say "hi""#
+    Template, // "hello {name}"
 
     // Control Flow
     If,
@@ -25,17 +52,21 @@ pub enum TokenKind {
     For,
     Loop,
     Match,
+    Select,
+    In,
     Break,
     Continue,
     Spawn,
 
     // Types
-    TypeDef,
+    Type,
     Impl,
     Trait,
     Where,
     As,
     Derive,
+    SelfType,  // Self
+    SelfValue, // self
 
     // Functions
     Function,
@@ -43,28 +74,29 @@ pub enum TokenKind {
     Arrow, // ->
 
     // Symbols
-    Ampersand,    // &
-    Pipe,         // |
-    Caret,        // ^
-    Tilde,        // ~
-    LeftShift,    // <<
-    RightShift,   // >>
-    Colon,        // :
-    DoubleColon,  // ::
-    Dot,          // .
-    DoubleDot,    // ..
-    DoubleArrow,  // =>
-    At,           // @
-    Question,     // ?
-    Semicolon,    // ;
-    OpenParen,    // (
-    CloseParen,   // )
-    OpenBracket,  // [
-    CloseBracket, // ]
-    OpenBrace,    // {
-    CloseBrace,   // }
-    Comma,        // ,
-    Underscore,   // _
+    Ampersand,      // &
+    Pipe,           // |
+    Caret,          // ^
+    Tilde,          // ~
+    LeftShift,      // <<
+    RightShift,     // >>
+    Colon,          // :
+    DoubleColon,    // ::
+    Dot,            // .
+    DoubleDot,      // ..
+    DoubleDotEqual, // ..=
+    ArrowRight,     // =>
+    At,             // @
+    Question,       // ?
+    Semicolon,      // ;
+    OpenParen,      // (
+    CloseParen,     // )
+    OpenBracket,    // [
+    CloseBracket,   // ]
+    OpenBrace,      // {
+    CloseBrace,     // }
+    Comma,          // ,
+    Underscore,     // _
 
     // Bool Operators
     And,          // &&
@@ -104,13 +136,76 @@ pub enum TokenKind {
 
     // Misc
     Mut,
+    Ref,
     Use,
     Pub,
     Const,
 
     // Comments
-    Comment,
+    Comment,            // -- line, /* block */
+    DocComment,         // --- documents the binding below
+    UntermBlockComment, // /* without its */
 
     Unknown,
     UntermDoubleQuote,
+    InvalidEscape, // "\q"
+    MissingDigits, // 0x, 1e
+    InvalidDigit,  // 0b12, 0o8
+    InvalidSuffix, // 42px, 1.5i32
+}
+
+// Every keyword and the kind it lexes to. Any other word is an `Identifier`.
+pub const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("true", TokenKind::Bool),
+    ("false", TokenKind::Bool),
+    ("if", TokenKind::If),
+    ("else", TokenKind::Else),
+    ("while", TokenKind::While),
+    ("for", TokenKind::For),
+    ("in", TokenKind::In),
+    ("loop", TokenKind::Loop),
+    ("match", TokenKind::Match),
+    ("select", TokenKind::Select),
+    ("break", TokenKind::Break),
+    ("continue", TokenKind::Continue),
+    ("spawn", TokenKind::Spawn),
+    ("type", TokenKind::Type),
+    ("impl", TokenKind::Impl),
+    ("trait", TokenKind::Trait),
+    ("where", TokenKind::Where),
+    ("as", TokenKind::As),
+    ("derive", TokenKind::Derive),
+    ("Self", TokenKind::SelfType),
+    ("self", TokenKind::SelfValue),
+    ("fn", TokenKind::Function),
+    ("return", TokenKind::Return),
+    ("mut", TokenKind::Mut),
+    ("ref", TokenKind::Ref),
+    ("use", TokenKind::Use),
+    ("pub", TokenKind::Pub),
+    ("const", TokenKind::Const),
+];
+
+impl TokenKind {
+    // the kind of `word` if it is a keyword
+    pub fn keyword(word: &str) -> Option<TokenKind> {
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == word)
+            .map(|(_, kind)| kind.clone())
+    }
+
+    // the name used in syntax errors: `CloseParen` is `CLOSE_PAREN`
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+
+        for (i, ch) in format!("{:?}", self).chars().enumerate() {
+            if ch.is_uppercase() && i > 0 {
+                name.push('_');
+            }
+            name.push(ch.to_ascii_uppercase());
+        }
+
+        name
+    }
 }
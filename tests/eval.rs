@@ -0,0 +1,763 @@
+use morph::eval::{Env, Eval};
+use morph::parser::{FlatParser, Parser};
+
+fn eval(input: &str) -> String {
+    let (ast, diagnostics) = Parser::new(input).parse();
+    assert!(diagnostics.is_empty(), "failed to parse: {:?}", diagnostics);
+    ast.eval(&Env::global()).to_string()
+}
+
+// evaluate a program the checker accepts, as the REPL would
+fn eval_checked(input: &str) -> String {
+    let (mut ast, _) = FlatParser::new(input).parse();
+    let errors = ast.infer_types().err().unwrap_or_default();
+    let messages: Vec<_> = errors.iter().map(|error| error.to_string()).collect();
+    assert!(messages.is_empty(), "failed to check: {:?}", messages);
+    eval(input)
+}
+
+#[test]
+fn literals() {
+    assert_eq!(eval("x = 42; x"), "42");
+    assert_eq!(eval("x = 4.2; x"), "4.2");
+    assert_eq!(eval("x = true; x"), "true");
+    assert_eq!(eval("x = 'a'; x"), "a");
+    assert_eq!(eval(r#"x = "hello"; x"#), "hello");
+}
+
+#[test]
+fn string_literals() {
+    assert_eq!(eval(r#""a\tb\\c \"q\"""#), "a\tb\\c \"q\"");
+    assert_eq!(eval(r#""\u{48}\u{1F600}""#), "H\u{1F600}");
+    assert_eq!(eval(r#"'\n'"#), "\n");
+    assert_eq!(eval("\"one \\\n    two\""), "one two");
+    assert_eq!(eval("\"line\n  two\""), "line\n  two");
+    assert_eq!(eval(r##"r"C:\dir\n""##), r"C:\dir\n");
+    assert_eq!(eval(r###"r#"say "hi""#"###), r#"say "hi""#);
+}
+
+#[test]
+fn string_interpolation() {
+    assert_eq!(eval(r#"x = 1; y = 2; "{x} + {y} = {x + y}""#), "1 + 2 = 3");
+    assert_eq!(eval(r#"name = "morph"; "hi {name}!""#), "hi morph!");
+    assert_eq!(eval(r#""{0..3 : len}""#), "3");
+    assert_eq!(eval(r#""{"nested"}" + "{}""#), "nested{}");
+    assert_eq!(eval(r#""\{x}""#), "{x}");
+    assert_eq!(eval(r#"format { "({}, {})", 1, 2.5 }"#), "(1, 2.5)");
+    assert_eq!(eval(r#"format { "{} and", 'a', 'b' }"#), "a and b");
+    assert_eq!(eval("format { 42 }"), "42");
+}
+
+#[test]
+fn number_literals() {
+    assert_eq!(eval("1_000_000"), "1000000");
+    assert_eq!(eval("0xff + 0o17 + 0b11"), "273");
+    assert_eq!(eval("255u8 + 1"), "256");
+    assert_eq!(eval("1e3"), "1000.0");
+    assert_eq!(eval("2.5e-1"), "0.25");
+    assert_eq!(eval("3f64 / 2.0"), "1.5");
+    assert_eq!(
+        eval("256u8"),
+        "Runtime Error: integer literal `256u8` is out of range for `u8`"
+    );
+    assert_eq!(
+        eval("x = 9223372036854775808; x"),
+        "Runtime Error: integer literal `9223372036854775808` is out of range for `Int`"
+    );
+    assert_eq!(eval("-9223372036854775808"), "-9223372036854775808");
+    assert_eq!(eval("-0x80i8"), "-128");
+    assert_eq!(
+        eval("-9223372036854775809"),
+        "Runtime Error: integer literal `-9223372036854775809` is out of range for `Int`"
+    );
+    assert_eq!(
+        eval("1e39f32"),
+        "Runtime Error: float literal `1e39f32` is out of range for `f32`"
+    );
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(eval("x = 1 + 2; x"), "3");
+    assert_eq!(eval("x = 1 + 2 * 3; x"), "7");
+    assert_eq!(eval("x = 7 - 4 - 2; x"), "1");
+    assert_eq!(eval("x = 7 % 4; x"), "3");
+    assert_eq!(eval("x = 1.5 * 2.0; x"), "3.0");
+    assert_eq!(eval(r#"x = "foo" + "bar"; x"#), "foobar");
+}
+
+#[test]
+fn bindings() {
+    assert_eq!(eval("x = 2; y = x * x; y"), "4");
+    assert_eq!(eval("x = 2; x = x + 1; x"), "3");
+}
+
+#[test]
+fn return_unwinds() {
+    assert_eq!(eval("return 1; x = 2; x"), "1");
+}
+
+#[test]
+fn runtime_errors() {
+    assert_eq!(eval("x"), "Runtime Error: unknown identifier `x`");
+    assert_eq!(eval("x = 1 / 0; x"), "Runtime Error: division by zero");
+    assert_eq!(
+        eval("x = 9223372036854775807 + 1; x"),
+        "Runtime Error: integer overflow"
+    );
+    assert_eq!(
+        eval(r#"x = 1 + "a"; x"#),
+        "Runtime Error: unsupported operands for Plus: Int and String"
+    );
+}
+
+#[test]
+fn compound_assignment() {
+    assert_eq!(eval("x = 1; x += 2; x"), "3");
+    assert_eq!(eval("x = 10; x -= 4; x *= 3; x /= 2; x %= 5; x"), "4");
+    assert_eq!(eval("x += 1; x"), "Runtime Error: unknown identifier `x`");
+    assert_eq!(eval("x = 1; loop { x += { break 7; }; }"), "7");
+    assert_eq!(
+        eval("f = o -> { x = 1; x += o?; Some(x) }; f(None)"),
+        "None"
+    );
+    assert_eq!(
+        eval("x = 1; x += y; x"),
+        "Runtime Error: unknown identifier `y`"
+    );
+}
+
+#[test]
+fn precedence() {
+    assert_eq!(eval("1 + 2 * 3 - 4 / 2"), "5");
+    assert_eq!(eval("(1 + 2) * 3"), "9");
+    assert_eq!(eval("2 * 3 % 4"), "2");
+    assert_eq!(eval("1 + 2 == 3 && 2 < 1 || true"), "true");
+    assert_eq!(eval("1 | 2 ^ 3 & 6 << 1"), "3");
+    assert_eq!(eval("1 << 2 + 1"), "8");
+    assert_eq!(eval("((((42))))"), "42");
+    assert_eq!(eval("()"), "()");
+}
+
+#[test]
+fn power_is_right_associative() {
+    assert_eq!(eval("2 ** 3 ** 2"), "512");
+    assert_eq!(eval("-2 ** 2"), "-4");
+    assert_eq!(eval("2 * 3 ** 2"), "18");
+    assert_eq!(eval("2.0 ** 0.5 > 1.41"), "true");
+    assert_eq!(eval("2 ** -1"), "Runtime Error: negative integer exponent");
+}
+
+#[test]
+fn unary_operators() {
+    assert_eq!(eval("-5 + 2"), "-3");
+    // `--5` would be a comment
+    assert_eq!(eval("- -5"), "5");
+    assert_eq!(eval("-(-5)"), "5");
+    assert_eq!(eval("!true"), "false");
+    assert_eq!(eval("!(1 > 2)"), "true");
+    assert_eq!(eval("~0"), "-1");
+    assert_eq!(eval("-2.5"), "-2.5");
+}
+
+#[test]
+fn comparisons() {
+    assert_eq!(eval("1 != 2"), "true");
+    assert_eq!(eval("2 <= 2"), "true");
+    assert_eq!(eval("3 >= 4"), "false");
+    assert_eq!(eval("'a' < 'b'"), "true");
+    assert_eq!(eval(r#""abc" == "abc""#), "true");
+    assert_eq!(
+        eval("1 == 1.0"),
+        "Runtime Error: cannot compare Int with Float"
+    );
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    assert_eq!(eval("false && 1 / 0 == 0"), "false");
+    assert_eq!(eval("true || 1 / 0 == 0"), "true");
+    assert_eq!(
+        eval("true && 1 / 0 == 0"),
+        "Runtime Error: division by zero"
+    );
+}
+
+#[test]
+fn bitwise_operators() {
+    assert_eq!(eval("6 & 3"), "2");
+    assert_eq!(eval("6 | 3"), "7");
+    assert_eq!(eval("6 ^ 3"), "5");
+    assert_eq!(eval("1 << 4"), "16");
+    assert_eq!(eval("-16 >> 2"), "-4");
+    assert_eq!(eval("x = 12; x &= 10; x <<= 1; x"), "16");
+    assert_eq!(eval("1 << 64"), "Runtime Error: shift amount out of range");
+}
+
+#[test]
+fn functions() {
+    assert_eq!(eval("add = a, b -> { a + b }; add"), "<function a, b>");
+    assert_eq!(eval("main = -> {}; main"), "<function>");
+}
+
+#[test]
+fn blocks() {
+    assert_eq!(eval("x = { a = 1; a + 1 }; x"), "2");
+    assert_eq!(eval("x = {}; x"), "()");
+    assert_eq!(
+        eval("x = { a = 1; a }; a"),
+        "Runtime Error: unknown identifier `a`"
+    );
+    assert_eq!(eval("x = 1; { x += 1; }; x"), "2");
+}
+
+#[test]
+fn calls() {
+    assert_eq!(eval("add = a, b -> { a + b }; add { 1, 2 }"), "3");
+    assert_eq!(eval("add = a, b -> a + b; add(1, 2)"), "3");
+    assert_eq!(eval("answer = -> 42; answer {}"), "42");
+    assert_eq!(eval("add = a -> b -> a + b; add { 1 } { 2 }"), "3");
+    assert_eq!(eval("f = x -> { return x * 2; x }; f { 4 }"), "8");
+    assert_eq!(eval("x = 1; x { 2 }"), "Runtime Error: Int is not callable");
+    assert_eq!(
+        eval("id = x -> x; id { 1, 2 }"),
+        "Runtime Error: expected 1 arguments, found 2"
+    );
+}
+
+#[test]
+fn closures() {
+    let source = "
+        apply_twice = f, x -> {
+            f { f { x } }
+        };
+
+        make_adder = n -> x -> x + n;
+        add_three = make_adder { 3 };
+        apply_twice { add_three, 1 }
+    ";
+
+    assert_eq!(eval(source), "7");
+
+    let source = "
+        count = 0;
+        increment = -> { count += 1; };
+        increment {};
+        increment {};
+        count
+    ";
+
+    assert_eq!(eval(source), "2");
+}
+
+#[test]
+fn pipelines() {
+    assert_eq!(eval("double = x -> x * 2; 4 : double"), "8");
+    assert_eq!(eval("add = a, b -> a + b; 4 : add { 1 } : add { 2 }"), "7");
+    assert_eq!(eval("1..4 : map { x -> x * x }"), "[1, 4, 9]");
+    assert_eq!(eval("1..=4 : sum"), "10");
+}
+
+#[test]
+fn ranges() {
+    assert_eq!(eval("0..10"), "0..10");
+    assert_eq!(eval("0..=10"), "0..11");
+    assert_eq!(eval("n = 3; 0..n - 1 : len"), "2");
+    assert_eq!(eval("(1..4).sum + \"abc\".len"), "9");
+    assert_eq!(
+        eval("Point :: { x: Int }; sum(Point { x: 1 })"),
+        "Runtime Error: Point has no method `sum`"
+    );
+    assert_eq!(
+        eval("f = o -> { t = 0; for i in 0..o.try { t += i; }; Some(t) }; f(None)"),
+        "None"
+    );
+    assert_eq!(
+        eval("f = o -> Some(o? .. 5 : len); f(Some(2))"),
+        "Some { 3 }"
+    );
+    assert_eq!(
+        eval("0..'a'"),
+        "Runtime Error: range bounds must be Int, found Int and Char"
+    );
+}
+
+#[test]
+fn calculate() {
+    let source = "
+        calculate = bottom, top -> {
+            bottom..=top
+                : filter { e -> e % 2 == 0 }
+                : sum
+        };
+
+        calculate { 1, 10 }
+    ";
+
+    assert_eq!(eval(source), "30");
+}
+
+#[test]
+fn signatures() {
+    assert_eq!(
+        eval("add | Int, Int -> Int; add = a, b -> a + b; add { 1, 2 }"),
+        "3"
+    );
+    assert_eq!(eval("x | Int; x = 1; x"), "1");
+}
+
+#[test]
+fn loops() {
+    assert_eq!(eval("sum = 0; for n in 1..=10 { sum += n; }; sum"), "55");
+    assert_eq!(eval("n = 0; while n < 5 { n += 1; }; n"), "5");
+    assert_eq!(eval("n = 0; loop { n += 1; break; }; n"), "1");
+    assert_eq!(eval("for n in 0..3 { n }"), "()");
+    assert_eq!(eval("n = 10; while n < 5 { n += 1; }; n"), "10");
+}
+
+#[test]
+fn break_with_value() {
+    assert_eq!(eval("n = 1; x = loop { n *= 2; break n; }; x"), "2");
+    assert_eq!(
+        eval("f = -> { for n in 0..10 { return n + 1; }; 0 }; f()"),
+        "1"
+    );
+}
+
+#[test]
+fn continue_skips_the_rest_of_the_body() {
+    assert_eq!(
+        eval("odd = 0; for n in 0..10 { m = n % 2; for _ in 0..m { continue; }; odd += m; }; odd"),
+        "5"
+    );
+}
+
+#[test]
+fn labeled_loops() {
+    assert_eq!(
+        eval("t = 0; 'outer: for a in 0..3 { for b in 0..3 { t += 1; continue 'outer; }; }; t"),
+        "3"
+    );
+    assert_eq!(
+        eval("t = 0; x = 'outer: loop { loop { t += 1; break 'outer t * 10; }; }; x"),
+        "10"
+    );
+}
+
+#[test]
+fn pattern_loops() {
+    assert_eq!(eval("k = 3; while 3 = k { k -= 1; }; k"), "2");
+    assert_eq!(eval("c = 0; for 1 in 0..5 { c += 1; }; c"), "1");
+    assert_eq!(
+        eval("for x in 1..3 { while Just { y } = x { } }"),
+        "Runtime Error: `Just` is not a constructor"
+    );
+}
+
+#[test]
+fn loop_errors() {
+    assert_eq!(eval("for n in 1 { }"), "Runtime Error: Int is not iterable");
+    assert_eq!(
+        eval("while 1 { }"),
+        "Runtime Error: condition must be a Bool"
+    );
+}
+
+#[test]
+fn conditionals() {
+    let check_value = r#"check_value = x -> {
+        if x > 0 {
+            "Positive"
+        } else {
+            "Non-positive"
+        };
+    };"#;
+    assert_eq!(
+        eval(&format!("{} check_value {{ 1 }}", check_value)),
+        "Positive"
+    );
+    assert_eq!(
+        eval(&format!("{} check_value {{ -1 }}", check_value)),
+        "Non-positive"
+    );
+
+    let sign = "sign = n -> if n > 0 { 1 } else if n < 0 { -1 } else { 0 };";
+    assert_eq!(eval(&format!("{} sign(-5) + sign(0) * 10", sign)), "-1");
+    assert_eq!(eval("x = if false { 1 }; x"), "()");
+    assert_eq!(eval("x = 1; if true { x = 2; }; x"), "1");
+    assert_eq!(
+        eval("if 1 { 2 }"),
+        "Runtime Error: condition must be a Bool"
+    );
+}
+
+#[test]
+fn tuples() {
+    assert_eq!(eval("t = (1, \"two\", 3.0); t"), "(1, two, 3.0)");
+    assert_eq!(eval("(1,)"), "(1,)");
+    assert_eq!(eval("(1, 2) < (1, 3)"), "true");
+    assert_eq!(eval("(1, 2) == (1, 2)"), "true");
+}
+
+#[test]
+fn matches() {
+    let describe = r#"describe = n -> match n {
+        0 => "zero",
+        1 | 2 | 3 => "small",
+        n if n < 0 => "negative",
+        10..=99 => "double digits",
+        _ => "large",
+    };"#;
+    for (n, expected) in [
+        (0, "zero"),
+        (2, "small"),
+        (-4, "negative"),
+        (42, "double digits"),
+        (100, "large"),
+    ] {
+        assert_eq!(
+            eval(&format!("{} describe {{ {} }}", describe, n)),
+            expected
+        );
+    }
+
+    assert_eq!(eval("match (1, (2, 3)) { (a, (b, c)) => a + b * c }"), "7");
+    assert_eq!(eval("match 5 { n @ 1..=9 => n * 2, _ => 0 }"), "10");
+    assert_eq!(eval("match -1 { -1 => 'm', _ => 'o' }"), "m");
+    assert_eq!(
+        eval("match 'q' { 'a'..'q' => 1, 'q'..='z' => 2, _ => 3 }"),
+        "2"
+    );
+    assert_eq!(
+        eval("x = match true { true => { 1 } false => { 2 } }; x"),
+        "1"
+    );
+    assert_eq!(eval("n = 1; match 2 { n => n }; n"), "1");
+}
+
+#[test]
+fn match_errors() {
+    assert_eq!(
+        eval("match 3 { 1 => 1 }"),
+        "Runtime Error: no arm matches 3"
+    );
+    assert_eq!(
+        eval("match 3 { n if n => 1 }"),
+        "Runtime Error: guard must be a Bool"
+    );
+    assert_eq!(
+        eval("match 3 { Just { x } => x }"),
+        "Runtime Error: `Just` is not a constructor"
+    );
+}
+
+#[test]
+fn structs() {
+    let point = "Point<T: Format> :: { x: T, y: T } derive Format;";
+    assert_eq!(
+        eval(&format!("{} p = Point {{ y: 2, x: 1 }}; p", point)),
+        "Point { x: 1, y: 2 }"
+    );
+    assert_eq!(
+        eval(&format!(
+            "{} p = Point {{ x: 1, y: 2 }}; p.x + p.y * 10",
+            point
+        )),
+        "21"
+    );
+    assert_eq!(eval("points = ((1, 2), 3); points.0.1"), "2");
+    assert_eq!(
+        eval(&format!(
+            "{} p = Point {{ x: 1, y: 2 }}; match p {{ Point {{ y = b }} => b }}",
+            point
+        )),
+        "2"
+    );
+    assert_eq!(
+        eval(&format!("{} Point {{ x: 1 }}", point)),
+        "Runtime Error: missing field `y` of `Point`"
+    );
+    assert_eq!(
+        eval(&format!("{} Point {{ x: 1, y: 2, z: 3 }}", point)),
+        "Runtime Error: `Point` has no field `z`"
+    );
+    assert_eq!(
+        eval(&format!("{} Point {{ x: 1, y: 2 }}.z", point)),
+        "Runtime Error: Point has no field or method `z`"
+    );
+    assert_eq!(
+        eval("x = 1; x.y"),
+        "Runtime Error: Int has no field or method `y`"
+    );
+}
+
+#[test]
+fn enums() {
+    let option = "Option :: T: Format -> | Some { T } | None;";
+    assert_eq!(eval(&format!("{} Some {{ 1 }}", option)), "Some { 1 }");
+    assert_eq!(eval(&format!("{} None", option)), "None");
+    assert_eq!(eval(&format!("{} Some", option)), "<constructor Some>");
+
+    let unwrap_or = "unwrap_or = o, d -> match o { Some { x } => x, None => d };";
+    assert_eq!(
+        eval(&format!(
+            "{} {} unwrap_or(Some {{ 1 }}, 0)",
+            option, unwrap_or
+        )),
+        "1"
+    );
+    assert_eq!(
+        eval(&format!("{} {} unwrap_or(None, 0)", option, unwrap_or)),
+        "0"
+    );
+
+    let shape = "Shape :: | Circle { radius: Float } | Rect(Float, Float);";
+    assert_eq!(
+        eval(&format!(
+            "{} match Rect(2.0, 3.0) {{ Circle {{ radius }} => radius, Rect {{ w, h }} => w * h }}",
+            shape
+        )),
+        "6.0"
+    );
+    assert_eq!(
+        eval(&format!("{} Some {{ 1, 2 }}", option)),
+        "Runtime Error: `Some` has 1 fields, found 2"
+    );
+    let shape = "Shape :: | Circle { radius: Float } | Rect { w: Float, h: Float };";
+    assert_eq!(
+        eval(&format!(
+            "{} s = Rect {{ h: 2.0, w: 3.0 }}; match s {{ Circle {{ radius }} => radius, Rect {{ w, h }} => w - h }}",
+            shape
+        )),
+        "1.0"
+    );
+    assert_eq!(
+        eval(&format!("{} Rect {{ w: 1.0 }}", shape)),
+        "Runtime Error: missing field `h` of `Rect`"
+    );
+    assert_eq!(
+        eval(&format!("{} Option {{ x: 1 }}", option)),
+        "Runtime Error: `Option` is not a struct"
+    );
+}
+
+#[test]
+fn traits_and_methods() {
+    let point = "Point :: { x: Int, y: Int };
+        trait Describe = { describe | Self -> String; greet = self -> \"hi \" + self.describe; };
+        impl Describe for Point { describe = self -> \"point\"; };
+        impl Point { sum = self -> self.x + self.y; scale = self, k -> Point { x: self.x * k, y: self.y * k }; };
+        p = Point { x: 1, y: 2 };";
+
+    assert_eq!(eval(&format!("{} p.sum", point)), "3");
+    assert_eq!(
+        eval(&format!("{} p.scale {{ 2 }}", point)),
+        "Point { x: 2, y: 4 }"
+    );
+    assert_eq!(
+        eval(&format!("{} p : scale {{ 3 }}", point)),
+        "Point { x: 3, y: 6 }"
+    );
+    assert_eq!(eval(&format!("{} scale(p, 3).sum", point)), "9");
+    assert_eq!(eval(&format!("{} p.greet", point)), "hi point");
+    assert_eq!(eval(&format!("{} p : describe", point)), "point");
+    assert_eq!(eval(&format!("{} p : sum", point)), "3");
+    assert_eq!(
+        eval(&format!(
+            "{} describe = x -> \"bound\"; p : describe",
+            point
+        )),
+        "point"
+    );
+    assert_eq!(
+        eval("impl Int { len = self -> self * 2; }; (4 : len) + (\"ab\" : len)"),
+        "10"
+    );
+    assert_eq!(
+        eval(&format!("{} triple = p.scale; triple(3).x", point)),
+        "3"
+    );
+    assert_eq!(
+        eval("impl Int { double = self -> self * 2; }; x = 20; x.double + 1 : double"),
+        "82"
+    );
+
+    assert_eq!(
+        eval(&format!("{} impl Describe for Int {{ }}", point)),
+        "Runtime Error: missing method `describe` in the impl of `Describe` for `Int`"
+    );
+    assert_eq!(
+        eval(&format!(
+            "{} impl Describe for Point {{ describe = self -> \"\"; }}",
+            point
+        )),
+        "Runtime Error: `Describe` is already implemented for `Point`"
+    );
+    assert_eq!(
+        eval(&format!(
+            "{} impl Describe for Int {{ describe = self -> \"\"; size = self -> 1; }}",
+            point
+        )),
+        "Runtime Error: `size` is not a method of `Describe`"
+    );
+    assert_eq!(
+        eval("impl Show for Int { }"),
+        "Runtime Error: unknown trait `Show`"
+    );
+    assert_eq!(
+        eval(&format!("{} p.norm", point)),
+        "Runtime Error: Point has no field or method `norm`"
+    );
+}
+
+#[test]
+fn derives() {
+    let point = "Point :: { x: Int, y: Int } derive Format, Eq, Ord, Hash, Clone;
+        Shape :: | Circle { radius: Float } | Rect(Float, Float) | Empty derive Eq, Ord, Clone;
+        p = Point { x: 1, y: 2 }; q = Point { x: 1, y: 3 };";
+
+    assert_eq!(eval(&format!("{} p.format", point)), "Point { x: 1, y: 2 }");
+    assert_eq!(eval(&format!("{} p.clone", point)), "Point { x: 1, y: 2 }");
+    assert_eq!(eval(&format!("{} p.eq {{ q }}", point)), "false");
+    assert_eq!(eval(&format!("{} p : eq {{ p.clone }}", point)), "true");
+    assert_eq!(eval(&format!("{} p.cmp {{ q }}", point)), "-1");
+    assert_eq!(eval(&format!("{} q.cmp {{ p }}", point)), "1");
+    assert_eq!(eval(&format!("{} p < q", point)), "true");
+    assert_eq!(eval(&format!("{} p.hash == p.clone.hash", point)), "true");
+    assert_eq!(eval(&format!("{} p.hash == q.hash", point)), "false");
+    assert_eq!(
+        eval(&format!("{} Circle(1.0).cmp {{ Empty }}", point)),
+        "-1"
+    );
+    assert_eq!(
+        eval(&format!(
+            "{} Rect(1.0, 2.0).cmp {{ Rect(1.0, 1.0) }}",
+            point
+        )),
+        "1"
+    );
+    assert_eq!(
+        eval(&format!("{} format {{ p }}", point)),
+        "Point { x: 1, y: 2 }"
+    );
+
+    assert_eq!(
+        eval(&format!("{} Circle(1.0).hash", point)),
+        "Runtime Error: Shape has no field or method `hash`"
+    );
+    assert_eq!(
+        eval("Wrapper :: { x: Int } derive Show;"),
+        "Runtime Error: `Show` cannot be derived"
+    );
+}
+
+#[test]
+fn bounded_generics() {
+    let describe =
+        "trait Describe = { describe | Self -> String; loud = self -> self.describe + \"!\"; };
+        impl Describe for Int { describe = self -> \"int\"; };
+        show<T: Describe> | T -> String; show = x -> x.loud + describe(x);
+        key<T: Hash> | T, Int -> Int; key = x, n -> x.hash - x.hash + n;";
+
+    assert_eq!(eval(&format!("{} show {{ 1 }}", describe)), "int!int");
+    assert_eq!(eval(&format!("{} key {{ \"a\", 2 }}", describe)), "2");
+    assert_eq!(eval(&format!("{} show", describe)), "<function x>");
+    assert_eq!(eval("id<T> | T -> T; id = x -> x; id { 3 }"), "3");
+
+    assert_eq!(
+        eval(&format!("{} show {{ true }}", describe)),
+        "Runtime Error: `Bool` doesn't implement `Describe`"
+    );
+    assert_eq!(
+        eval(&format!("{} key {{ 1.5, 2 }}", describe)),
+        "Runtime Error: `Float` doesn't implement `Hash`"
+    );
+
+    // the bounds of parameters inside the types of the arguments are checked
+    let first = "first<T: Describe> | Option<T> -> String;
+        first = o -> match o { Some { x } => describe(x), None => \"none\" };";
+    assert_eq!(
+        eval(&format!("{} {} first(Some(1))", describe, first)),
+        "int"
+    );
+    assert_eq!(eval(&format!("{} {} first(None)", describe, first)), "none");
+    assert_eq!(
+        eval(&format!("{} {} first(Some('a'))", describe, first)),
+        "Runtime Error: `Char` doesn't implement `Describe`"
+    );
+    assert_eq!(
+        eval(&format!(
+            "{} both<T: Describe> | (T, Int) -> String; both = p -> p.0.describe; both((1.5, 2))",
+            describe
+        )),
+        "Runtime Error: `Float` doesn't implement `Describe`"
+    );
+
+    // a bounded argument only has the methods of its bounds
+    assert_eq!(
+        eval(&format!(
+            "{} impl Int {{ secret = self -> 1; }}; peek<T: Describe> | T -> Int; peek = x -> x.secret; peek {{ 1 }}",
+            describe
+        )),
+        "Runtime Error: Int has no field or method `secret`"
+    );
+}
+
+#[test]
+fn option_and_result() {
+    let half = "half = n -> if n % 2 == 0 { Ok(n / 2) } else { Err(\"odd\") };
+        quarter = n -> { h = half(n)?; half(h) };
+        plus_one = n -> Ok(half(n).try + 1);
+        first = o -> { x = o ? 7; x * 10 };";
+
+    assert_eq!(eval_checked(&format!("{} quarter(8)", half)), "Ok { 2 }");
+    assert_eq!(eval_checked(&format!("{} quarter(6)", half)), "Err { odd }");
+    assert_eq!(
+        eval_checked(&format!("{} plus_one(3)", half)),
+        "Err { odd }"
+    );
+    assert_eq!(
+        eval_checked(&format!("{} first(Some(2)) + first(None)", half)),
+        "27"
+    );
+
+    assert_eq!(
+        eval_checked("f = o -> Some(o? + 1); f(Some(1))"),
+        "Some { 2 }"
+    );
+    assert_eq!(
+        eval_checked("f = o -> { x = o? - 1; Some(x) }; f(Some(5))"),
+        "Some { 4 }"
+    );
+    assert_eq!(
+        eval_checked("f = o -> { x = o? - 1; Some(x) }; f(None)"),
+        "None"
+    );
+    assert_eq!(
+        eval_checked("f = o -> Some(o? * 2); f(Some(3))"),
+        "Some { 6 }"
+    );
+    assert_eq!(eval_checked("f = o -> { x = o ? -1; x }; f(Some(5))"), "5");
+    assert_eq!(eval_checked("f = o -> { x = o ? -1; x }; f(None)"), "-1");
+    assert_eq!(
+        eval_checked("f = o -> { x = o ? (0 - 1); x }; f(None)"),
+        "-1"
+    );
+    assert_eq!(eval_checked("f = o -> { x = o ? { 5 }; x }; f(None)"), "5");
+
+    assert_eq!(eval("Some(2) : map { x -> x + 1 }"), "Some { 3 }");
+    assert_eq!(eval("None : map { x -> x + 1 } : unwrap_or { 0 }"), "0");
+    assert_eq!(eval("Some(4).and_then { x -> None }.unwrap_or { 1 }"), "1");
+    assert_eq!(
+        eval("Ok(1) : and_then { x -> Err('e') } : map { x -> x * 2 }"),
+        "Err { e }"
+    );
+    assert_eq!(eval("Err(1) : unwrap_or { 5 }"), "5");
+    assert_eq!(eval("Some(1) == Some(1)"), "true");
+    assert_eq!(eval("\"ab\" : map { c -> c }"), "[a, b]");
+
+    assert_eq!(
+        eval("f = n -> n.try + 1; f(2)"),
+        "Runtime Error: `?` expects an Option or a Result, found Int"
+    );
+}
@@ -1,6 +1,6 @@
-use morph::{Lexer, TokenKind, TokenKind::*};
+use morph::{parser::Span, Lexer, Token, TokenKind, TokenKind::*, TokenStream, KEYWORDS};
 
-fn lex<'a>(input: &'a str) -> Vec<TokenKind> {
+fn lex(input: &str) -> Vec<TokenKind> {
     Lexer::new(input).map(|token| token.kind).collect()
 }
 
@@ -24,6 +24,47 @@ fn keywords() {
     assert_eq!(lex("const"), vec![Const]);
     assert_eq!(lex("fn"), vec![Function]);
     assert_eq!(lex("return"), vec![Return]);
+    assert_eq!(lex("select"), vec![Select]);
+    assert_eq!(lex("in"), vec![In]);
+    assert_eq!(lex("ref"), vec![Ref]);
+    assert_eq!(lex("Self"), vec![SelfType]);
+    assert_eq!(lex("self"), vec![SelfValue]);
+}
+
+#[test]
+fn keyword_table() {
+    for (keyword, kind) in KEYWORDS {
+        assert_eq!(lex(keyword), vec![kind.clone()], "`{}`", keyword);
+        assert_eq!(TokenKind::keyword(keyword), Some(kind.clone()));
+
+        // keywords are whole words
+        for word in [
+            format!("{}_", keyword),
+            format!("_{}", keyword),
+            format!("{}1", keyword),
+        ] {
+            assert_eq!(lex(&word), vec![Identifier], "`{}`", word);
+        }
+        assert_eq!(lex(&keyword.to_uppercase()), vec![Identifier]);
+    }
+
+    let mut words: Vec<_> = KEYWORDS.iter().map(|(word, _)| *word).collect();
+    words.sort();
+    words.dedup();
+    assert_eq!(words.len(), KEYWORDS.len(), "duplicate keyword");
+
+    // every kind that only a keyword produces is in the table
+    let kinds = [
+        Bool, If, Else, While, For, In, Loop, Match, Select, Break, Continue, Spawn, Type, Impl,
+        Trait, Where, As, Derive, SelfType, SelfValue, Function, Return, Mut, Ref, Use, Pub, Const,
+    ];
+    for kind in kinds {
+        assert!(
+            KEYWORDS.iter().any(|(_, keyword)| *keyword == kind),
+            "{:?} has no keyword",
+            kind
+        );
+    }
 }
 
 #[test]
@@ -213,3 +254,68 @@ fn complex_expressions() {
         ]
     );
 }
+
+// xorshift, so that failures are reproducible without a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// pieces that change how their neighbours lex when spliced in anywhere
+const FRAGMENTS: &[&str] = &[
+    "x", "_", "if", "1", "0x", ".", "5", "e", "-", "--", "---", "/*", "*/", "/", "*", "\"", "'",
+    "r#\"", "\"#", "{", "}", "\\", "\n", " ", "é", "=", ">", "\\u{48}", "i32", "..", ";",
+];
+
+fn fragments(rng: &mut Rng, count: usize) -> String {
+    (0..count)
+        .map(|_| FRAGMENTS[rng.below(FRAGMENTS.len())])
+        .collect()
+}
+
+// a random byte offset in `source` that is on a char boundary
+fn boundary(rng: &mut Rng, source: &str) -> usize {
+    let mut offset = rng.below(source.len() + 1);
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[test]
+fn incremental_relex_matches_full_relex() {
+    let mut rng = Rng(0x5eed_1e55_f00d_cafe);
+
+    for case in 0..2000 {
+        let len = rng.below(40);
+        let mut stream = TokenStream::new(fragments(&mut rng, len));
+
+        for _ in 0..8 {
+            let source = stream.source().to_owned();
+            let (a, b) = (boundary(&mut rng, &source), boundary(&mut rng, &source));
+            let range = Span::new(a.min(b), a.max(b));
+            let len = rng.below(4);
+            let text = fragments(&mut rng, len);
+
+            stream.edit(range, &text);
+
+            let incremental: Vec<Token> = stream.tokens().collect();
+            let full: Vec<Token> = Lexer::new(stream.source()).collect();
+            assert_eq!(
+                incremental, full,
+                "case {}: replacing {:?} of {:?} with {:?}",
+                case, range, source, text
+            );
+        }
+    }
+}
@@ -0,0 +1,892 @@
+use morph::parser::flat_ast::{self, Expression, ExpressionRef};
+use morph::parser::{
+    Ast, Expr, ExprKind, FlatParser, Function, Parser, Signature, Span, Stmt, StmtKind,
+};
+
+fn parse(input: &str) -> Ast {
+    let (ast, diagnostics) = Parser::new(input).parse();
+    assert!(diagnostics.is_empty(), "failed to parse: {:?}", diagnostics);
+    ast
+}
+
+fn parse_flat(input: &str) -> flat_ast::Ast<'_> {
+    let (ast, diagnostics) = FlatParser::new(input).parse();
+    assert!(diagnostics.is_empty(), "failed to parse: {:?}", diagnostics);
+    ast
+}
+
+fn parse_errs(input: &str) -> Vec<String> {
+    let (_, diagnostics) = Parser::new(input).parse();
+    diagnostics.into_iter().map(|err| err.message).collect()
+}
+
+// the first syntax error in `input`
+fn parse_err(input: &str) -> String {
+    match Parser::new(input).parse() {
+        (ast, diagnostics) if diagnostics.is_empty() => {
+            panic!("expected a parse error, found {:?}", ast)
+        }
+        (_, mut diagnostics) => diagnostics.remove(0).message,
+    }
+}
+
+#[test]
+fn missing_operand() {
+    assert_eq!(parse_err("1 +"), "expected expression");
+    assert_eq!(parse_err("x = * 2;"), "expected expression");
+}
+
+#[test]
+fn unbalanced_parens() {
+    assert_eq!(parse_err("(1 + 2"), "expected CLOSE_PAREN");
+    assert_eq!(parse_err("x = (1 + 2;"), "expected CLOSE_PAREN");
+}
+
+#[test]
+fn malformed_numbers() {
+    assert_eq!(parse_err("x = 0x;"), "number literal has no digits");
+    assert_eq!(parse_err("x = 1e+;"), "number literal has no digits");
+    assert_eq!(
+        parse_err("x = 0b102;"),
+        "invalid digit for the base of the number literal"
+    );
+    assert_eq!(parse_err("x = 42px;"), "invalid suffix on number literal");
+    assert_eq!(parse_err("1.2.3"), "expected SEMICOLON");
+}
+
+#[test]
+fn missing_semicolon() {
+    assert_eq!(parse_err("x = 1 y = 2;"), "expected SEMICOLON");
+}
+
+fn parse_binding(input: &str) -> (String, Expr) {
+    let ast = parse(input);
+
+    match ast.stmts().as_slice() {
+        [Stmt {
+            kind: StmtKind::Binding(ident, expr),
+            ..
+        }] => (ident.clone(), expr.clone()),
+        stmts => panic!("expected a single binding, found {:?}", stmts),
+    }
+}
+
+fn parse_function(input: &str) -> Function {
+    match parse_binding(input).1.kind {
+        ExprKind::Function(function) => *function,
+        expr => panic!("expected a function, found {:?}", expr),
+    }
+}
+
+fn args(function: &Function) -> Vec<(&str, bool)> {
+    function
+        .args
+        .iter()
+        .map(|arg| (arg.ident.as_str(), arg.mutable))
+        .collect()
+}
+
+#[test]
+fn functions() {
+    let add = parse_function("add = a, b -> { a + b };");
+    assert_eq!(args(&add), vec![("a", false), ("b", false)]);
+    assert!(matches!(add.body.kind, ExprKind::Scope(ref stmts) if stmts.len() == 1));
+
+    let main = parse_function("main = -> { x = 1; x };");
+    assert!(main.args.is_empty());
+    assert!(matches!(main.body.kind, ExprKind::Scope(ref stmts) if stmts.len() == 2));
+
+    let main = parse_function("main = () -> {};");
+    assert!(main.args.is_empty());
+
+    let identity = parse_function("identity = x -> x;");
+    assert_eq!(args(&identity), vec![("x", false)]);
+    assert!(matches!(identity.body.kind, ExprKind::Ident(ref ident) if ident == "x"));
+
+    let swap = parse_function("swap = (x, y) -> y;");
+    assert_eq!(args(&swap), vec![("x", false), ("y", false)]);
+}
+
+#[test]
+fn mutable_arguments() {
+    let increment = parse_function("increment = i mut -> { i += 1; };");
+    assert_eq!(args(&increment), vec![("i", true)]);
+
+    let worker = parse_function("worker = (state mut, channel) -> {};");
+    assert_eq!(args(&worker), vec![("state", true), ("channel", false)]);
+}
+
+#[test]
+fn curried_functions() {
+    let add = parse_function("add = a -> b -> a + b;");
+    assert_eq!(args(&add), vec![("a", false)]);
+    assert!(matches!(add.body.kind, ExprKind::Function(ref inner) if inner.args.len() == 1));
+}
+
+#[test]
+fn grouping_is_not_a_function() {
+    let (_, expr) = parse_binding("x = (a);");
+    assert!(matches!(expr.kind, ExprKind::Ident(_)));
+}
+
+#[test]
+fn unterminated_function() {
+    assert_eq!(parse_err("f = a, b -> "), "expected expression");
+    assert_eq!(parse_err("f = (a, b -> a;"), "expected CLOSE_PAREN");
+    assert_eq!(parse_err("f = -> { a"), "expected CLOSE_BRACE");
+}
+
+#[test]
+fn brace_calls() {
+    let (_, expr) = parse_binding("x = print { a, b + 1, };");
+    let call = match expr.kind {
+        ExprKind::Call(call) => call,
+        expr => panic!("expected a call, found {:?}", expr),
+    };
+    assert!(matches!(call.callee.kind, ExprKind::Ident(ref ident) if ident == "print"));
+    assert_eq!(call.args.len(), 2);
+
+    let (_, expr) = parse_binding("x = f { g { y } };");
+    assert!(
+        matches!(expr.kind, ExprKind::Call(ref call) if matches!(call.args[0].kind, ExprKind::Call(_)))
+    );
+}
+
+#[test]
+fn pipelines_desugar_into_calls() {
+    let (_, expr) = parse_binding("x = a : f { b } : g;");
+
+    let outer = match expr.kind {
+        ExprKind::Call(call) => call,
+        expr => panic!("expected a call, found {:?}", expr),
+    };
+    assert!(matches!(outer.callee.kind, ExprKind::Ident(ref ident) if ident == "g"));
+    assert_eq!(outer.args.len(), 1);
+
+    let inner = match &outer.args[0].kind {
+        ExprKind::Call(call) => call,
+        expr => panic!("expected a call, found {:?}", expr),
+    };
+    assert!(matches!(inner.callee.kind, ExprKind::Ident(ref ident) if ident == "f"));
+    assert!(
+        matches!(inner.args.as_slice(), [a, b] if matches!((&a.kind, &b.kind), (ExprKind::Ident(a), ExprKind::Ident(b)) if a == "a" && b == "b"))
+    );
+}
+
+#[test]
+fn unterminated_call() {
+    assert_eq!(parse_err("f { a, b"), "expected CLOSE_BRACE");
+    assert_eq!(parse_err("f(a, b"), "expected CLOSE_PAREN");
+}
+
+fn parse_signature(input: &str) -> Signature {
+    let ast = parse(input);
+
+    match ast.stmts().as_slice() {
+        [Stmt {
+            kind: StmtKind::Signature(signature),
+            ..
+        }] => *signature.clone(),
+        stmts => panic!("expected a single signature, found {:?}", stmts),
+    }
+}
+
+fn signature(input: &str) -> String {
+    parse_signature(input).to_string()
+}
+
+#[test]
+fn type_signatures() {
+    assert_eq!(signature("s | String;"), "s | String");
+    assert_eq!(signature("main | () -> Never;"), "main | () -> Never");
+    assert_eq!(signature("add | Int, Int -> Int;"), "add | Int, Int -> Int");
+    assert_eq!(
+        signature("whitespace_char | String -> ParseResult<Char>;"),
+        "whitespace_char | String -> ParseResult<Char>"
+    );
+    assert_eq!(
+        signature("format | Self, format.Formatter -> format.Result;"),
+        "format | Self, format.Formatter -> format.Result"
+    );
+}
+
+#[test]
+fn compound_types() {
+    assert_eq!(signature("p | (Int, Float);"), "p | (Int, Float)");
+    assert_eq!(
+        signature("swap | (a, b) -> (b, a);"),
+        "swap | (a, b) -> (b, a)"
+    );
+    assert_eq!(
+        signature("apply | (a -> b), a -> b;"),
+        "apply | (a -> b), a -> b"
+    );
+    assert_eq!(
+        signature("add | Int -> Int -> Int;"),
+        "add | Int -> Int -> Int"
+    );
+    assert_eq!(
+        signature("next | Option<Node<T>>;"),
+        "next | Option<Node<T>>"
+    );
+    assert_eq!(
+        signature("res | Result<Option<Int>, String>;"),
+        "res | Result<Option<Int>, String>"
+    );
+}
+
+#[test]
+fn generic_bounds() {
+    let left =
+        parse_signature("left | P1: Parser<R1>, P2: Parser<R2>, R1, R2 -> P1, P2 -> Parser<R1>;");
+
+    assert_eq!(left.generics.len(), 4);
+    assert_eq!(left.generics[0].to_string(), "P1: Parser<R1>");
+    assert_eq!(left.generics[2].to_string(), "R1");
+    assert_eq!(left.ty.to_string(), "P1, P2 -> Parser<R1>");
+
+    let show = parse_signature("show | T: Format + Eq -> T -> String;");
+    assert_eq!(show.to_string(), "show | T: Format + Eq -> T -> String");
+
+    let id = parse_signature("id<T> | T -> T; id = x -> x;");
+    assert_eq!(id.to_string(), "id<T> | T -> T");
+    assert_eq!(
+        signature("max<T: Ord> | T, T -> T;"),
+        "max | T: Ord -> T, T -> T"
+    );
+    assert_eq!(
+        signature("pick<A> | B: Eq -> A, B -> A;"),
+        "pick | A, B: Eq -> A, B -> A"
+    );
+
+    assert_eq!(
+        lisp("max<T: Ord> | T, T -> T;"),
+        "(sig max (forall ((T Ord)) (-> (T T) T)))"
+    );
+    assert_eq!(
+        lisp("impl Point<T: Add> { sum = self -> self.x + self.y; }"),
+        "(impl (generics (T Add)) (Point T) (bind sum (fn (self) (+ (. self x) (. self y)))))"
+    );
+    assert_eq!(
+        lisp("impl Format for Pair<A: Format, B: Format> { format = self -> \"\"; }"),
+        "(impl (generics (A Format) (B Format)) (for Format (Pair A B)) (bind format (fn (self) \"\")))"
+    );
+    assert_eq!(lisp("a < b | c"), "(< a (| b c))");
+}
+
+#[test]
+fn try_propagation() {
+    assert_eq!(
+        lisp("x = divide { 10, 2 }.try;"),
+        "(bind x (? (divide 10 2)))"
+    );
+    assert_eq!(
+        lisp("expr = self : parse_expr ?;"),
+        "(bind expr (? (parse_expr self)))"
+    );
+    assert_eq!(
+        lisp("token = self.peek ? Ok { token };"),
+        "(bind token (? (. self peek) (Ok token)))"
+    );
+    assert_eq!(lisp("a? : f ? b : g"), "(g (? (f (? a)) b))");
+    assert_eq!(lisp("f(a?, b ? c)"), "(f (? a) (? b c))");
+    assert_eq!(lisp("if a? { b }"), "(if (? a) (do b))");
+    assert_eq!(lisp("o? - 1"), "(- (? o) 1)");
+    assert_eq!(lisp("Some(o? * 2)"), "(Some (* (? o) 2))");
+    assert_eq!(lisp("o.try?.x ? 0"), "(? (. (? (? o)) x) 0)");
+    assert_eq!(lisp("o ? -1"), "(? o (- 1))");
+    assert_eq!(lisp("o ? (0 - 1) + 2"), "(+ (? o (- 0 1)) 2)");
+    assert_eq!(lisp("o ? { 5 }"), "(? o (do 5))");
+    assert_eq!(lisp("o ? !done"), "(? o (! done))");
+
+    assert_flat_matches("half = n -> { x = n.try; y = n ? Err { x }; n?; Ok(x + y) }");
+    assert_flat_matches("self.peek ? Ok { 1 } : map { x -> x }; while a.b? { }");
+    assert_flat_matches("x = o ? -1; y = o ? (0 - 1); z = o ? { 5 }; o? - 1");
+}
+
+#[test]
+fn signatures_attach_to_bindings() {
+    let add = parse_signature("add | Int, Int -> Int; add = a, b -> a + b;");
+    assert!(
+        matches!(add.binding, Some(Stmt { kind: StmtKind::Binding(ref ident, _), .. }) if ident == "add")
+    );
+
+    let ast = parse("a | Int; b = 1;");
+    assert!(
+        matches!(ast.stmts()[0].kind, StmtKind::Signature(ref signature) if signature.binding.is_none())
+    );
+    assert!(matches!(ast.stmts()[1].kind, StmtKind::Binding(..)));
+}
+
+#[test]
+fn malformed_signatures() {
+    assert_eq!(parse_err("x | ;"), "expected type");
+    assert_eq!(parse_err("x | Option<Int;"), "expected GREATER_THAN");
+    assert_eq!(
+        parse_err("x | Option<T>: Eq -> T;"),
+        "expected type parameter"
+    );
+    assert_eq!(parse_err("x | Int Int;"), "expected SEMICOLON");
+}
+
+// the flat parser agrees with the boxed one, and both conversions round trip
+fn assert_flat_matches(input: &str) {
+    let boxed = parse(input);
+    let flat = parse_flat(input);
+
+    assert_eq!(Ast::from(&flat), boxed, "{}", input);
+    assert_eq!(Ast::from(&flat_ast::Ast::from(&boxed)), boxed, "{}", input);
+}
+
+#[test]
+fn flat_ast_matches_boxed_ast() {
+    assert_flat_matches("x = 1 + 2 * 3; x -= 1;");
+    assert_flat_matches("-2 ** 2 ** 3 | 1 & ~3 << 2");
+    assert_flat_matches("add = a, b -> a + b; add { 1, 2 }; add(3, 4)");
+    assert_flat_matches("f = (a, b mut) -> { b += a; return b; };; f { 1, 2 }");
+    assert_flat_matches("numbers : filter { n -> n % 2 == 0 } : map { n -> n * n } : sum");
+    assert_flat_matches("0..10; 1..=n");
+    assert_flat_matches("print(\"hello\", 'c', 1.5, true, ())");
+    assert_flat_matches("run = () -> {}; run { }");
+    assert_flat_matches("add | Int, Int -> Int; add = a, b -> a + b;");
+    assert_flat_matches("max<T: Ord> | T, T -> T; max = a, b -> if a > b { a } else { b };");
+    assert_flat_matches("impl Point<T: Add> { sum = self -> self.x + self.y; }");
+    assert_flat_matches("parse | P: Parser<R> + Clone, R -> P -> Option<Vec<R>>;");
+    assert_flat_matches("write | (format.Formatter, String), () -> Never;");
+    assert_flat_matches(
+        "'outer: for n in 0..=top { while Some { x, _ } = f(n) { continue 'outer; }; }",
+    );
+    assert_flat_matches("x = loop { break 1; }; loop { while n > 0 { n -= 1; break; } }");
+    assert_flat_matches("if a { 1 } else if b { 2 } else if c { 3 }; if x > 0 { y } else { z }");
+    assert_flat_matches(
+        "x = (1, a + b,); match x { (0, _) | (_, 0) => y, n @ 1..=9 if ok => { n } _ => z }",
+    );
+    assert_flat_matches(
+        "match p { Point { x = px, y } => px, Some(-1) => 'a'..'z', () => (a, b) }",
+    );
+    assert_flat_matches(
+        "Point<T: Format> :: { x: T, y: T } derive Format; p = Point { x: 0, y: 1 };",
+    );
+    assert_flat_matches("Option :: T -> | Some { T } | None; Pair :: (Int, Float); t.0.1 + p.x");
+    assert_flat_matches(
+        "trait Describe = { describe | Self -> String; greet = self -> self.describe; };",
+    );
+    assert_flat_matches("impl<T> Format for Node<T> where T: Format { format = self, f -> f; }");
+    assert_flat_matches(
+        "--- doc\nimpl ToString for Int {\n    --- method doc\n    to_string | Self -> String;\n    to_string = self -> format(self);\n}",
+    );
+}
+
+#[test]
+fn flat_ast_borrows_from_source() {
+    let input = "greeting = \"hello\";";
+    let ast = parse_flat(input);
+    let binding = ast.statements().next().unwrap().expr();
+
+    let Expression::Binding { ident, value } = ast.get_expression(binding.root()) else {
+        panic!("expected a binding");
+    };
+    assert_eq!(ident.as_ptr(), input.as_ptr());
+
+    let Expression::Str(literal) = ast.get_expression(value.root()) else {
+        panic!("expected a string");
+    };
+    assert_eq!(*literal, "hello");
+    assert_eq!(literal.as_ptr(), input[12..].as_ptr());
+}
+
+#[test]
+fn flat_ast_layout() {
+    let ast = parse_flat("f { 1, g(2) }");
+    let call = ast.statements().next().unwrap().expr();
+    assert_eq!(call, ExpressionRef { index: 0, len: 6 });
+
+    let Expression::Call { callee, args } = ast.get_expression(call.root()) else {
+        panic!("expected a call");
+    };
+    assert_eq!(*callee, ExpressionRef { index: 0, len: 1 });
+    assert_eq!(
+        ast.children(*args),
+        vec![
+            ExpressionRef { index: 1, len: 1 },
+            ExpressionRef { index: 2, len: 3 },
+        ]
+    );
+}
+
+fn lisp(input: &str) -> String {
+    let ast = parse(input);
+    flat_ast::Ast::from(&ast).to_string()
+}
+
+#[test]
+fn s_expressions() {
+    assert_eq!(
+        lisp("add = a, b -> a + b;"),
+        "(bind add (fn (a b) (+ a b)))"
+    );
+    assert_eq!(lisp("1 + 2 * 3 ** -x"), "(+ 1 (* 2 (** 3 (- x))))");
+    assert_eq!(lisp("x += 1; return x"), "(+= x 1)\n(return x)");
+    assert_eq!(
+        lisp("f = (a, b mut) -> { b <<= a; b }"),
+        "(bind f (fn (a (mut b)) (do (<<= b a) b)))"
+    );
+    assert_eq!(
+        lisp("run = -> print(\"hi\", 'c', ())"),
+        "(bind run (fn () (print \"hi\" 'c' ())))"
+    );
+    assert_eq!(
+        lisp("0..10 : filter { n -> n % 2 == 0 } : sum"),
+        "(sum (filter (.. 0 10) (fn (n) (== (% n 2) 0))))"
+    );
+    assert_eq!(lisp("{}; 1..=n"), "(do)\n(..= 1 n)");
+}
+
+#[test]
+fn loops() {
+    assert_eq!(
+        lisp("for num in bottom..=top { sum += num; }"),
+        "(for num (..= bottom top) (do (+= sum num)))"
+    );
+    assert_eq!(
+        lisp("while Some { data } = receive(channel) { print(data) }"),
+        "(while (= (Some data) (receive channel)) (do (print data)))"
+    );
+    assert_eq!(
+        lisp("'outer: loop { while n < 10 { break 'outer n; }; continue }"),
+        "(loop 'outer (do (while (< n 10) (do (break 'outer n))) (continue)))"
+    );
+    assert_eq!(
+        lisp("for _ in xs { break }; for 0 in xs { }"),
+        "(for _ xs (do (break)))\n(for 0 xs (do))"
+    );
+}
+
+#[test]
+fn conditionals() {
+    assert_eq!(lisp("if x > 0 { 1 }"), "(if (> x 0) (do 1))");
+    assert_eq!(
+        lisp("y = if a { 1 } else if b { 2 } else { 3 };"),
+        "(bind y (if a (do 1) (if b (do 2) (do 3))))"
+    );
+    assert_eq!(lisp("if f { x }; { g { x } }"), "(if f (do x))\n(do (g x))");
+    assert_eq!(parse_err("if f { x } { g { x } }"), "expected SEMICOLON");
+    assert_eq!(parse_err("if x { 1 } else 2"), "expected OPEN_BRACE");
+    assert_eq!(parse_err("if { 1 }"), "expected OPEN_BRACE");
+}
+
+#[test]
+fn loop_heads_are_not_brace_calls() {
+    assert_eq!(lisp("for x in f { g { x } }"), "(for x f (do (g x)))");
+    assert_eq!(lisp("while (f { x }) { }"), "(while (f x) (do))");
+}
+
+#[test]
+fn matches() {
+    assert_eq!(
+        lisp("match x { 0 => a, 1 | 2 => b, n if n < 0 => c, _ => d }"),
+        "(match x (=> 0 a) (=> (| 1 2) b) (=> n (when (< n 0)) c) (=> _ d))"
+    );
+    assert_eq!(
+        lisp("match (x, y) { (0, _) => { 1 } n @ -9..=-1 => 2, Some { val } => val }"),
+        "(match (tuple x y) (=> (tuple 0 _) (do 1)) (=> (@ n (..= (- 9) (- 1))) 2) (=> (Some val) val))"
+    );
+    assert_eq!(
+        lisp("match p { Point { x = 0, y } => y, Err(_) => 0, None => (1,) }"),
+        "(match p (=> (Point (= x 0) y) y) (=> (Err _) 0) (=> (None) (tuple 1)))"
+    );
+    assert_eq!(lisp("match (f { x }) { _ => 1 }"), "(match (f x) (=> _ 1))");
+}
+
+#[test]
+fn type_declarations() {
+    assert_eq!(
+        lisp("Point<T: Format> :: { x: T, y: T } derive Format;"),
+        "(struct (Point (T Format)) (x T) (y T) (derive Format))"
+    );
+    assert_eq!(
+        lisp("Option :: T: Format -> | Some { T } | None;"),
+        "(enum (Option (T Format)) (Some T) (None))"
+    );
+    assert_eq!(
+        lisp("Shape :: | Circle { radius: Float } | Rect(Float, Float) derive Eq, Clone;"),
+        "(enum Shape (Circle (radius Float)) (Rect Float Float) (derive Eq Clone))"
+    );
+    assert_eq!(lisp("Pair<A, B> :: (A, B);"), "(struct (Pair A B) A B)");
+}
+
+#[test]
+fn structs_and_fields() {
+    assert_eq!(
+        lisp("p = Point { x: 0, y: a + 1 };"),
+        "(bind p (Point (= x 0) (= y (+ a 1))))"
+    );
+    assert_eq!(lisp("point.x + points.0"), "(+ (. point x) (. points 0))");
+    assert_eq!(lisp("t.0.1"), "(. (. t 0) 1)");
+    assert_eq!(lisp("f(x).y"), "(. (f x) y)");
+    assert_eq!(lisp("if p.x { 1 }"), "(if (. p x) (do 1))");
+}
+
+#[test]
+fn type_declaration_errors() {
+    assert_eq!(parse_err("Point :: 1;"), "expected `{`, `(` or `|`");
+    assert_eq!(parse_err("Point :: { x: };"), "expected type");
+    assert_eq!(parse_err("t.1e3"), "invalid tuple field `1e3`");
+    assert_eq!(parse_err("p = Point { x: 0, y };"), "expected COLON");
+}
+
+#[test]
+fn traits_and_impls() {
+    assert_eq!(
+        lisp("trait ToString = { to_string | Self -> String; };"),
+        "(trait ToString (sig to_string (-> (Self) String)))"
+    );
+    assert_eq!(
+        lisp("trait Describe = { describe | Self -> String; greet = self -> self.describe; };"),
+        "(trait Describe (sig describe (-> (Self) String)) (bind greet (fn (self) (. self describe))))"
+    );
+    assert_eq!(
+        lisp("trait Parser<R> = { parse | Self, String -> Option<R>; };"),
+        "(trait (Parser R) (sig parse (-> (Self String) (Option R))))"
+    );
+    assert_eq!(
+        lisp("impl Point { sum = self -> self.x + self.y; }"),
+        "(impl Point (bind sum (fn (self) (+ (. self x) (. self y)))))"
+    );
+    assert_eq!(
+        lisp("impl<T> Format for Node<T> where T: Format { format = self, f -> f : write { self.value }; }"),
+        "(impl (generics T) (for Format (Node T)) (where (T Format)) (bind format (fn (self f) (write f (. self value)))))"
+    );
+}
+
+#[test]
+fn trait_and_impl_errors() {
+    assert_eq!(
+        parse_err("impl Point { sum | Self -> Int; }"),
+        "method `sum` has no body"
+    );
+    assert_eq!(parse_err("trait Show = { 1 };"), "expected method");
+    assert_eq!(parse_err("trait Show { }"), "expected ASSIGN");
+    assert_eq!(parse_err("impl Show for { }"), "expected type");
+    assert_eq!(parse_err("impl Point where { }"), "expected IDENTIFIER");
+}
+
+#[test]
+fn match_errors() {
+    assert_eq!(parse_err("match x { 1 2 }"), "expected ARROW_RIGHT");
+    assert_eq!(
+        parse_err("match x { 1 => a b => c }"),
+        "expected CLOSE_BRACE"
+    );
+    assert_eq!(parse_err("match x { x + 1 => a }"), "expected ARROW_RIGHT");
+    assert_eq!(parse_err("match x { [] => a }"), "expected pattern");
+    assert_eq!(
+        parse_err("match f { x } { _ => 1 }"),
+        "expected ARROW_RIGHT"
+    );
+}
+
+#[test]
+fn loop_errors() {
+    assert_eq!(parse_err("break;"), "`break` outside of a loop");
+    assert_eq!(
+        parse_err("loop { f = -> { continue }; }"),
+        "`continue` outside of a loop"
+    );
+    assert_eq!(
+        parse_err("'a: loop { break 'b; }"),
+        "use of undeclared label `'b`"
+    );
+    assert_eq!(
+        parse_err("for x in xs { break x; }"),
+        "`break` with a value outside of `loop`"
+    );
+    assert_eq!(parse_err("for x xs { }"), "expected IN");
+    assert_eq!(parse_err("'a: f()"), "expected `loop`, `while` or `for`");
+}
+
+#[test]
+fn type_s_expressions() {
+    assert_eq!(
+        lisp("add | Int, Int -> Int; add = a, b -> a + b;"),
+        "(sig add (-> (Int Int) Int) (bind add (fn (a b) (+ a b))))"
+    );
+    assert_eq!(lisp("run | () -> ();"), "(sig run (-> () ()))");
+    assert_eq!(
+        lisp("pair | (Int, Option<Node<T>>), Float;"),
+        "(sig pair (tuple (tuple Int (Option (Node T))) Float))"
+    );
+    assert_eq!(
+        lisp("parse | P: Parser<R> + Clone, R -> P -> Vec<R>;"),
+        "(sig parse (forall ((P (Parser R) Clone) R) (-> (P) (Vec R))))"
+    );
+}
+
+fn source_of(input: &str, span: Span) -> &str {
+    &input[span.start..span.end]
+}
+
+#[test]
+fn spans() {
+    let input = "total = add { 1, -n } * y;\nreturn total";
+    let ast = parse(input);
+
+    assert_eq!(
+        source_of(input, ast.stmts()[0].span),
+        "total = add { 1, -n } * y"
+    );
+    assert_eq!(source_of(input, ast.stmts()[1].span), "return total");
+
+    let StmtKind::Binding(_, value) = &ast.stmts()[0].kind else {
+        panic!("expected a binding");
+    };
+    assert_eq!(source_of(input, value.span), "add { 1, -n } * y");
+
+    let ExprKind::Binary(binary) = &value.kind else {
+        panic!("expected a binary expression");
+    };
+    assert_eq!(source_of(input, binary.left_operand.span), "add { 1, -n }");
+    assert_eq!(source_of(input, binary.right_operand.span), "y");
+
+    let ExprKind::Call(call) = &binary.left_operand.kind else {
+        panic!("expected a call");
+    };
+    assert_eq!(source_of(input, call.args[1].span), "-n");
+
+    let function = parse_function("f = (a, b mut) -> { b };");
+    let spans: Vec<_> = function.args.iter().map(|arg| arg.span).collect();
+    assert_eq!(spans, vec![Span::new(5, 6), Span::new(8, 13)]);
+}
+
+#[test]
+fn syntax_error_diagnostics() {
+    let input = "x = 1 y = 2;";
+    let (_, mut diagnostics) = Parser::new(input).parse();
+    let err = diagnostics.remove(0);
+    assert_eq!(
+        err.to_string(),
+        "Syntax Error: expected SEMICOLON [syntax-error]"
+    );
+    assert_eq!(
+        err.render("main.mo", input),
+        "\
+Syntax Error: expected SEMICOLON [syntax-error]
+ --> main.mo:1:7
+  |
+1 | x = 1 y = 2;
+  |       ^ found `y`
+"
+    );
+
+    let input = "add = a, b -> {\n    a + b\n";
+    let (_, mut diagnostics) = Parser::new(input).parse();
+    let err = diagnostics.remove(0);
+    assert_eq!(
+        err.render("main.mo", input),
+        "\
+Syntax Error: expected CLOSE_BRACE [syntax-error]
+ --> main.mo:2:10
+  |
+2 |     a + b
+  |          ^ found end of input
+"
+    );
+}
+
+#[test]
+fn recovers_at_statement_boundaries() {
+    let input = "x = ;\ny = 1 +;\nz = 3;";
+    let (ast, diagnostics) = Parser::new(input).parse();
+
+    let errors: Vec<_> = diagnostics.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(errors, vec!["expected expression", "expected expression"]);
+
+    let kinds: Vec<_> = ast.stmts().iter().map(|stmt| &stmt.kind).collect();
+    assert!(matches!(
+        kinds[..],
+        [StmtKind::Error, StmtKind::Error, StmtKind::Binding(..)]
+    ));
+    assert_eq!(source_of(input, ast.stmts()[1].span), "y = 1 +");
+}
+
+#[test]
+fn recovers_inside_blocks() {
+    let (ast, diagnostics) = Parser::new("f = () -> { a = ; b = 2 }; g = 1;").parse();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(ast.stmts().len(), 2);
+
+    let StmtKind::Binding(_, value) = &ast.stmts()[0].kind else {
+        panic!("expected a binding");
+    };
+    let ExprKind::Function(function) = &value.kind else {
+        panic!("expected a function");
+    };
+    let ExprKind::Scope(stmts) = &function.body.kind else {
+        panic!("expected a block");
+    };
+    assert!(matches!(
+        stmts[..],
+        [
+            Stmt {
+                kind: StmtKind::Error,
+                ..
+            },
+            Stmt {
+                kind: StmtKind::Binding(..),
+                ..
+            }
+        ]
+    ));
+}
+
+#[test]
+fn skips_nested_delimiters() {
+    assert_eq!(
+        parse_errs("x = f { 1 + ; 2 }; y = 3;"),
+        vec!["expected expression"]
+    );
+    assert_eq!(parse_errs("x = (1 +; y = 3;"), vec!["expected expression"]);
+    assert_eq!(
+        parse_errs("} x = 1; }"),
+        vec!["unexpected CLOSE_BRACE", "unexpected CLOSE_BRACE"]
+    );
+    assert_eq!(
+        parse_errs("f = () -> { x = 1 +"),
+        vec!["expected expression", "expected CLOSE_BRACE"]
+    );
+}
+
+#[test]
+fn unsupported_syntax_does_not_panic() {
+    assert_eq!(
+        parse_errs("x = [1, 2]; y = 1;"),
+        vec!["expected expression"]
+    );
+    assert_eq!(parse_errs("x = 1 ] 2;"), vec!["expected SEMICOLON"]);
+}
+
+#[test]
+fn flat_parser_recovers() {
+    let input = "x = ; y = f { 1, + }; z = 3;";
+    let (boxed, boxed_diagnostics) = Parser::new(input).parse();
+    let (flat, flat_diagnostics) = FlatParser::new(input).parse();
+
+    assert_eq!(flat_diagnostics, boxed_diagnostics);
+    assert_eq!(Ast::from(&flat), boxed);
+    assert_eq!(flat.to_string(), "(error)\n(error)\n(bind z 3)");
+}
+
+#[test]
+fn comments_are_skipped() {
+    let input = "x = 1; -- one\n/* block /* nested */ */ y = x /* two */ + 2; -- trailing";
+    assert_eq!(lisp(input), lisp("x = 1; y = x + 2;"));
+
+    let ast = parse(input);
+    assert_eq!(ast.stmts().len(), 2);
+    assert_eq!(ast.trivia.len(), 4);
+    assert!(ast.trivia.iter().all(|trivia| !trivia.doc));
+
+    assert_eq!(
+        parse_errs("x = 1 /* never closed"),
+        vec!["expected SEMICOLON"]
+    );
+}
+
+#[test]
+fn doc_comments_attach_to_bindings() {
+    let input = "\
+--- Adds two numbers.
+---
+--- Wraps on overflow.
+add | Int, Int -> Int;
+add = a, b -> a + b;
+
+-- not documentation
+x = 1;
+--- the answer
+answer = 42;
+--- dangling";
+    let ast = parse(input);
+
+    assert_eq!(
+        ast.stmts()[0].docs,
+        vec!["Adds two numbers.", "", "Wraps on overflow."]
+    );
+    assert!(ast.stmts()[1].docs.is_empty());
+    assert_eq!(ast.stmts()[2].docs, vec!["the answer"]);
+
+    let docs: Vec<_> = ast
+        .trivia
+        .iter()
+        .map(|trivia| (source_of(input, trivia.span), trivia.doc))
+        .collect();
+    assert_eq!(
+        docs,
+        vec![
+            ("--- Adds two numbers.", true),
+            ("---", true),
+            ("--- Wraps on overflow.", true),
+            ("-- not documentation", false),
+            ("--- the answer", true),
+            ("--- dangling", true),
+        ]
+    );
+
+    assert_flat_matches(input);
+
+    let flat = parse_flat(input);
+    let answer = flat.statements().nth(2).unwrap().expr();
+    assert_eq!(flat.get_docs(answer.root()), ["the answer"]);
+}
+
+#[test]
+fn templates_desugar_to_format_calls() {
+    let input = r#"greeting = "hi {name}, {a + b}!";"#;
+    assert_eq!(
+        lisp(input),
+        r#"(bind greeting (+ (+ (+ (+ "hi " (format name)) ", ") (format (+ a b))) "!"))"#
+    );
+
+    let flat = parse_flat(input);
+    assert_eq!(Ast::from(&flat), parse(input));
+    assert_eq!(flat.to_string(), lisp(input));
+
+    let ast = parse(input);
+    let StmtKind::Binding(_, value) = &ast.stmts()[0].kind else {
+        panic!("expected a binding");
+    };
+    assert_eq!(source_of(input, value.span), r#""hi {name}, {a + b}!""#);
+
+    assert_eq!(lisp(r#"r"raw {x}";"#), r##"r#"raw {x}"#"##);
+}
+
+#[test]
+fn string_errors() {
+    assert_eq!(parse_err(r#"x = "bad \q";"#), "invalid escape sequence");
+    assert_eq!(
+        parse_errs(r"x = '\u{D800}'; y = '\u{110000}';"),
+        vec!["invalid escape sequence", "invalid escape sequence"]
+    );
+    assert_eq!(parse_err(r#"x = "{1 +}";"#), "expected expression");
+    assert_eq!(parse_err(r#"x = "{1 2}";"#), "expected CLOSE_BRACE");
+
+    let input = "x = 1;\ny = \"never closed;\n";
+    let (_, diagnostics) = Parser::new(input).parse();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].render("main.mo", input),
+        "\
+Syntax Error: expected expression [syntax-error]
+ --> main.mo:2:5
+  |
+2 | y = \"never closed;
+  |     ^^^^^^^^^^^^^^ found an unterminated string
+"
+    );
+
+    let input = r#"x = "{1 2}";"#;
+    let (_, boxed) = Parser::new(input).parse();
+    let (_, flat) = FlatParser::new(input).parse();
+    assert_eq!(flat, boxed);
+    assert_eq!(boxed[0].primary.span, Span::new(8, 9));
+}